@@ -0,0 +1,13 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use secretfop::sources::vk::VKGroupFeed;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(s) = std::str::from_utf8(data) {
+        if let Ok(feed) = VKGroupFeed::from_response(s) {
+            // conversion to posts should not panic either
+            feed.as_iter().for_each(drop);
+        }
+    }
+});
@@ -0,0 +1,151 @@
+//! Archive of every mirrored post in a SQLite database, to find when and
+//! where something was posted long after it left the cache.
+
+use std::path::Path;
+
+use rusqlite::{params, Connection};
+use thiserror::Error;
+
+use crate::{
+    config::Snowflake,
+    sources::{Media, Post},
+};
+
+#[derive(Debug, Error)]
+pub enum ArchiveError {
+    #[error("failed to open archive {path}")]
+    Open {
+        path: String,
+        #[source]
+        source: rusqlite::Error,
+    },
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+}
+
+/// Mirrored post found by [`Archive::search`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchivedPost {
+    /// Cache namespace of the source, like `vk`.
+    pub network: String,
+    /// Name of the source shown in captions.
+    pub source: String,
+    /// URL of the original post.
+    pub url: String,
+    pub text: String,
+    /// URLs of the original media.
+    pub media: Vec<String>,
+    /// Unix timestamp of the original post.
+    pub date: u64,
+    /// Unix timestamp of mirroring.
+    pub posted_at: u64,
+    /// Chat the post was mirrored to.
+    pub chat: String,
+    pub message_id: u64,
+}
+
+/// SQLite database of mirrored posts.
+pub struct Archive {
+    conn: Connection,
+}
+
+impl Archive {
+    /// Opens the archive at `path`, creating it if needed.
+    pub fn open(path: &Path) -> Result<Self, ArchiveError> {
+        let conn = Connection::open(path).map_err(|source| ArchiveError::Open {
+            path: path.display().to_string(),
+            source,
+        })?;
+
+        Self::with_connection(conn)
+    }
+
+    /// Archive kept in memory, for tests.
+    pub fn in_memory() -> Result<Self, ArchiveError> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(conn: Connection) -> Result<Self, ArchiveError> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS posts (
+                network TEXT NOT NULL,
+                source TEXT NOT NULL,
+                url TEXT NOT NULL,
+                text TEXT NOT NULL,
+                media TEXT NOT NULL,
+                date INTEGER NOT NULL,
+                posted_at INTEGER NOT NULL,
+                chat TEXT NOT NULL,
+                message_id INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS posts_posted_at ON posts (posted_at);",
+        )?;
+
+        Ok(Self { conn })
+    }
+
+    /// Remembers `post` mirrored to `chat` as `message_id` at `posted_at`.
+    pub fn record(
+        &self,
+        network: &str,
+        post: &Post,
+        chat: &Snowflake,
+        message_id: u64,
+        posted_at: u64,
+    ) -> Result<(), ArchiveError> {
+        let media: Vec<&str> = post.media.iter().map(Media::url).collect();
+        self.conn.execute(
+            "INSERT INTO posts (network, source, url, text, media, date, posted_at, chat, message_id)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                network,
+                post.source,
+                post.url,
+                post.text,
+                media.join("\n"),
+                post.date as i64,
+                posted_at as i64,
+                chat.to_string(),
+                message_id as i64,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Finds up to `limit` posts whose text, source, URL or media URLs
+    /// contain `query`, ignoring ASCII case. Newest first.
+    pub fn search(&self, query: &str, limit: usize) -> Result<Vec<ArchivedPost>, ArchiveError> {
+        let pattern = format!(
+            "%{}%",
+            query
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_")
+        );
+        let mut statement = self.conn.prepare(
+            "SELECT network, source, url, text, media, date, posted_at, chat, message_id
+            FROM posts
+            WHERE text LIKE ?1 ESCAPE '\\' OR source LIKE ?1 ESCAPE '\\'
+                OR url LIKE ?1 ESCAPE '\\' OR media LIKE ?1 ESCAPE '\\'
+            ORDER BY posted_at DESC, rowid DESC
+            LIMIT ?2",
+        )?;
+        let posts = statement.query_map(params![pattern, limit as i64], |row| {
+            let media: String = row.get(4)?;
+            Ok(ArchivedPost {
+                network: row.get(0)?,
+                source: row.get(1)?,
+                url: row.get(2)?,
+                text: row.get(3)?,
+                media: media.lines().map(str::to_owned).collect(),
+                date: row.get::<_, i64>(5)? as u64,
+                posted_at: row.get::<_, i64>(6)? as u64,
+                chat: row.get(7)?,
+                message_id: row.get::<_, i64>(8)? as u64,
+            })
+        })?;
+
+        Ok(posts.collect::<Result<_, _>>()?)
+    }
+}
@@ -0,0 +1,452 @@
+//! OAuth flows behind `secretfop auth`, that obtain user tokens and save them
+//! to config.
+
+use std::{io, path::Path, sync::Arc};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rand::{distributions::Alphanumeric, Rng};
+use reqwest::Url;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::http::{HttpError, Request, ReqwestTransport, Transport};
+
+#[cfg(feature = "vk")]
+/// Redirect page of VK apps without own site, the token is shown in its URL.
+pub const VK_REDIRECT_URI: &str = "https://oauth.vk.com/blank.html";
+
+#[cfg(feature = "vk")]
+/// Scopes of VK user token. `offline` makes it never expire.
+pub const VK_SCOPES: &str = "wall,groups,video,offline";
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuthError {
+    #[error("authorization failed: {error}: {description}")]
+    Denied { error: String, description: String },
+    #[error("redirect URL has neither access_token nor code")]
+    NoToken,
+    #[error("authorization code needs --client-secret of the app")]
+    NoSecret,
+    #[error("state of redirect URL does not match, it is from another request")]
+    StateMismatch,
+    #[error("failed to send request")]
+    Http(#[source] HttpError),
+    #[error("failed to parse token response")]
+    Parse(#[source] serde_json::Error),
+    #[error("failed to update config {path}")]
+    Config {
+        path: String,
+        #[source]
+        source: io::Error,
+    },
+}
+
+#[cfg(feature = "vk")]
+/// User token obtained by OAuth.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct VKToken {
+    pub access_token: String,
+    /// Seconds the token is valid for, 0 if it never expires.
+    #[serde(default)]
+    pub expires_in: u64,
+    pub user_id: Option<u64>,
+}
+
+#[cfg(feature = "vk")]
+/// OAuth of a VK app, that obtains user tokens with [`VK_SCOPES`].
+///
+/// Without client secret uses implicit flow, where the token is returned in
+/// redirect URL. With it, the redirect contains a code, exchanged to token by
+/// [`VKOAuth::token`].
+pub struct VKOAuth {
+    pub client_id: u64,
+    pub client_secret: Option<String>,
+    /// OAuth endpoint, `https://oauth.vk.com` by default.
+    pub base_url: String,
+    transport: Arc<dyn Transport>,
+}
+
+#[cfg(feature = "vk")]
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum VKTokenResponse {
+    Token(VKToken),
+    Error {
+        error: String,
+        #[serde(default)]
+        error_description: String,
+    },
+}
+
+#[cfg(feature = "vk")]
+impl VKOAuth {
+    pub fn new(client_id: u64) -> Self {
+        Self {
+            client_id,
+            client_secret: None,
+            base_url: "https://oauth.vk.com".to_owned(),
+            transport: Arc::new(ReqwestTransport::default()),
+        }
+    }
+
+    /// Sets secret key of the app, to use authorization code flow.
+    pub fn with_client_secret(mut self, client_secret: impl Into<String>) -> Self {
+        self.client_secret = Some(client_secret.into());
+
+        self
+    }
+
+    /// Replaces OAuth endpoint, e.g. with mock server address.
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into();
+
+        self
+    }
+
+    /// Replaces HTTP transport.
+    pub fn with_transport(mut self, transport: Arc<dyn Transport>) -> Self {
+        self.transport = transport;
+
+        self
+    }
+
+    /// URL to open in browser to grant access.
+    pub fn authorize_url(&self) -> String {
+        let response_type = match self.client_secret {
+            Some(_) => "code",
+            None => "token",
+        };
+
+        format!(
+            "{}/authorize?client_id={}&display=page&redirect_uri={VK_REDIRECT_URI}\
+             &scope={VK_SCOPES}&response_type={response_type}&v={}",
+            self.base_url,
+            self.client_id,
+            crate::sources::vk::API_VERSION,
+        )
+    }
+
+    /// Obtains token from URL of the redirect page, or from bare code or
+    /// token copied from it.
+    pub async fn token(&self, redirect: &str) -> Result<VKToken, AuthError> {
+        let params = redirect_params(redirect);
+        let param = |key: &str| {
+            params
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.clone())
+        };
+
+        if let Some(error) = param("error") {
+            return Err(AuthError::Denied {
+                error,
+                description: param("error_description").unwrap_or_default(),
+            });
+        }
+        if let Some(access_token) = param("access_token") {
+            return Ok(VKToken {
+                access_token,
+                expires_in: param("expires_in")
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or_default(),
+                user_id: param("user_id").and_then(|v| v.parse().ok()),
+            });
+        }
+
+        let code = match param("code") {
+            Some(code) => code,
+            // bare value without URL
+            None if !redirect.trim().is_empty() && !redirect.contains(['=', '/']) => {
+                let value = redirect.trim().to_owned();
+                if self.client_secret.is_none() {
+                    return Ok(VKToken {
+                        access_token: value,
+                        expires_in: 0,
+                        user_id: None,
+                    });
+                }
+                value
+            }
+            None => return Err(AuthError::NoToken),
+        };
+
+        self.exchange(&code).await
+    }
+
+    /// Exchanges authorization code to token.
+    async fn exchange(&self, code: &str) -> Result<VKToken, AuthError> {
+        let secret = self.client_secret.as_ref().ok_or(AuthError::NoSecret)?;
+        let request = Request::get(format!("{}/access_token", self.base_url))
+            .query("client_id", self.client_id.to_string())
+            .query("client_secret", secret.as_str())
+            .query("redirect_uri", VK_REDIRECT_URI)
+            .query("code", code);
+        let body = self
+            .transport
+            .send(request)
+            .await
+            .map_err(AuthError::Http)?;
+
+        match serde_json::from_str(&body).map_err(AuthError::Parse)? {
+            VKTokenResponse::Token(token) => Ok(token),
+            VKTokenResponse::Error {
+                error,
+                error_description,
+            } => Err(AuthError::Denied {
+                error,
+                description: error_description,
+            }),
+        }
+    }
+}
+
+/// Scopes of Twitter user token. `offline.access` gives a refresh token.
+pub const TWITTER_SCOPES: &str = "tweet.read users.read offline.access";
+
+/// Redirect URL of Twitter apps by default. Nothing has to listen there, the
+/// code is copied from the address bar.
+pub const TWITTER_REDIRECT_URI: &str = "http://127.0.0.1/callback";
+
+/// PKCE code verifier and its `S256` challenge, see RFC 7636.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pkce {
+    pub verifier: String,
+    pub challenge: String,
+}
+
+/// Access token of Twitter user with the token to refresh it.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct TwitterToken {
+    pub access_token: String,
+    /// Absent without `offline.access` scope.
+    pub refresh_token: Option<String>,
+    /// Seconds the access token is valid for.
+    #[serde(default)]
+    pub expires_in: u64,
+}
+
+/// OAuth2 of a Twitter app with PKCE. The app should be a public client
+/// (native app), that has no client secret.
+pub struct TwitterOAuth {
+    pub client_id: String,
+    pub redirect_uri: String,
+    /// Token endpoint, `https://api.twitter.com/2/oauth2/token` by default.
+    pub token_url: String,
+    pkce: Pkce,
+    state: String,
+    transport: Arc<dyn Transport>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TwitterTokenResponse {
+    Token(TwitterToken),
+    Error {
+        error: String,
+        #[serde(default)]
+        error_description: String,
+    },
+}
+
+impl Pkce {
+    /// Random verifier of 64 characters.
+    pub fn new() -> Self {
+        Self::from_verifier(random_string(64))
+    }
+
+    pub fn from_verifier(verifier: impl Into<String>) -> Self {
+        let verifier = verifier.into();
+        let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()));
+
+        Self {
+            verifier,
+            challenge,
+        }
+    }
+}
+impl Default for Pkce {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TwitterOAuth {
+    pub fn new(client_id: impl Into<String>) -> Self {
+        Self {
+            client_id: client_id.into(),
+            redirect_uri: TWITTER_REDIRECT_URI.to_owned(),
+            token_url: "https://api.twitter.com/2/oauth2/token".to_owned(),
+            pkce: Pkce::new(),
+            state: random_string(16),
+            transport: Arc::new(ReqwestTransport::default()),
+        }
+    }
+
+    /// Replaces redirect URL, it should be registered in the app settings.
+    pub fn with_redirect_uri(mut self, redirect_uri: impl Into<String>) -> Self {
+        self.redirect_uri = redirect_uri.into();
+
+        self
+    }
+
+    /// Replaces token endpoint, e.g. with mock server address.
+    pub fn with_token_url(mut self, token_url: impl Into<String>) -> Self {
+        self.token_url = token_url.into();
+
+        self
+    }
+
+    /// Replaces HTTP transport.
+    pub fn with_transport(mut self, transport: Arc<dyn Transport>) -> Self {
+        self.transport = transport;
+
+        self
+    }
+
+    /// Replaces random PKCE verifier and state.
+    pub fn with_pkce(mut self, pkce: Pkce, state: impl Into<String>) -> Self {
+        self.pkce = pkce;
+        self.state = state.into();
+
+        self
+    }
+
+    /// URL to open in browser to grant access.
+    pub fn authorize_url(&self) -> String {
+        let mut url = Url::parse("https://twitter.com/i/oauth2/authorize").expect("valid URL");
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.client_id)
+            .append_pair("redirect_uri", &self.redirect_uri)
+            .append_pair("scope", TWITTER_SCOPES)
+            .append_pair("state", &self.state)
+            .append_pair("code_challenge", &self.pkce.challenge)
+            .append_pair("code_challenge_method", "S256");
+
+        url.into()
+    }
+
+    /// Exchanges code from redirect URL to tokens.
+    pub async fn token(&self, redirect: &str) -> Result<TwitterToken, AuthError> {
+        let params = redirect_params(redirect);
+        let param = |key: &str| {
+            params
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.clone())
+        };
+
+        if let Some(error) = param("error") {
+            return Err(AuthError::Denied {
+                error,
+                description: param("error_description").unwrap_or_default(),
+            });
+        }
+        if matches!(param("state"), Some(state) if state != self.state) {
+            return Err(AuthError::StateMismatch);
+        }
+        let code = param("code").ok_or(AuthError::NoToken)?;
+
+        self.send(&[
+            ("grant_type", "authorization_code"),
+            ("code", &code),
+            ("redirect_uri", &self.redirect_uri),
+            ("code_verifier", &self.pkce.verifier),
+        ])
+        .await
+    }
+
+    /// Obtains new tokens by refresh token. Twitter refresh tokens are single
+    /// use, so returned refresh token should replace the old one.
+    pub async fn refresh(&self, refresh_token: &str) -> Result<TwitterToken, AuthError> {
+        self.send(&[
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token),
+        ])
+        .await
+    }
+
+    async fn send(&self, form: &[(&str, &str)]) -> Result<TwitterToken, AuthError> {
+        let request = form
+            .iter()
+            .fold(Request::post(&self.token_url), |r, (key, value)| {
+                r.form(key, *value)
+            })
+            .form("client_id", self.client_id.as_str());
+        let body = self
+            .transport
+            .send(request)
+            .await
+            .map_err(AuthError::Http)?;
+
+        match serde_json::from_str(&body).map_err(AuthError::Parse)? {
+            TwitterTokenResponse::Token(token) => Ok(token),
+            TwitterTokenResponse::Error {
+                error,
+                error_description,
+            } => Err(AuthError::Denied {
+                error,
+                description: error_description,
+            }),
+        }
+    }
+}
+
+fn random_string(len: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(Alphanumeric)
+        .take(len)
+        .map(char::from)
+        .collect()
+}
+
+/// Parameters of redirect URL, from fragment or query.
+fn redirect_params(redirect: &str) -> Vec<(String, String)> {
+    let redirect = redirect.trim();
+    let Some(params) = redirect.rsplit(['#', '?']).next() else {
+        return Vec::new();
+    };
+    if !params.contains('=') {
+        return Vec::new();
+    }
+
+    let mut url = Url::parse("http://localhost/").expect("valid URL");
+    url.set_query(Some(params));
+
+    url.query_pairs().into_owned().collect()
+}
+
+/// Sets top-level `keys` of YAML config text, keeping other lines and
+/// comments as is. Missing keys are appended.
+pub fn set_config_keys(text: &str, keys: &[(&str, &str)]) -> String {
+    let mut lines: Vec<String> = text.lines().map(str::to_owned).collect();
+    for (key, value) in keys {
+        // JSON strings are valid YAML scalars
+        let line = format!("{key}: {}", serde_json::Value::from(*value));
+        let prefix = format!("{key}:");
+        match lines.iter_mut().find(|l| l.starts_with(&prefix)) {
+            Some(l) => *l = line,
+            None => lines.push(line),
+        }
+    }
+
+    let mut text = lines.join("\n");
+    text.push('\n');
+    text
+}
+
+/// Sets top-level `keys` of config file, see [`set_config_keys`]. Missing
+/// file is created.
+pub fn update_config(path: &Path, keys: &[(&str, &str)]) -> Result<(), AuthError> {
+    let err = |source| AuthError::Config {
+        path: path.display().to_string(),
+        source,
+    };
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(err(e)),
+    };
+
+    std::fs::write(path, set_config_keys(&text, keys)).map_err(err)
+}
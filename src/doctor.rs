@@ -0,0 +1,164 @@
+//! Diagnosis of the environment for `secretfop doctor`: whether the config
+//! and the cache can be used, and whether the bot reaches its APIs and can
+//! post to its channels.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    path::Path,
+    sync::Arc,
+};
+
+#[cfg(feature = "vk")]
+use crate::sources::vk::VKClient;
+use crate::{
+    config::{CacheLocation, CacheRecords, Config, Snowflake},
+    error::{Error, Report},
+    http::Transport,
+    sources::Registry,
+    telegram::TelegramClient,
+};
+
+/// Result of a single check of [`diagnose`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    /// What was checked, like `config` or `channel @foxart`.
+    pub name: String,
+    pub passed: bool,
+    /// What was found, or why the check failed.
+    pub details: String,
+}
+
+impl Check {
+    fn new(name: impl Into<String>, result: Result<String, String>) -> Self {
+        let (passed, details) = match result {
+            Ok(details) => (true, details),
+            Err(details) => (false, details),
+        };
+
+        Self {
+            name: name.into(),
+            passed,
+            details,
+        }
+    }
+}
+
+/// Checks the config at `config`, the cache at `cache`, the Telegram token,
+/// rights of the bot in every channel it posts to and reachability of VK, if
+/// there are VK sources. Requests are sent through `transport`, or the
+/// transport of the config if [`None`]. Checks that need the config or the
+/// bot are left out if those fail.
+pub async fn diagnose(
+    config: &Path,
+    cache: &CacheLocation,
+    transport: Option<Arc<dyn Transport>>,
+) -> Vec<Check> {
+    let loaded = Config::load(config);
+    let mut checks = vec![Check::new(
+        "config",
+        match &loaded {
+            Ok(cfg) if cfg.warnings.is_empty() => Ok(format!("{} is valid", config.display())),
+            Ok(cfg) => Ok(format!(
+                "{} is valid, but {}",
+                config.display(),
+                cfg.warnings.join("; ")
+            )),
+            Err(e) => Err(Report(e).to_string()),
+        },
+    )];
+    checks.push(Check::new("cache", check_cache(cache)));
+    let Ok(cfg) = loaded else {
+        return checks;
+    };
+
+    let transport = transport.unwrap_or_else(|| Arc::new(cfg.transport()));
+    let (sources, errors) = Registry::default().build_skipping(&cfg, transport.clone());
+    checks.push(Check::new(
+        "sources",
+        match &errors[..] {
+            [] => Ok(format!("{} configured", sources.len())),
+            errors => Err(errors
+                .iter()
+                .map(|e| Report(e).to_string())
+                .collect::<Vec<_>>()
+                .join("; ")),
+        },
+    ));
+
+    #[cfg(feature = "vk")]
+    if sources.iter().any(|s| s.network() == "vk") {
+        let vk = VKClient::new(cfg.vk_token.clone())
+            .with_token_kind(cfg.vk_token_kind)
+            .with_transport(transport.clone());
+        let res = match vk.server_time().await {
+            Ok(_) => Ok("API is reachable".to_owned()),
+            Err(e) => Err(Report(&e).to_string()),
+        };
+        checks.push(Check::new("vk", res));
+    }
+
+    let telegram = TelegramClient::new(cfg.telegram.token.clone(), cfg.telegram.channel.clone())
+        .with_transport(transport);
+    let me = match telegram.get_me().await {
+        Ok(me) => me,
+        Err(e) => {
+            checks.push(Check::new("telegram", Err(Report(&e).to_string())));
+            return checks;
+        }
+    };
+    let name = match &me.username {
+        Some(username) => format!("@{username}"),
+        None => me.id.to_string(),
+    };
+    checks.push(Check::new("telegram", Ok(format!("token of {name}"))));
+
+    let mut chats: Vec<&Snowflake> = vec![&cfg.telegram.channel];
+    for account in sources.iter().map(|s| s.account()) {
+        let copies = account.copy_to.iter().map(|d| &d.chat);
+        for chat in account.channel.iter().chain(copies) {
+            if !chats.contains(&chat) {
+                chats.push(chat);
+            }
+        }
+    }
+    for chat in chats {
+        let res = match telegram.get_chat_member(chat, me.id).await {
+            Ok(member) if member.can_post() => Ok(format!("{name} can post")),
+            Ok(member) => Err(Error::CantPost {
+                chat: chat.to_string(),
+                status: member.status,
+            }
+            .to_string()),
+            Err(e) => Err(Report(&e).to_string()),
+        };
+        checks.push(Check::new(format!("channel {chat}"), res));
+    }
+
+    checks
+}
+
+/// Checks that the cache at `location` can be read and written, without
+/// changing it.
+fn check_cache(location: &CacheLocation) -> Result<String, String> {
+    let path = location.path();
+    // not loaded, as loading creates a missing database
+    if !path.exists() {
+        File::create(path)
+            .and_then(|_| fs::remove_file(path))
+            .map_err(|e| format!("can't write {location}: {e}"))?;
+
+        return Ok(format!("{location} will be created"));
+    }
+
+    let cache = CacheRecords::load_from(location).map_err(|e| Report(&e).to_string())?;
+    // opening to append writes nothing
+    OpenOptions::new()
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("can't write {location}: {e}"))?;
+
+    Ok(format!(
+        "{location} has {} recently mirrored posts",
+        cache.posted.len()
+    ))
+}
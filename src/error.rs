@@ -0,0 +1,262 @@
+//! Crate-wide error type.
+
+use std::{fmt::Display, io, path::PathBuf};
+
+use thiserror::Error;
+
+#[cfg(feature = "archive")]
+use crate::archive::ArchiveError;
+#[cfg(feature = "exec")]
+use crate::exec::ExecError;
+use crate::http::HttpError;
+use crate::media::InvalidMedia;
+#[cfg(feature = "vk")]
+use crate::sources::vk::VKError;
+use crate::telegram::TelegramError;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("failed to open config {}", path.display())]
+    OpenConfig {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("failed to parse config {}", path.display())]
+    ParseConfig {
+        path: PathBuf,
+        #[source]
+        source: serde_yaml::Error,
+    },
+    #[error("failed to open cache file {}", path.display())]
+    OpenCache {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("failed to parse cache file {}", path.display())]
+    ParseCache {
+        path: PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
+    #[error("failed to write cache file {}", path.display())]
+    WriteCache {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[cfg(feature = "sqlite")]
+    #[error("failed to access cache database {}", path.display())]
+    CacheDatabase {
+        path: PathBuf,
+        #[source]
+        source: rusqlite::Error,
+    },
+    #[error("failed to open event log {}", path.display())]
+    OpenEventLog {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("failed to write event log")]
+    EventLog(#[source] io::Error),
+    #[error("skipped media {url} of {post}")]
+    InvalidMedia {
+        post: String,
+        url: String,
+        #[source]
+        source: InvalidMedia,
+    },
+    #[error("failed to push metrics to {url}")]
+    PushMetrics {
+        url: String,
+        #[source]
+        source: HttpError,
+    },
+    #[error("failed to serialize cache")]
+    SerializeCache(#[source] serde_json::Error),
+    #[error("config {} has unknown keys: {}", path.display(), keys.join(", "))]
+    UnknownKeys { path: PathBuf, keys: Vec<String> },
+    #[error("config {} uses undefined pipeline {name:?}", path.display())]
+    UnknownPipeline { path: PathBuf, name: String },
+    #[error("config {} uses {feature} but it is disabled at compile time", path.display())]
+    Disabled {
+        path: PathBuf,
+        feature: &'static str,
+    },
+    #[error("unknown source type {0:?}")]
+    UnknownSource(String),
+    #[error("no configured source {0}")]
+    NoSuchSource(String),
+    #[error("source {0} can't fetch older posts")]
+    NoHistory(String),
+    #[error("post {post} not found among latest posts of {account}")]
+    NoSuchPost { account: String, post: String },
+    #[error("invalid {kind} source")]
+    SourceConfig {
+        kind: String,
+        #[source]
+        source: serde_yaml::Error,
+    },
+    #[cfg(feature = "vk")]
+    #[error("failed to resolve vk account {account}")]
+    Resolve {
+        account: String,
+        #[source]
+        source: VKError,
+    },
+    #[cfg(feature = "vk")]
+    #[error("failed to fetch posts of vk account {account}")]
+    Fetch {
+        account: String,
+        #[source]
+        source: VKError,
+    },
+    #[cfg(feature = "vk")]
+    #[error("failed to fetch communities of vk user {user}")]
+    Discover {
+        user: u64,
+        #[source]
+        source: VKError,
+    },
+    #[cfg(feature = "vk")]
+    #[error("failed to look up {post}")]
+    Exists {
+        post: String,
+        #[source]
+        source: VKError,
+    },
+    #[cfg(feature = "vk")]
+    #[error("failed to fetch comments of {post}")]
+    FetchComments {
+        post: String,
+        #[source]
+        source: VKError,
+    },
+    #[cfg(feature = "exec")]
+    #[error("failed to fetch posts of exec source {account}")]
+    ExecSource {
+        account: String,
+        #[source]
+        source: ExecError,
+    },
+    #[cfg(feature = "exec")]
+    #[error("failed to pass {post} to exec sink")]
+    ExecSink {
+        post: String,
+        #[source]
+        source: ExecError,
+    },
+    #[cfg(feature = "archive")]
+    #[error("failed to archive {post}")]
+    Archive {
+        post: String,
+        #[source]
+        source: ArchiveError,
+    },
+    #[cfg(feature = "archive")]
+    #[error(transparent)]
+    OpenArchive(ArchiveError),
+    #[error("failed to post {post} to telegram")]
+    Post {
+        post: String,
+        #[source]
+        source: TelegramError,
+    },
+    #[error("bot can't post to {chat} as {status}, it should be an administrator allowed to post messages")]
+    CantPost { chat: String, status: String },
+    #[error("failed to check rights of the bot in {chat}")]
+    CheckRights {
+        chat: String,
+        #[source]
+        source: TelegramError,
+    },
+    #[error("{count} media of {post} rejected by telegram, posted without them")]
+    DroppedMedia { post: String, count: usize },
+    #[error("{count} media of {post} rejected by telegram, posted as links")]
+    MediaAsLinks { post: String, count: usize },
+    #[error("failed to copy {post} to {chat}")]
+    Copy {
+        post: String,
+        chat: String,
+        #[source]
+        source: TelegramError,
+    },
+    #[error("failed to update caption of {post}")]
+    EditCaption {
+        post: String,
+        #[source]
+        source: TelegramError,
+    },
+    #[error("failed to pin {post}")]
+    Pin {
+        post: String,
+        #[source]
+        source: TelegramError,
+    },
+    #[error("failed to post comments of {post} to telegram")]
+    PostComments {
+        post: String,
+        #[source]
+        source: TelegramError,
+    },
+    #[error("{account} now refers to {new} instead of {old}")]
+    IdChanged { account: String, old: u64, new: u64 },
+    #[error("watermark of {account} moved back from deleted post {old} to {new}")]
+    WatermarkRepaired { account: String, old: u64, new: u64 },
+    #[error("{account} has no new posts for {days} days")]
+    StaleSource { account: String, days: u64 },
+    #[error("{chat} failed {failures} times in a row, nothing else is sent there this run")]
+    Breaker { chat: String, failures: usize },
+    #[error("failed to send alert to admin chat")]
+    Alert(#[source] TelegramError),
+    #[error("failed to post digest")]
+    Digest(#[source] TelegramError),
+    #[error("telegram rate limit lasts {seconds} more seconds, nothing is posted")]
+    FloodWait { seconds: u64 },
+    #[error("failed to fetch telegram updates")]
+    Updates(#[source] TelegramError),
+    #[error(
+        "run used {used} bytes of max_bandwidth_per_run {limit}, the rest is posted on next runs"
+    )]
+    BandwidthExceeded { used: u64, limit: u64 },
+}
+
+impl Error {
+    /// Whether a source can't be read at all, like a banned VK group, so
+    /// retrying it soon is pointless.
+    pub fn is_inaccessible(&self) -> bool {
+        match self {
+            #[cfg(feature = "vk")]
+            Self::Resolve {
+                source: VKError::Inaccessible { .. },
+                ..
+            }
+            | Self::Fetch {
+                source: VKError::Inaccessible { .. },
+                ..
+            } => true,
+            _ => false,
+        }
+    }
+}
+
+/// Displays error with all its sources, like `failed to post: chat not found`.
+pub struct Report<'a>(pub &'a (dyn std::error::Error + 'static));
+
+impl<'a> Display for Report<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)?;
+
+        let mut source = self.0.source();
+        while let Some(e) = source {
+            write!(f, ": {e}")?;
+            source = e.source();
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,89 @@
+//! Append-only log of pipeline decisions, one JSON object per line, to audit
+//! runs independently of stderr.
+//!
+//! Every line has the start of the run (`run`), the time of the event (`at`),
+//! both unix timestamps, and the kind of `event` with its fields:
+//!
+//! ```json
+//! {"run":1677600000,"at":1677600002,"event":"fetch","source":"vk/apiclub","posts":5}
+//! {"run":1677600000,"at":1677600002,"event":"skip","post":"https://vk.com/wall-1_2","reason":"too_old"}
+//! {"run":1677600000,"at":1677600003,"event":"post","post":"https://vk.com/wall-1_3","chat":"@foxart","message_ids":[42]}
+//! {"run":1677600000,"at":1677600004,"event":"error","message":"failed to post ..."}
+//! {"run":1677600000,"at":1677600004,"event":"traffic","source":"vk/apiclub","downloaded":8200,"uploaded":310}
+//! {"run":1677600000,"at":1677600004,"event":"traffic","downloaded":20500,"uploaded":900}
+//! ```
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+};
+
+use serde::Serialize;
+
+use crate::{hooks::SkipReason, unix_now};
+
+/// Entry of [`EventLog`].
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// Posts were fetched from a source, like `vk/apiclub`.
+    Fetch { source: String, posts: usize },
+    /// New post was rejected by a filter.
+    Skip { post: String, reason: SkipReason },
+    /// Post was mirrored to a chat as these messages.
+    Post {
+        post: String,
+        chat: String,
+        message_ids: Vec<u64>,
+    },
+    /// Run reported an error, with all its sources.
+    Error { message: String },
+    /// Bytes sent and received for a source during the run, or for the
+    /// whole run without `source`.
+    Traffic {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        source: Option<String>,
+        downloaded: u64,
+        uploaded: u64,
+    },
+}
+
+/// JSONL file that events of runs are appended to.
+pub struct EventLog {
+    file: File,
+    run: u64,
+}
+
+#[derive(Serialize)]
+struct Line<'a> {
+    run: u64,
+    at: u64,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+impl EventLog {
+    /// Opens the log at `path` for a new run, creating it if needed.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        Ok(Self {
+            file,
+            run: unix_now(),
+        })
+    }
+
+    /// Appends `event` as a line.
+    pub fn record(&mut self, event: &Event) -> io::Result<()> {
+        let line = Line {
+            run: self.run,
+            at: unix_now(),
+            event,
+        };
+        let mut json = serde_json::to_string(&line)?;
+        json.push('\n');
+
+        self.file.write_all(json.as_bytes())
+    }
+}
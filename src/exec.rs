@@ -0,0 +1,165 @@
+//! Plugin mechanism: external commands as sources and sinks.
+//!
+//! Source command prints posts to stdout, one JSON object per line:
+//!
+//! ```json
+//! {"id": 1, "date": 1677600000, "text": "hi", "media": [{"type": "photo", "url": "https://..."}], "url": "https://..."}
+//! ```
+//!
+//! Sink command receives a single post in the same format on stdin. Both
+//! should exit with zero status on success.
+
+use std::{io, process::Stdio};
+
+use futures::future::BoxFuture;
+use serde::Deserialize;
+use thiserror::Error;
+use tokio::{io::AsyncWriteExt, process::Command};
+
+use crate::{
+    config::{ExecSink, ExecSource, Snowflake, SocialAccount},
+    error::{self, Error as CrateError},
+    sources::{Context, Media, Post, Source},
+};
+
+#[derive(Debug, Error)]
+pub enum ExecError {
+    #[error("command is empty")]
+    EmptyCommand,
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("command exited with {status}: {stderr}")]
+    Status {
+        status: std::process::ExitStatus,
+        stderr: String,
+    },
+    #[error("invalid post on line {line}")]
+    Scheme {
+        line: usize,
+        #[source]
+        source: serde_json::Error,
+    },
+    #[error(transparent)]
+    Serialize(serde_json::Error),
+}
+
+/// Post as printed by source command.
+#[derive(Deserialize)]
+struct ExecPost {
+    id: Snowflake,
+    #[serde(default)]
+    date: u64,
+    #[serde(default)]
+    text: String,
+    #[serde(default)]
+    media: Vec<Media>,
+    #[serde(default)]
+    source: Option<String>,
+    url: String,
+}
+
+fn command(argv: &[String]) -> Result<Command, ExecError> {
+    let (program, args) = argv.split_first().ok_or(ExecError::EmptyCommand)?;
+    let mut command = Command::new(program);
+    command.args(args).kill_on_drop(true);
+
+    Ok(command)
+}
+
+impl ExecSource {
+    /// [`crate::sources::Constructor`] of `exec` source type.
+    pub fn construct(
+        _: &Context,
+        params: serde_yaml::Value,
+    ) -> Result<Box<dyn Source>, serde_yaml::Error> {
+        Ok(Box::new(serde_yaml::from_value::<Self>(params)?))
+    }
+
+    /// Runs the command and parses posts from its output.
+    async fn run(&self) -> Result<Vec<Post>, ExecError> {
+        let output = command(&self.command)?
+            .stdin(Stdio::null())
+            .output()
+            .await?;
+        if !output.status.success() {
+            return Err(ExecError::Status {
+                status: output.status,
+                stderr: String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+            });
+        }
+
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| {
+                let post: ExecPost =
+                    serde_json::from_str(line).map_err(|source| ExecError::Scheme {
+                        line: i + 1,
+                        source,
+                    })?;
+
+                Ok(Post {
+                    id: post.id,
+                    source_id: self.account.id.clone(),
+                    date: post.date,
+                    text: post.text,
+                    media: post.media,
+                    extra: Vec::new(),
+                    stats: None,
+                    source: post
+                        .source
+                        .unwrap_or_else(|| format!("exec // {}", self.account.id)),
+                    url: post.url,
+                    messages: Vec::new(),
+                })
+            })
+            .collect()
+    }
+}
+
+impl Source for ExecSource {
+    fn account(&self) -> &SocialAccount {
+        &self.account
+    }
+
+    fn network(&self) -> &'static str {
+        "exec"
+    }
+
+    fn fetch(&self) -> BoxFuture<'_, error::Result<Vec<Post>>> {
+        Box::pin(async move {
+            self.run().await.map_err(|source| CrateError::ExecSource {
+                account: self.account.id.to_string(),
+                source,
+            })
+        })
+    }
+}
+
+impl ExecSink {
+    /// Runs the command with post on its stdin.
+    pub async fn send(&self, post: &Post) -> Result<(), ExecError> {
+        let data = serde_json::to_vec(post).map_err(ExecError::Serialize)?;
+        let mut child = command(&self.command)?
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(&data).await?;
+            stdin.write_all(b"\n").await?;
+        }
+
+        let output = child.wait_with_output().await?;
+        if !output.status.success() {
+            return Err(ExecError::Status {
+                status: output.status,
+                stderr: String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+            });
+        }
+
+        Ok(())
+    }
+}
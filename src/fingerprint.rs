@@ -0,0 +1,74 @@
+//! Content fingerprints of posts, used to find the same content posted by
+//! different sources or posted again.
+//!
+//! Hashes are FNV-1a, so fingerprints are stable across builds and can be
+//! stored or compared by external tools.
+
+use serde::{Deserialize, Serialize};
+
+use crate::sources::{Media, Post};
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// Fingerprint of post content. Equal fingerprints mean same content.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Fingerprint {
+    /// Hash of the text with case and whitespace normalized.
+    pub text: u64,
+    /// Hashes of media URLs without query string, in post order.
+    pub media: Vec<u64>,
+}
+
+/// Computes fingerprint of post.
+pub fn fingerprint(post: &Post) -> Fingerprint {
+    Fingerprint {
+        text: text_hash(&post.text),
+        media: post.media.iter().map(media_hash).collect(),
+    }
+}
+
+/// Hash of a text with case and whitespace normalized.
+pub fn text_hash(text: &str) -> u64 {
+    let text = text
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase();
+
+    fnv1a(text.as_bytes())
+}
+
+/// Hash of a media URL. Query string is ignored, as it usually contains
+/// signatures and size hints that differ between fetches.
+pub fn media_hash(media: &Media) -> u64 {
+    url_hash(media.url())
+}
+
+/// Hash of a media URL with the kind the media is sent as, as Telegram file
+/// ids of one kind can't be sent as another, like a photo uploaded as a
+/// document. Photos keep their [`media_hash`].
+pub fn file_hash(media: &Media) -> u64 {
+    let kind = match media {
+        Media::Photo(_) => 0,
+        // animations are sent as videos
+        Media::Video(_) | Media::Animation(_) => 1,
+        Media::Audio { .. } => 2,
+        Media::Document { .. } => 3,
+    };
+
+    media_hash(media).wrapping_add(kind)
+}
+
+/// Hash of a URL without query string and fragment.
+pub fn url_hash(url: &str) -> u64 {
+    let url = url.split(['?', '#']).next().unwrap_or_default();
+
+    fnv1a(url.as_bytes())
+}
+
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(FNV_OFFSET, |h, &b| {
+        (h ^ u64::from(b)).wrapping_mul(FNV_PRIME)
+    })
+}
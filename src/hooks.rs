@@ -0,0 +1,383 @@
+//! Extension points of the pipeline: [`Transform`]s change fetched posts and
+//! [`Filter`]s decide whether they should be mirrored.
+//!
+//! ```
+//! use secretfop::{config::SocialAccount, hooks::Chain, sources::Post};
+//!
+//! let chain = Chain::default()
+//!     .transform(|_: &SocialAccount, post: &mut Post| post.text.push_str("\n\n#art"))
+//!     .filter(|_: &SocialAccount, post: &Post| !post.text.contains("giveaway"));
+//! ```
+
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fmt::{self, Display},
+    sync::Arc,
+};
+
+use serde::Serialize;
+
+use crate::{
+    config::{CaptionMode, SocialAccount, Step},
+    sources::ContentKind,
+    sources::Post,
+    text, unix_now,
+};
+
+/// Changes a post before it is filtered and posted.
+pub trait Transform: Send + Sync {
+    fn apply(&self, account: &SocialAccount, post: &mut Post);
+}
+
+/// Accepts or rejects a post. Rejected posts are not mirrored.
+pub trait Filter: Send + Sync {
+    fn accepts(&self, account: &SocialAccount, post: &Post) -> bool;
+
+    /// Why `post` is rejected. Only called for rejected posts.
+    fn reason(&self, _account: &SocialAccount, _post: &Post) -> SkipReason {
+        SkipReason::Filtered
+    }
+}
+
+/// Why a fetched post is not mirrored.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    /// No media, or none left after transforms.
+    NoMedia,
+    /// Has attachments of kinds not in `content`.
+    Content,
+    /// Rejected by `only_if` or by `require` and `exclude` steps.
+    FilteredKeyword,
+    /// Older than `max_age`.
+    TooOld,
+    /// Younger than `min_age`, mirrored later.
+    TooNew,
+    /// Not written in one of `languages`.
+    Language,
+    /// Rejected by a custom [`Filter`].
+    Filtered,
+}
+
+impl Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::NoMedia => "no_media",
+            Self::Content => "content",
+            Self::FilteredKeyword => "filtered_keyword",
+            Self::TooOld => "too_old",
+            Self::TooNew => "too_new",
+            Self::Language => "language",
+            Self::Filtered => "filtered",
+        })
+    }
+}
+
+impl<F: Fn(&SocialAccount, &mut Post) + Send + Sync> Transform for F {
+    fn apply(&self, account: &SocialAccount, post: &mut Post) {
+        self(account, post)
+    }
+}
+
+impl<F: Fn(&SocialAccount, &Post) -> bool + Send + Sync> Filter for F {
+    fn accepts(&self, account: &SocialAccount, post: &Post) -> bool {
+        self(account, post)
+    }
+}
+
+/// Transforms followed by filters. Transforms run in order they were added,
+/// then post should be accepted by every filter.
+pub struct Chain {
+    transforms: Vec<Box<dyn Transform>>,
+    filters: Vec<Box<dyn Filter>>,
+}
+
+impl Chain {
+    /// Chain without any hooks, even built-in ones.
+    pub fn empty() -> Self {
+        Self {
+            transforms: Vec::new(),
+            filters: Vec::new(),
+        }
+    }
+
+    /// Appends transform.
+    pub fn transform(mut self, transform: impl Transform + 'static) -> Self {
+        self.transforms.push(Box::new(transform));
+
+        self
+    }
+
+    /// Appends filter.
+    pub fn filter(mut self, filter: impl Filter + 'static) -> Self {
+        self.filters.push(Box::new(filter));
+
+        self
+    }
+
+    /// Appends named `pipelines` of the config, see [`Pipelines`].
+    pub fn pipelines(self, pipelines: &HashMap<String, Vec<Step>>) -> Self {
+        let hook = Pipelines(Arc::new(pipelines.clone()));
+
+        self.transform(hook.clone()).filter(hook)
+    }
+
+    /// Appends `media_blocklist` of the config, see [`MediaBlocklist`].
+    pub fn media_blocklist(self, patterns: &[String]) -> Self {
+        if patterns.is_empty() {
+            return self;
+        }
+
+        self.transform(MediaBlocklist(Arc::new(patterns.to_vec())))
+    }
+
+    /// Transforms the post and returns it if all filters accept it.
+    pub fn process(&self, account: &SocialAccount, mut post: Post) -> Option<Post> {
+        self.check(account, &mut post).is_none().then_some(post)
+    }
+
+    /// Transforms the post in place and returns why the first filter that
+    /// rejects it does so.
+    pub fn check(&self, account: &SocialAccount, post: &mut Post) -> Option<SkipReason> {
+        for transform in &self.transforms {
+            transform.apply(account, post);
+        }
+
+        self.filters
+            .iter()
+            .find(|f| !f.accepts(account, post))
+            .map(|f| f.reason(account, post))
+    }
+}
+
+impl Default for Chain {
+    /// Built-in hooks driven by account settings.
+    fn default() -> Self {
+        Self::empty()
+            .transform(NormalizeText)
+            .transform(StripText)
+            .transform(CleanupText)
+            .transform(ContentKinds)
+            .filter(ContentKinds)
+            .filter(Languages)
+            .filter(Age)
+            .filter(Allowlist)
+    }
+}
+
+/// Applies `normalize` of the account.
+pub struct NormalizeText;
+
+impl Transform for NormalizeText {
+    fn apply(&self, account: &SocialAccount, post: &mut Post) {
+        if !account.normalize {
+            return;
+        }
+        if let Cow::Owned(s) = text::normalize(Cow::Borrowed(&post.text)) {
+            post.text = s;
+        }
+    }
+}
+
+/// Applies `strip` of the account.
+pub struct StripText;
+
+impl Transform for StripText {
+    fn apply(&self, account: &SocialAccount, post: &mut Post) {
+        if let Cow::Owned(s) = text::strip(Cow::Borrowed(&post.text), &account.strip) {
+            post.text = s;
+        }
+    }
+}
+
+/// Applies `cleanup` of the account.
+pub struct CleanupText;
+
+impl Transform for CleanupText {
+    fn apply(&self, account: &SocialAccount, post: &mut Post) {
+        if let Cow::Owned(s) = text::cleanup(Cow::Borrowed(&post.text), &account.cleanup) {
+            post.text = s;
+        }
+    }
+}
+
+/// Applies `content` of the account: drops media and text of other kinds,
+/// rejects posts with attachments of other kinds and ones without media,
+/// unless they have text and the account has `text_posts`.
+pub struct ContentKinds;
+
+impl Transform for ContentKinds {
+    fn apply(&self, account: &SocialAccount, post: &mut Post) {
+        post.media.retain(|m| account.allows(m.kind()));
+        if !account.allows(ContentKind::Text) {
+            post.text.clear();
+        }
+    }
+}
+
+impl Filter for ContentKinds {
+    fn accepts(&self, account: &SocialAccount, post: &Post) -> bool {
+        let has_content =
+            !post.media.is_empty() || account.text_posts && !post.text.trim().is_empty();
+
+        has_content && post.extra.iter().all(|&k| account.allows(k))
+    }
+
+    fn reason(&self, account: &SocialAccount, post: &Post) -> SkipReason {
+        if post.media.is_empty() && (!account.text_posts || post.text.trim().is_empty()) {
+            SkipReason::NoMedia
+        } else {
+            SkipReason::Content
+        }
+    }
+}
+
+/// Applies `languages` of the account.
+pub struct Languages;
+
+impl Filter for Languages {
+    fn accepts(&self, account: &SocialAccount, post: &Post) -> bool {
+        account.accepts_language(&post.text)
+    }
+
+    fn reason(&self, _: &SocialAccount, _: &Post) -> SkipReason {
+        SkipReason::Language
+    }
+}
+
+/// Applies `min_age` and `max_age` of the account.
+pub struct Age;
+
+impl Filter for Age {
+    fn accepts(&self, account: &SocialAccount, post: &Post) -> bool {
+        account.fits_age(post.date, unix_now())
+    }
+
+    fn reason(&self, account: &SocialAccount, post: &Post) -> SkipReason {
+        match account.max_age {
+            Some(max) if unix_now().saturating_sub(post.date) > max => SkipReason::TooOld,
+            _ => SkipReason::TooNew,
+        }
+    }
+}
+
+/// Applies `only_if` of the account.
+pub struct Allowlist;
+
+impl Filter for Allowlist {
+    fn accepts(&self, account: &SocialAccount, post: &Post) -> bool {
+        account
+            .only_if
+            .as_ref()
+            .map(|rules| rules.matches(&post.text))
+            .unwrap_or(true)
+    }
+
+    fn reason(&self, _: &SocialAccount, _: &Post) -> SkipReason {
+        SkipReason::FilteredKeyword
+    }
+}
+
+/// Drops media from blocked hosts. A pattern without `/` is a host that is
+/// blocked with its subdomains, like `reupload.example`, otherwise it is a
+/// prefix of URLs without scheme, like `cdn.example/watermarked/`. Posts left
+/// without media are rejected by [`ContentKinds`].
+#[derive(Clone)]
+pub struct MediaBlocklist(pub Arc<Vec<String>>);
+
+impl MediaBlocklist {
+    /// Whether media at `url` is blocked.
+    pub fn blocks(&self, url: &str) -> bool {
+        let url = url.split_once("://").map(|(_, rest)| rest).unwrap_or(url);
+        let host = url.split(['/', '?', '#']).next().unwrap_or_default();
+        let host = host.rsplit('@').next().unwrap_or_default();
+        let host = host.split(':').next().unwrap_or_default().to_lowercase();
+
+        self.0.iter().any(|pattern| {
+            if pattern.contains('/') {
+                return url.to_lowercase().starts_with(&pattern.to_lowercase());
+            }
+            let pattern = pattern.trim_start_matches("*.").to_lowercase();
+            host == pattern
+                || matches!(host.strip_suffix(&pattern), Some(sub) if sub.ends_with('.'))
+        })
+    }
+}
+
+impl Transform for MediaBlocklist {
+    fn apply(&self, _: &SocialAccount, post: &mut Post) {
+        post.media.retain(|m| !self.blocks(m.url()));
+    }
+}
+
+/// Applies named pipelines listed in `pipelines` of the account. Filter
+/// steps see the post after transform steps of all its pipelines.
+#[derive(Clone)]
+pub struct Pipelines(pub Arc<HashMap<String, Vec<Step>>>);
+
+impl Pipelines {
+    fn steps<'a>(&'a self, account: &'a SocialAccount) -> impl Iterator<Item = &'a Step> {
+        account
+            .pipelines
+            .iter()
+            .filter_map(|name| self.0.get(name))
+            .flatten()
+    }
+}
+
+impl Transform for Pipelines {
+    fn apply(&self, account: &SocialAccount, post: &mut Post) {
+        for step in self.steps(account) {
+            match step {
+                Step::Strip(strip) => {
+                    let stripped = text::strip(Cow::Borrowed(&post.text), &[*strip]);
+                    if let Cow::Owned(s) = stripped {
+                        post.text = s;
+                    }
+                }
+                Step::Cleanup(pass) => {
+                    let cleaned = text::cleanup(Cow::Borrowed(&post.text), &[*pass]);
+                    if let Cow::Owned(s) = cleaned {
+                        post.text = s;
+                    }
+                }
+                Step::MaxText(length) => {
+                    if let Some(s) = text::summarize(&post.text, CaptionMode::Truncate, *length) {
+                        post.text = format!("{s}…");
+                    }
+                }
+                Step::Hashtag(tag) => {
+                    let tag = if tag.starts_with('#') {
+                        Cow::Borrowed(tag.as_str())
+                    } else {
+                        Cow::Owned(format!("#{tag}"))
+                    };
+                    if !post.text.contains(tag.as_ref()) {
+                        if !post.text.is_empty() {
+                            post.text.push_str("\n\n");
+                        }
+                        post.text.push_str(&tag);
+                    }
+                }
+                Step::Require(_) | Step::Exclude(_) => {}
+            }
+        }
+    }
+}
+
+impl Filter for Pipelines {
+    fn accepts(&self, account: &SocialAccount, post: &Post) -> bool {
+        let text = post.text.to_lowercase();
+
+        self.steps(account).all(|step| match step {
+            Step::Require(s) => text.contains(&s.to_lowercase()),
+            Step::Exclude(s) => !text.contains(&s.to_lowercase()),
+            _ => true,
+        })
+    }
+
+    fn reason(&self, _: &SocialAccount, _: &Post) -> SkipReason {
+        SkipReason::FilteredKeyword
+    }
+}
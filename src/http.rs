@@ -0,0 +1,720 @@
+//! HTTP layer shared by API clients. Clients talk to the network only through
+//! a [`Transport`], so it can be replaced with a mock in tests or with
+//! recorded responses, see [`RecordTransport`] and [`ReplayTransport`].
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    future::Future,
+    io,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use futures::future::BoxFuture;
+use rand::Rng;
+use reqwest::{
+    multipart::{Form, Part},
+    Client, Proxy,
+};
+use tokio::sync::Semaphore;
+
+use crate::fingerprint::fnv1a;
+
+/// Error returned by a [`Transport`].
+pub type HttpError = Box<dyn Error + Send + Sync>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    Get,
+    Post,
+    Put,
+}
+
+/// API request. Parameters are sent as query string, form fields as
+/// urlencoded body and files as multipart form. Without both, `body` is sent
+/// as it is.
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: Method,
+    pub url: String,
+    pub query: Vec<(String, String)>,
+    pub form: Vec<(String, String)>,
+    pub bearer: Option<String>,
+    pub files: Vec<RequestFile>,
+    pub body: Option<String>,
+}
+
+/// File of a [`Request`], sent as form field with the same file name, unless
+/// `name` is set.
+#[derive(Clone)]
+pub struct RequestFile {
+    pub field: String,
+    pub bytes: Vec<u8>,
+    pub name: Option<String>,
+    /// MIME type, guessed by the server if not set.
+    pub mime: Option<String>,
+}
+
+/// Sends requests and returns bodies of responses.
+pub trait Transport: Send + Sync {
+    fn send(&self, request: Request) -> BoxFuture<'_, Result<String, HttpError>>;
+
+    /// Sends request and returns binary body, like media file. By default
+    /// uses [`Transport::send`], so text-only transports can serve files too.
+    fn download(&self, request: Request) -> BoxFuture<'_, Result<Vec<u8>, HttpError>> {
+        Box::pin(async move { Ok(self.send(request).await?.into_bytes()) })
+    }
+}
+
+/// `User-Agent` sent by default.
+pub const USER_AGENT: &str = concat!("secretfop/", env!("CARGO_PKG_VERSION"));
+
+/// Default [`Transport`] backed by [`reqwest`].
+pub struct ReqwestTransport {
+    client: Client,
+}
+
+/// [`Transport`] that sends requests through a pool of proxies in turn. A
+/// failed request is retried through the next proxy, a proxy that fails
+/// [`MAX_PROXY_FAILURES`] times in a row is not used anymore.
+pub struct ProxyPool {
+    proxies: Vec<Arc<dyn Transport>>,
+    /// Consecutive failures of every proxy.
+    failures: Mutex<Vec<usize>>,
+    next: AtomicUsize,
+}
+
+/// [`Transport`] that limits concurrent requests, keeps a delay between them
+/// and retries failed ones, as set by [`PacingProfile`].
+pub struct PacedTransport {
+    inner: Arc<dyn Transport>,
+    profile: PacingProfile,
+    permits: Semaphore,
+    /// When the last request was started.
+    last: tokio::sync::Mutex<Option<Instant>>,
+}
+
+/// Limits of [`PacedTransport`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PacingProfile {
+    /// Requests sent at the same time.
+    pub concurrency: usize,
+    /// Least time between starts of requests.
+    pub delay: Duration,
+    /// Times a GET request failed by network is repeated. Other requests,
+    /// like Telegram posts, may have been handled before failing, so they are
+    /// never repeated.
+    pub retries: usize,
+}
+
+/// Consecutive failures after which [`ProxyPool`] evicts a proxy.
+pub const MAX_PROXY_FAILURES: usize = 3;
+
+/// [`Transport`] that saves every response to a directory, named by
+/// [`Request::record_name`]. Secrets are replaced with `REDACTED`.
+pub struct RecordTransport {
+    inner: Arc<dyn Transport>,
+    dir: PathBuf,
+    secrets: Vec<String>,
+}
+
+/// [`Transport`] that reads responses from a directory instead of network.
+/// Response of a request is read from file named by
+/// [`Request::record_name`], or from `<api method>.json` if there is no such
+/// file.
+pub struct ReplayTransport {
+    dir: PathBuf,
+}
+
+tokio::task_local! {
+    /// Source that requests are counted for, see [`Traffic::scope`].
+    static METERED_SOURCE: String;
+}
+
+/// Bytes sent and received over HTTP, without headers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Bandwidth {
+    pub downloaded: u64,
+    pub uploaded: u64,
+}
+
+/// Traffic of a run, counted by [`MeteredTransport`], in total and by
+/// source.
+#[derive(Debug, Default)]
+pub struct Traffic {
+    total: Mutex<Bandwidth>,
+    by_source: Mutex<HashMap<String, Bandwidth>>,
+}
+
+/// [`Transport`] that counts bytes of requests and responses in [`Traffic`].
+/// Requests made in [`Traffic::scope`] are also counted for its source.
+pub struct MeteredTransport {
+    inner: Arc<dyn Transport>,
+    traffic: Arc<Traffic>,
+}
+
+/// [`Transport`] that fails requests at random, to see how the bot copes
+/// with failing APIs. A failed request is not sent and gets a simulated error
+/// or rate limit of VK or Telegram, or a network error.
+pub struct ChaosTransport {
+    inner: Arc<dyn Transport>,
+    probability: f64,
+}
+
+impl Request {
+    pub fn get(url: impl Into<String>) -> Self {
+        Self {
+            method: Method::Get,
+            url: url.into(),
+            query: Vec::new(),
+            form: Vec::new(),
+            bearer: None,
+            files: Vec::new(),
+            body: None,
+        }
+    }
+
+    pub fn post(url: impl Into<String>) -> Self {
+        Self {
+            method: Method::Post,
+            ..Self::get(url)
+        }
+    }
+
+    pub fn put(url: impl Into<String>) -> Self {
+        Self {
+            method: Method::Put,
+            ..Self::get(url)
+        }
+    }
+
+    pub fn query(mut self, key: &str, value: impl Into<String>) -> Self {
+        self.query.push((key.to_owned(), value.into()));
+
+        self
+    }
+
+    pub fn form(mut self, key: &str, value: impl Into<String>) -> Self {
+        self.form.push((key.to_owned(), value.into()));
+
+        self
+    }
+
+    pub fn bearer_auth(mut self, token: &str) -> Self {
+        self.bearer = Some(token.to_owned());
+
+        self
+    }
+
+    pub fn file(mut self, field: &str, bytes: Vec<u8>) -> Self {
+        self.files.push(RequestFile {
+            field: field.to_owned(),
+            bytes,
+            name: None,
+            mime: None,
+        });
+
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<String>) -> Self {
+        self.body = Some(body.into());
+
+        self
+    }
+
+    /// API method, the last segment of URL path, like `wall.get`.
+    pub fn api_method(&self) -> &str {
+        let path = self.url.split(['?', '#']).next().unwrap_or_default();
+
+        path.rsplit('/').next().unwrap_or_default()
+    }
+
+    /// File name for recorded response of this request, like
+    /// `wall.get-0123456789abcdef.json`. Depends on API method and query, but
+    /// not on host or credentials, including `access_token` parameter.
+    pub fn record_name(&self) -> String {
+        let query = self
+            .query
+            .iter()
+            .filter(|(k, _)| k != "access_token")
+            .map(|(k, v)| format!("{k}={v}"))
+            .collect::<Vec<_>>()
+            .join("&");
+        let method: String = self
+            .api_method()
+            .chars()
+            .map(|c| match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '_' | '-' => c,
+                _ => '_',
+            })
+            .collect();
+
+        format!("{method}-{:016x}.json", fnv1a(query.as_bytes()))
+    }
+
+    /// Bytes sent with the request without headers, counting parameters
+    /// as they are and not encoded.
+    pub fn size(&self) -> u64 {
+        let params = self
+            .query
+            .iter()
+            .chain(&self.form)
+            .map(|(k, v)| k.len() + v.len() + 2);
+        let files = self.files.iter().map(|f| f.bytes.len());
+        let body = self.body.as_ref().map_or(0, String::len);
+
+        (self.url.len() + params.sum::<usize>() + files.sum::<usize>() + body) as u64
+    }
+}
+
+// files may be large, only their sizes are shown
+impl std::fmt::Debug for RequestFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RequestFile")
+            .field("field", &self.field)
+            .field("len", &self.bytes.len())
+            .finish()
+    }
+}
+
+impl ReqwestTransport {
+    pub fn new(client: Client) -> Self {
+        Self { client }
+    }
+
+    /// Transport sending given `User-Agent` with every request.
+    ///
+    /// # Panics
+    ///
+    /// Like [`Client::new`], if TLS backend can't be initialized. Also if
+    /// `user_agent` is not a valid header value, see [`is_valid_header`].
+    pub fn with_user_agent(user_agent: &str) -> Self {
+        Self::new(
+            Client::builder()
+                .user_agent(user_agent)
+                .build()
+                .expect("valid user agent and TLS backend"),
+        )
+    }
+}
+impl ReqwestTransport {
+    /// Transport sending requests through `proxy`, like
+    /// `socks5://127.0.0.1:1080`, with given `User-Agent`.
+    pub fn with_proxy(user_agent: &str, proxy: &str) -> Result<Self, HttpError> {
+        let client = Client::builder()
+            .user_agent(user_agent)
+            .proxy(Proxy::all(proxy)?)
+            .build()?;
+
+        Ok(Self::new(client))
+    }
+}
+impl Default for ReqwestTransport {
+    /// Transport sending [`USER_AGENT`].
+    fn default() -> Self {
+        Self::with_user_agent(USER_AGENT)
+    }
+}
+impl Transport for ReqwestTransport {
+    fn send(&self, request: Request) -> BoxFuture<'_, Result<String, HttpError>> {
+        Box::pin(async move {
+            let builder = match request.method {
+                Method::Get => self.client.get(&request.url),
+                Method::Post => self.client.post(&request.url),
+                Method::Put => self.client.put(&request.url),
+            };
+            let builder = match &request.bearer {
+                Some(token) => builder.bearer_auth(token),
+                None => builder,
+            };
+            let builder = if !request.form.is_empty() {
+                builder.form(&request.form)
+            } else if let (true, Some(body)) = (request.files.is_empty(), request.body) {
+                builder.body(body)
+            } else if request.files.is_empty() {
+                builder
+            } else {
+                let mut form = Form::new();
+                for file in request.files {
+                    let name = file.name.unwrap_or_else(|| file.field.clone());
+                    let part = Part::bytes(file.bytes).file_name(name);
+                    let part = match &file.mime {
+                        Some(mime) => part.mime_str(mime)?,
+                        None => part,
+                    };
+                    form = form.part(file.field, part);
+                }
+                builder.multipart(form)
+            };
+
+            let res = builder.query(&request.query).send().await?.text().await?;
+
+            Ok(res)
+        })
+    }
+
+    fn download(&self, request: Request) -> BoxFuture<'_, Result<Vec<u8>, HttpError>> {
+        Box::pin(async move {
+            let res = self
+                .client
+                .get(&request.url)
+                .query(&request.query)
+                .send()
+                .await?
+                .error_for_status()?
+                .bytes()
+                .await?;
+
+            Ok(res.to_vec())
+        })
+    }
+}
+
+impl ProxyPool {
+    pub fn new(proxies: Vec<Arc<dyn Transport>>) -> Self {
+        Self {
+            failures: Mutex::new(vec![0; proxies.len()]),
+            proxies,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Indexes of proxies to try, starting from the next one in turn.
+    fn order(&self) -> Vec<usize> {
+        let failures = self.failures.lock().expect("not poisoned");
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+
+        (0..self.proxies.len())
+            .map(|i| (start + i) % self.proxies.len())
+            .filter(|&i| failures[i] < MAX_PROXY_FAILURES)
+            .collect()
+    }
+
+    fn record(&self, proxy: usize, ok: bool) {
+        let mut failures = self.failures.lock().expect("not poisoned");
+        failures[proxy] = if ok { 0 } else { failures[proxy] + 1 };
+    }
+
+    /// Sends request through proxies until one succeeds.
+    async fn with_proxies<'a, T, F>(&'a self, send: F) -> Result<T, HttpError>
+    where
+        F: Fn(&'a dyn Transport) -> BoxFuture<'a, Result<T, HttpError>>,
+    {
+        let mut last = None;
+        for i in self.order() {
+            let res = send(self.proxies[i].as_ref()).await;
+            self.record(i, res.is_ok());
+            match res {
+                Ok(v) => return Ok(v),
+                Err(e) => last = Some(e),
+            }
+        }
+
+        Err(last.unwrap_or_else(|| "all proxies of the pool failed".into()))
+    }
+}
+impl Transport for ProxyPool {
+    fn send(&self, request: Request) -> BoxFuture<'_, Result<String, HttpError>> {
+        Box::pin(self.with_proxies(move |proxy| proxy.send(request.clone())))
+    }
+
+    fn download(&self, request: Request) -> BoxFuture<'_, Result<Vec<u8>, HttpError>> {
+        Box::pin(self.with_proxies(move |proxy| proxy.download(request.clone())))
+    }
+}
+
+impl PacedTransport {
+    pub fn new(inner: Arc<dyn Transport>, profile: PacingProfile) -> Self {
+        Self {
+            inner,
+            permits: Semaphore::new(profile.concurrency.max(1)),
+            profile,
+            last: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    /// Sends request when pacing allows, retrying network failures if
+    /// `retry` is set.
+    async fn paced<'a, T, F>(&'a self, retry: bool, send: F) -> Result<T, HttpError>
+    where
+        F: Fn(&'a dyn Transport) -> BoxFuture<'a, Result<T, HttpError>>,
+    {
+        let _permit = self.permits.acquire().await?;
+
+        let mut attempt = 0;
+        loop {
+            {
+                let mut last = self.last.lock().await;
+                if let Some(wait) = last.and_then(|l| self.profile.delay.checked_sub(l.elapsed())) {
+                    tokio::time::sleep(wait).await;
+                }
+                *last = Some(Instant::now());
+            }
+
+            match send(self.inner.as_ref()).await {
+                Err(_) if retry && attempt < self.profile.retries => {
+                    attempt += 1;
+                    tokio::time::sleep(RETRY_DELAY * attempt as u32).await;
+                }
+                res => return res,
+            }
+        }
+    }
+}
+impl Transport for PacedTransport {
+    fn send(&self, request: Request) -> BoxFuture<'_, Result<String, HttpError>> {
+        let retry = request.method == Method::Get;
+        Box::pin(self.paced(retry, move |inner| inner.send(request.clone())))
+    }
+
+    fn download(&self, request: Request) -> BoxFuture<'_, Result<Vec<u8>, HttpError>> {
+        let retry = request.method == Method::Get;
+        Box::pin(self.paced(retry, move |inner| inner.download(request.clone())))
+    }
+}
+
+/// Delay before the first retry of [`PacedTransport`], grows with every
+/// next one.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Checks that value can be sent as HTTP header, e.g. has no line breaks.
+pub fn is_valid_header(value: &str) -> bool {
+    reqwest::header::HeaderValue::from_str(value).is_ok()
+}
+
+/// Checks that value is a proxy URL, like `socks5://127.0.0.1:1080`.
+pub fn is_valid_proxy(url: &str) -> bool {
+    Proxy::all(url).is_ok()
+}
+
+impl ChaosTransport {
+    /// Wraps transport, failing requests with `probability` from 0 to 1.
+    pub fn new(inner: Arc<dyn Transport>, probability: f64) -> Self {
+        Self {
+            inner,
+            probability: probability.clamp(0.0, 1.0),
+        }
+    }
+
+    fn fails(&self) -> bool {
+        rand::thread_rng().gen_bool(self.probability)
+    }
+
+    /// Simulated response to `request`, by its API.
+    fn failure(request: &Request) -> Result<String, HttpError> {
+        let mut rng = rand::thread_rng();
+        // VK methods are like `wall.get`, Telegram ones are under `/bot<token>`
+        let vk = request.api_method().contains('.');
+        let telegram = request.url.contains("/bot");
+        match rng.gen_range(0..3) {
+            0 if vk => Ok(
+                r#"{"error":{"error_code":6,"error_msg":"Too many requests per second"}}"#
+                    .to_owned(),
+            ),
+            1 if vk => {
+                Ok(r#"{"error":{"error_code":10,"error_msg":"Internal server error"}}"#.to_owned())
+            }
+            0 if telegram => {
+                let retry = rng.gen_range(1..=120);
+                Ok(format!(
+                    r#"{{"ok":false,"error_code":429,"description":"Too Many Requests: retry after {retry}","parameters":{{"retry_after":{retry}}}}}"#
+                ))
+            }
+            1 if telegram => Ok(
+                r#"{"ok":false,"error_code":500,"description":"Internal Server Error"}"#.to_owned(),
+            ),
+            _ => Err("simulated network error".into()),
+        }
+    }
+}
+impl Transport for ChaosTransport {
+    fn send(&self, request: Request) -> BoxFuture<'_, Result<String, HttpError>> {
+        Box::pin(async move {
+            if self.fails() {
+                return Self::failure(&request);
+            }
+
+            self.inner.send(request).await
+        })
+    }
+
+    fn download(&self, request: Request) -> BoxFuture<'_, Result<Vec<u8>, HttpError>> {
+        Box::pin(async move {
+            if self.fails() {
+                return Err("simulated network error".into());
+            }
+
+            self.inner.download(request).await
+        })
+    }
+}
+
+impl Bandwidth {
+    /// Downloaded and uploaded bytes together.
+    pub fn total(&self) -> u64 {
+        self.downloaded + self.uploaded
+    }
+}
+impl std::ops::AddAssign for Bandwidth {
+    fn add_assign(&mut self, other: Self) {
+        self.downloaded += other.downloaded;
+        self.uploaded += other.uploaded;
+    }
+}
+
+impl Traffic {
+    /// Runs `future`, counting requests it makes for `source`, like
+    /// `vk/apiclub`.
+    pub async fn scope<F: Future>(source: String, future: F) -> F::Output {
+        METERED_SOURCE.scope(source, future).await
+    }
+
+    /// Bytes of the run so far.
+    pub fn total(&self) -> Bandwidth {
+        *self.total.lock().expect("traffic is not poisoned")
+    }
+
+    /// Bytes of every source, sorted by source.
+    pub fn by_source(&self) -> Vec<(String, Bandwidth)> {
+        let by_source = self.by_source.lock().expect("traffic is not poisoned");
+        let mut by_source: Vec<_> = by_source.iter().map(|(k, v)| (k.clone(), *v)).collect();
+        by_source.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        by_source
+    }
+
+    fn record(&self, source: Option<String>, bandwidth: Bandwidth) {
+        *self.total.lock().expect("traffic is not poisoned") += bandwidth;
+        if let Some(source) = source {
+            let mut by_source = self.by_source.lock().expect("traffic is not poisoned");
+            *by_source.entry(source).or_default() += bandwidth;
+        }
+    }
+}
+
+impl MeteredTransport {
+    /// Wraps transport, counting its traffic in `traffic`.
+    pub fn new(inner: Arc<dyn Transport>, traffic: Arc<Traffic>) -> Self {
+        Self { inner, traffic }
+    }
+}
+impl Transport for MeteredTransport {
+    fn send(&self, request: Request) -> BoxFuture<'_, Result<String, HttpError>> {
+        Box::pin(async move {
+            let source = METERED_SOURCE.try_with(String::clone).ok();
+            let uploaded = request.size();
+            let res = self.inner.send(request).await;
+            let downloaded = res.as_ref().map(|body| body.len() as u64).unwrap_or(0);
+            self.traffic.record(
+                source,
+                Bandwidth {
+                    downloaded,
+                    uploaded,
+                },
+            );
+
+            res
+        })
+    }
+
+    fn download(&self, request: Request) -> BoxFuture<'_, Result<Vec<u8>, HttpError>> {
+        Box::pin(async move {
+            let source = METERED_SOURCE.try_with(String::clone).ok();
+            let uploaded = request.size();
+            let res = self.inner.download(request).await;
+            let downloaded = res.as_ref().map(|body| body.len() as u64).unwrap_or(0);
+            self.traffic.record(
+                source,
+                Bandwidth {
+                    downloaded,
+                    uploaded,
+                },
+            );
+
+            res
+        })
+    }
+}
+
+impl RecordTransport {
+    /// Wraps transport, creating the directory if needed.
+    pub fn new(inner: Arc<dyn Transport>, dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+
+        Ok(Self {
+            inner,
+            dir,
+            secrets: Vec::new(),
+        })
+    }
+
+    /// Adds string that should not get to recorded responses, like API token.
+    pub fn redact(mut self, secret: impl Into<String>) -> Self {
+        let secret = secret.into();
+        if !secret.is_empty() {
+            self.secrets.push(secret);
+        }
+
+        self
+    }
+}
+impl Transport for RecordTransport {
+    fn send(&self, request: Request) -> BoxFuture<'_, Result<String, HttpError>> {
+        Box::pin(async move {
+            let path = self.dir.join(request.record_name());
+            let res = self.inner.send(request).await?;
+
+            let recorded = self
+                .secrets
+                .iter()
+                .fold(res.clone(), |body, secret| body.replace(secret, "REDACTED"));
+            // a recorded run is only useful to replay if every response is saved
+            tokio::fs::write(&path, recorded)
+                .await
+                .map_err(|e| format!("failed to record {}: {e}", path.display()))?;
+
+            Ok(res)
+        })
+    }
+
+    /// Files are not recorded, only API responses.
+    fn download(&self, request: Request) -> BoxFuture<'_, Result<Vec<u8>, HttpError>> {
+        self.inner.download(request)
+    }
+}
+
+impl ReplayTransport {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+}
+impl Transport for ReplayTransport {
+    fn send(&self, request: Request) -> BoxFuture<'_, Result<String, HttpError>> {
+        Box::pin(async move {
+            let exact = self.dir.join(request.record_name());
+            let fallback = self.dir.join(format!("{}.json", request.api_method()));
+
+            for path in [exact, fallback] {
+                match tokio::fs::read_to_string(&path).await {
+                    Ok(res) => return Ok(res),
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                    Err(e) => return Err(e.into()),
+                }
+            }
+
+            Err(format!(
+                "no recorded response for {} in {}",
+                request.record_name(),
+                self.dir.display()
+            )
+            .into())
+        })
+    }
+}
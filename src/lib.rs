@@ -0,0 +1,50 @@
+//! Library behind the secretfop bot, that mirrors social media posts to a
+//! Telegram channel.
+//!
+//! - [`config`] contains configuration and cache models;
+//! - [`sources`] contains clients of social networks, that produce
+//!   [`sources::ForeignPost`]s;
+//! - [`telegram`] contains Telegram client to post them;
+//! - [`pipeline`] glues everything together;
+//! - [`hooks`] are extension points to transform and filter posts;
+//! - [`http`] is a replaceable HTTP layer used by all clients;
+//! - [`fingerprint`] defines when two posts have the same content;
+//! - [`media`] caches downloaded media files;
+//! - [`auth`] obtains user tokens for the config by OAuth;
+//! - [`doctor`] diagnoses the environment of the bot;
+//! - [`exec`] runs external commands as sources and sinks;
+//! - [`archive`] keeps every mirrored post for `search`;
+//! - [`events`] logs decisions of every run;
+//! - [`metrics`] pushes counters of every run to Prometheus;
+//! - [`error`] contains [`error::Error`] returned by the library.
+//!
+//! The `secretfop` binary is a thin CLI over [`pipeline::Pipeline`].
+
+#[cfg(feature = "archive")]
+pub mod archive;
+pub mod auth;
+pub mod config;
+pub mod doctor;
+pub mod error;
+pub mod events;
+#[cfg(feature = "exec")]
+pub mod exec;
+pub mod fingerprint;
+pub mod hooks;
+pub mod http;
+pub mod media;
+pub mod metrics;
+pub mod pipeline;
+pub mod sources;
+pub mod telegram;
+pub mod text;
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Current Unix time in seconds.
+pub(crate) fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
@@ -1,22 +1,29 @@
 use std::{
-    fs::{self, File},
-    io::{self, BufReader},
-    path::PathBuf,
+    io::Write,
+    path::{Path, PathBuf},
     process::ExitCode,
+    sync::Arc,
+    time::Duration,
 };
 
-use clap::Parser;
-use config::{Config, SocialAccount};
-use futures::future::join_all;
-use sources::vk::VKGroupFeed;
-use telegram::{TelegramClient, TelegramError};
-use tokio::time;
-
-use crate::{config::CacheRecords, sources::vk::VKClient};
+use chrono::{Local, TimeZone};
+use clap::{Parser, Subcommand};
+#[cfg(feature = "archive")]
+use secretfop::archive::{Archive, ArchivedPost};
+use secretfop::{
+    auth,
+    config::{self, CacheLocation, CacheRecords, Config},
+    doctor::{self, Check},
+    error::{Error, Report},
+    http::{ChaosTransport, RecordTransport, ReplayTransport, Traffic, Transport},
+    pipeline::{Pipeline, PlanStatus, PlannedPost, PostPreview},
+    sources::{Media, Registry},
+    telegram,
+};
+use tokio::sync::watch;
 
-mod config;
-mod sources;
-mod telegram;
+/// Exit code of runs skipped due to Telegram rate limit, `EX_TEMPFAIL`.
+const EXIT_FLOOD_WAIT: u8 = 75;
 
 #[derive(Parser)]
 #[command(version, about)]
@@ -25,13 +32,150 @@ struct Args {
     #[arg(long, default_value = ".secretfop.yml")]
     config: PathBuf,
 
-    /// Path to cache file.
+    /// Path to cache file, or to a SQLite database like sqlite:cache.db
     #[arg(long, default_value = ".cache.secretfop.json")]
-    cache: PathBuf,
+    cache: CacheLocation,
 
     /// Populate cache, but not post
     #[arg(long)]
     populate: bool,
+
+    /// Read API responses from directory instead of network
+    #[arg(long, value_name = "DIR")]
+    replay: Option<PathBuf>,
+
+    /// Save API responses to directory, to replay them later
+    #[arg(long, value_name = "DIR", conflicts_with = "replay")]
+    record: Option<PathBuf>,
+
+    /// Seed dedup with channel history exported by Telegram Desktop as JSON
+    #[arg(long, value_name = "FILE")]
+    seed: Option<PathBuf>,
+
+    /// Print posts that would be posted and requests they would be sent with,
+    /// but not post them or save cache
+    #[arg(long, conflicts_with = "populate")]
+    dry_run: bool,
+
+    /// With --dry-run, also print mirrored posts and check new ones against
+    /// dedup and channel history
+    #[arg(long, requires = "dry_run")]
+    diff: bool,
+
+    /// Fail on unknown config keys, like `strict: true` in config
+    #[arg(long)]
+    strict_config: bool,
+
+    /// Skip sources with invalid config with a warning, like
+    /// `ignore_source_errors: true` in config
+    #[arg(long)]
+    ignore_source_errors: bool,
+
+    /// Fail API requests with simulated errors and rate limits at random,
+    /// with probability from 0 to 1, to test recovery from failures
+    #[arg(long, hide = true, value_name = "PROBABILITY", value_parser = probability)]
+    chaos: Option<f64>,
+
+    /// Keep running, mirroring new posts every DURATION, like 5m, and saving
+    /// cache after every run
+    #[arg(long, value_name = "DURATION", value_parser = interval)]
+    #[arg(conflicts_with_all = ["populate", "dry_run"])]
+    interval: Option<u64>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Obtain a user token by OAuth and save it to config
+    #[command(subcommand)]
+    Auth(AuthCommand),
+    /// Print caption and media of a post as they would be sent, without
+    /// posting
+    Preview {
+        /// Configured source, like vk:apiclub
+        #[arg(long)]
+        source: String,
+
+        /// Id of a recent post of the source
+        #[arg(long)]
+        post: String,
+    },
+    /// Mirror a range of older posts of a source, without moving its
+    /// watermark
+    Backfill {
+        /// Configured source, like vk:apiclub
+        #[arg(long)]
+        source: String,
+
+        /// Id of the oldest post to mirror
+        #[arg(long)]
+        from: u64,
+
+        /// Id of the newest post to mirror
+        #[arg(long)]
+        to: u64,
+
+        /// Time to wait between posts, like 30s
+        #[arg(long, value_name = "DURATION", default_value = "30s")]
+        #[arg(value_parser = config::parse_duration)]
+        delay: u64,
+    },
+    /// Find mirrored posts in the archive by text, source or URL
+    #[cfg(feature = "archive")]
+    Search {
+        /// Text to look for, ignoring case
+        query: String,
+
+        /// Maximum number of posts to print, newest first
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Seed dedup with channel history and save the cache, without posting
+    Seed {
+        /// Channel history exported by Telegram Desktop as JSON
+        #[arg(long, value_name = "FILE")]
+        from_export: PathBuf,
+    },
+    /// Check config, cache, tokens and rights of the bot in its channels
+    Doctor,
+    /// Stop fetching a source until it is resumed, keeping its watermark
+    Pause {
+        /// Configured source, like vk:apiclub
+        source: String,
+    },
+    /// Fetch a paused source again
+    Resume {
+        /// Configured source, like vk:apiclub
+        source: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum AuthCommand {
+    /// VKontakte user token, through a VK app
+    #[cfg(feature = "vk")]
+    Vk {
+        /// ID of the VK app
+        #[arg(long)]
+        client_id: u64,
+
+        /// Secret key of the app, to exchange authorization code instead of
+        /// getting token right in redirect URL
+        #[arg(long)]
+        client_secret: Option<String>,
+    },
+    /// Twitter user and refresh token, through OAuth 2.0 of a Twitter app
+    Twitter {
+        /// OAuth 2.0 client ID of the app, which should be a native app
+        #[arg(long)]
+        client_id: String,
+
+        /// Callback URL registered in the app settings
+        #[arg(long, default_value = auth::TWITTER_REDIRECT_URI)]
+        redirect_uri: String,
+    },
 }
 
 #[tokio::main]
@@ -40,125 +184,520 @@ async fn main() -> ExitCode {
         config,
         cache,
         populate,
+        replay,
+        record,
+        seed,
+        dry_run,
+        diff,
+        strict_config,
+        ignore_source_errors,
+        chaos,
+        interval,
+        command,
     } = Args::parse();
 
-    let cfg: Config = {
-        let file = match File::open(config) {
-            Ok(f) => BufReader::new(f),
-            Err(e) => {
-                eprintln!("Failed to open config: {e}");
+    #[cfg(feature = "archive")]
+    let mut search = None;
+    let mut backfill = None;
+    let preview = match command {
+        Some(Command::Auth(command)) => {
+            return match authorize(command, &config).await {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(e) => {
+                    eprintln!("Error: {}", Report(&e));
+                    ExitCode::FAILURE
+                }
+            };
+        }
+        Some(Command::Preview { source, post }) => Some((source, post)),
+        Some(Command::Backfill {
+            source,
+            from,
+            to,
+            delay,
+        }) => {
+            if from > to {
+                eprintln!("Error: --from {from} is greater than --to {to}");
+                return ExitCode::FAILURE;
+            }
+            backfill = Some((source, from..=to, Duration::from_secs(delay)));
+            None
+        }
+        #[cfg(feature = "archive")]
+        Some(Command::Search { query, limit }) => {
+            search = Some((query, limit));
+            None
+        }
+        Some(Command::Seed { from_export }) => {
+            let mut cache_records = match CacheRecords::load_from(&cache) {
+                Ok(v) => v,
+                Err(e) => {
+                    eprintln!("Error: {}", Report(&e));
+                    return ExitCode::FAILURE;
+                }
+            };
+            let count = match seed_from_export(&mut cache_records, &from_export) {
+                Ok(count) => count,
+                Err(e) => {
+                    eprintln!("Error: failed to read {}: {e}", from_export.display());
+                    return ExitCode::FAILURE;
+                }
+            };
+            if let Err(e) = cache_records.save_to(&cache) {
+                eprintln!("Error: {}", Report(&e));
                 return ExitCode::FAILURE;
             }
+            println!("Seeded {count} texts and links");
+
+            return ExitCode::SUCCESS;
+        }
+        Some(Command::Doctor) => {
+            let checks = doctor::diagnose(&config, &cache, None).await;
+            print_checks(&checks);
+
+            return if checks.iter().all(|c| c.passed) {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            };
+        }
+        Some(Command::Pause { source }) => {
+            return update_cache(&cache, |c| {
+                if c.pause(&source) {
+                    format!("Paused {source}")
+                } else {
+                    format!("{source} is already paused")
+                }
+            });
+        }
+        Some(Command::Resume { source }) => {
+            return update_cache(&cache, |c| {
+                if c.resume(&source) {
+                    format!("Resumed {source}")
+                } else {
+                    format!("{source} is not paused")
+                }
+            });
+        }
+        None => None,
+    };
+
+    let loaded = Config::load_with(&config, ignore_source_errors).and_then(|cfg| {
+        if strict_config {
+            cfg.ensure_known_keys(&config)?;
+        }
+
+        Ok(cfg)
+    });
+    let mut cfg = match loaded {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("Error: {}", Report(&e));
+            return ExitCode::FAILURE;
+        }
+    };
+
+    for warning in &cfg.warnings {
+        eprintln!("Warning: {warning}");
+    }
+
+    #[cfg(feature = "archive")]
+    if let Some((query, limit)) = search {
+        let Some(path) = &cfg.archive else {
+            eprintln!("Error: `archive` is not set in {}", config.display());
+            return ExitCode::FAILURE;
+        };
+        return match Archive::open(path).and_then(|a| a.search(&query, limit)) {
+            Ok(posts) => {
+                print_archived(&posts);
+                ExitCode::SUCCESS
+            }
+            Err(e) => {
+                eprintln!("Error: {}", Report(&e));
+                ExitCode::FAILURE
+            }
         };
+    }
 
-        match serde_yaml::from_reader(file) {
-            Ok(v) => v,
+    // recorded and replayed runs only go through their transport
+    if replay.is_some() || record.is_some() {
+        cfg.proxies.clear();
+    }
+    let transport: Arc<dyn Transport> = match (replay, record) {
+        (Some(dir), _) => Arc::new(ReplayTransport::new(dir)),
+        (None, Some(dir)) => match RecordTransport::new(Arc::new(cfg.transport()), &dir) {
+            Ok(t) => Arc::new(
+                t.redact(&cfg.vk_token)
+                    .redact(&cfg.twitter_token)
+                    .redact(&cfg.telegram.token),
+            ),
             Err(e) => {
-                eprintln!("Failed to parse config: {e}");
+                eprintln!("Error: failed to create {}: {e}", dir.display());
                 return ExitCode::FAILURE;
             }
-        }
+        },
+        (None, None) => Arc::new(cfg.transport()),
+    };
+    let transport: Arc<dyn Transport> = match chaos {
+        Some(probability) => Arc::new(ChaosTransport::new(transport, probability)),
+        None => transport,
     };
 
-    let cache_records: CacheRecords = 'brk: {
-        let file = match File::open(&cache) {
-            Ok(f) => BufReader::new(f),
-            Err(e) if e.kind() == io::ErrorKind::NotFound => break 'brk CacheRecords::new(),
+    if let Some((source, post)) = preview {
+        let preview = Pipeline::new(&cfg)
+            .transport(transport)
+            .preview(&source, &post)
+            .await;
+        return match preview {
+            Ok(preview) => {
+                print_preview(&preview);
+                ExitCode::SUCCESS
+            }
             Err(e) => {
-                eprintln!("Failed to open cache file: {e}");
-                return ExitCode::FAILURE;
+                eprintln!("Error: {}", Report(&e));
+                ExitCode::FAILURE
             }
         };
+    }
 
-        match serde_json::from_reader(file) {
+    if let Some((source, ids, delay)) = backfill {
+        let mut cache_records = match CacheRecords::load_from(&cache) {
             Ok(v) => v,
             Err(e) => {
-                eprintln!("Warning: Failed to parse cache file: {e}");
+                eprintln!("Error: {}", Report(&e));
+                return ExitCode::FAILURE;
+            }
+        };
+        let errors = Pipeline::new(&cfg)
+            .transport(transport)
+            .backfill(&mut cache_records, &source, ids, delay)
+            .await;
+        for e in &errors {
+            eprintln!("Error: {}", Report(e));
+        }
+        if let Err(e) = cache_records.save_to(&cache) {
+            eprintln!("Error: {}", Report(&e));
+            return ExitCode::FAILURE;
+        }
 
-                CacheRecords::new()
+        return if errors.is_empty() {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        };
+    }
+
+    // sources are built here, so their traffic is counted for the run
+    let traffic = Arc::new(Traffic::default());
+    let registry = Registry::default().metered(traffic.clone());
+    let sources = if cfg.ignore_source_errors {
+        let (sources, errors) = registry.build_skipping(&cfg, transport.clone());
+        for e in &errors {
+            eprintln!("Warning: skipping source: {}", Report(e));
+        }
+
+        sources
+    } else {
+        match registry.build(&cfg, transport.clone()) {
+            Ok(v) => v,
+            Err(e) => {
+                eprintln!("Error: {}", Report(&e));
+                return ExitCode::FAILURE;
             }
         }
     };
-    let mut new_cache_records = cache_records.clone();
 
-    let vk = VKClient::new(cfg.vk_token);
+    let mut cache_records = match CacheRecords::load_from(&cache) {
+        Ok(v) => v,
+        Err(e @ Error::ParseCache { .. }) => {
+            eprintln!("Warning: {}", Report(&e));
+
+            CacheRecords::new()
+        }
+        Err(e) => {
+            eprintln!("Error: {}", Report(&e));
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if let Some(path) = seed {
+        if let Err(e) = seed_from_export(&mut cache_records, &path) {
+            eprintln!("Error: failed to read {}: {e}", path.display());
+            return ExitCode::FAILURE;
+        }
+    }
+
+    if dry_run {
+        let (plan, errors) = Pipeline::new(&cfg)
+            .sources(sources)
+            .transport(transport)
+            .dry_run(&mut cache_records, diff)
+            .await;
+        for e in &errors {
+            eprintln!("Error: {}", Report(e));
+        }
+        print_plan(&plan);
 
-    let feeds: Vec<VKGroupFeed> = {
-        let jobs = cfg
-            .vk
-            .iter()
-            .map(|SocialAccount { id, .. }| vk.get_posts(id.as_ref()).send());
+        return ExitCode::SUCCESS;
+    }
 
-        join_all(jobs)
-            .await
-            .into_iter()
-            .filter_map(|v| match v {
-                Ok(v) => Some(v),
-                Err(e) => {
-                    eprintln!("Failed to fetch posts: {e}");
-                    None
-                }
-            })
-            .collect()
+    let pipeline = Pipeline::new(&cfg)
+        .sources(sources)
+        .transport(transport.clone())
+        .traffic(traffic)
+        .populate(populate);
+    let Some(interval) = interval else {
+        return run(pipeline, &mut cache_records, &cache).await;
     };
-    let posts = feeds.iter().flat_map(VKGroupFeed::as_iter).filter(|f| {
-        !f.media.is_empty()
-            && cache_records
-                .vk
-                .get(&f.source_id.to_string())
-                .map(|r| f.id > *r)
-                .unwrap_or(true)
+
+    // Ctrl-C during a run stops the bot after the run, with cache saved, so
+    // the handler is installed before the first one
+    let (stop, mut stopped) = watch::channel(false);
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            let _ = stop.send(true);
+        }
     });
+    // the bot exits with the code of its last run, like a rate limit
+    let mut code = run(pipeline, &mut cache_records, &cache).await;
+    // later runs build sources themselves, reporting broken ones as errors
+    loop {
+        if *stopped.borrow() {
+            return code;
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(interval)) => {}
+            _ = stopped.changed() => return code,
+        }
+        let pipeline = Pipeline::new(&cfg).transport(transport.clone());
+        code = run(pipeline, &mut cache_records, &cache).await;
+    }
+}
 
-    let telegram = TelegramClient::new(cfg.telegram_token, cfg.telegram_channel);
+/// Runs `pipeline` and saves the cache to `path`, printing errors.
+async fn run(pipeline: Pipeline<'_>, cache: &mut CacheRecords, path: &CacheLocation) -> ExitCode {
+    let errors = pipeline.run(cache).await;
+    for e in &errors {
+        eprintln!("Error: {}", Report(e));
+    }
 
-    if populate {
-        for post in posts.rev() {
-            new_cache_records
-                .vk
-                .entry(post.source_id.to_string())
-                .and_modify(|k| {
-                    if post.id > *k {
-                        *k = post.id.unwrap_number();
-                    }
-                })
-                .or_insert_with(|| post.id.unwrap_number());
-        }
+    if let Err(e) = cache.save_to(path) {
+        eprintln!("Error: {}", Report(&e));
+    }
+
+    if errors.iter().any(|e| matches!(e, Error::FloodWait { .. })) {
+        ExitCode::from(EXIT_FLOOD_WAIT)
     } else {
-        for post in posts.rev() {
-            let res = {
-                let res = telegram.send_message().by_foreign(&post).send().await;
+        ExitCode::SUCCESS
+    }
+}
 
-                if let Err(TelegramError::RateLimited { timeout }) = res {
-                    time::sleep(timeout).await;
-                    telegram.send_message().by_foreign(&post).send().await
-                } else {
-                    res
-                }
-            };
-            if let Err(e) = res {
-                eprintln!("Failed to post to telegram: {e}");
-            } else {
-                new_cache_records
-                    .vk
-                    .entry(post.source_id.to_string())
-                    .and_modify(|k| {
-                        if post.id > *k {
-                            *k = post.id.unwrap_number();
-                        }
-                    })
-                    .or_insert_with(|| post.id.unwrap_number());
+/// Parses probability of `--chaos`.
+fn probability(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(p) if (0.0..=1.0).contains(&p) => Ok(p),
+        _ => Err("expected a number from 0 to 1".to_owned()),
+    }
+}
+
+/// Parses duration of `--interval`, at least a second.
+fn interval(s: &str) -> Result<u64, String> {
+    match config::parse_duration(s)? {
+        0 => Err("expected a duration of at least 1s".to_owned()),
+        seconds => Ok(seconds),
+    }
+}
+
+/// Loads the cache at `path`, changes it with `update` and saves it, printing
+/// what `update` returns.
+fn update_cache(
+    path: &CacheLocation,
+    update: impl FnOnce(&mut CacheRecords) -> String,
+) -> ExitCode {
+    let mut cache = match CacheRecords::load_from(path) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("Error: {}", Report(&e));
+            return ExitCode::FAILURE;
+        }
+    };
+    let message = update(&mut cache);
+    if let Err(e) = cache.save_to(path) {
+        eprintln!("Error: {}", Report(&e));
+        return ExitCode::FAILURE;
+    }
+    println!("{message}");
+
+    ExitCode::SUCCESS
+}
+
+/// Seeds dedup with texts and links to original posts of a Telegram Desktop
+/// export at `path`. Returns how many were read.
+fn seed_from_export(cache: &mut CacheRecords, path: &Path) -> Result<usize, String> {
+    let json = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let texts = telegram::export_texts(&json).map_err(|e| e.to_string())?;
+    let links = telegram::export_links(&json).map_err(|e| e.to_string())?;
+    texts.iter().for_each(|text| cache.seed(text));
+    links.iter().for_each(|link| cache.seed_link(link));
+
+    Ok(texts.len() + links.len())
+}
+
+/// Runs OAuth flow of `auth` subcommand and saves the token to `config`.
+async fn authorize(command: AuthCommand, config: &Path) -> Result<(), auth::AuthError> {
+    match command {
+        #[cfg(feature = "vk")]
+        AuthCommand::Vk {
+            client_id,
+            client_secret,
+        } => {
+            let mut oauth = auth::VKOAuth::new(client_id);
+            if let Some(secret) = client_secret {
+                oauth = oauth.with_client_secret(secret);
+            }
+
+            println!(
+                "Open this URL and allow access:\n\n{}\n",
+                oauth.authorize_url()
+            );
+            let token = oauth
+                .token(&prompt("Paste URL of the page you were redirected to: "))
+                .await?;
+            auth::update_config(
+                config,
+                &[("vk_token", &token.access_token), ("vk_token_kind", "user")],
+            )?;
+            println!("Saved vk_token to {}", config.display());
+        }
+        AuthCommand::Twitter {
+            client_id,
+            redirect_uri,
+        } => {
+            let oauth = auth::TwitterOAuth::new(&client_id).with_redirect_uri(redirect_uri);
+
+            println!(
+                "Open this URL and allow access:\n\n{}\n",
+                oauth.authorize_url()
+            );
+            let token = oauth
+                .token(&prompt("Paste URL of the page you were redirected to: "))
+                .await?;
+            let mut keys = vec![
+                ("twitter_token", token.access_token.as_str()),
+                ("twitter_client_id", client_id.as_str()),
+            ];
+            if let Some(refresh) = &token.refresh_token {
+                keys.push(("twitter_refresh_token", refresh));
             }
+            auth::update_config(config, &keys)?;
+            println!("Saved twitter_token to {}", config.display());
         }
     }
 
-    if let Ok(data) = serde_json::to_string(&new_cache_records) {
-        if let Err(e) = fs::write(cache, data) {
-            eprintln!("Failed to write to cache: {e}");
+    Ok(())
+}
+
+/// Prints `message` and reads a line from stdin.
+fn prompt(message: &str) -> String {
+    print!("{message}");
+    let _ = std::io::stdout().flush();
+    let mut line = String::new();
+    let _ = std::io::stdin().read_line(&mut line);
+
+    line
+}
+
+/// Prints a post as it would be sent.
+fn print_preview(preview: &PostPreview) {
+    println!("Source: {}/{}", preview.network, preview.account);
+    println!("Post: {}", preview.post.url);
+    if !preview.accepted {
+        println!("Rejected by filters, would not be posted");
+    }
+    println!("Media:");
+    for media in &preview.post.media {
+        let kind = match media {
+            Media::Photo(_) => "photo",
+            Media::Video(_) => "video",
+            Media::Animation(_) => "animation",
+            Media::Audio { .. } => "audio",
+            Media::Document { .. } => "document",
+        };
+        println!("  {kind:<9}  {}", media.url());
+    }
+    println!(
+        "Caption ({} of {} characters after parsing):",
+        telegram::html_len(&preview.caption),
+        telegram::MAX_CAPTION_LENGTH
+    );
+    println!("{}", preview.caption);
+}
+
+/// Prints posts found in the archive, with the first line of their text.
+#[cfg(feature = "archive")]
+fn print_archived(posts: &[ArchivedPost]) {
+    println!("{:<16}  {:<24}  {:<32}  URL", "POSTED", "SOURCE", "MESSAGE");
+    for post in posts {
+        let posted = i64::try_from(post.posted_at)
+            .ok()
+            .and_then(|date| Local.timestamp_opt(date, 0).single())
+            .map(|date| date.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_default();
+        println!(
+            "{posted:<16}  {:<24}  {:<32}  {}",
+            post.source,
+            message_link(&post.chat, post.message_id),
+            post.url
+        );
+        if let Some(line) = post.text.lines().find(|l| !l.trim().is_empty()) {
+            println!("    {line}");
         }
-    } else {
-        eprintln!("Failed to serialize data to cache (why?..)");
     }
+}
 
-    ExitCode::SUCCESS
+/// Link to message of a public channel or a private one by id.
+#[cfg(feature = "archive")]
+fn message_link(chat: &str, message_id: u64) -> String {
+    match (chat.strip_prefix('@'), chat.strip_prefix("-100")) {
+        (Some(name), _) => format!("https://t.me/{name}/{message_id}"),
+        (_, Some(id)) => format!("https://t.me/c/{id}/{message_id}"),
+        _ => format!("{chat}/{message_id}"),
+    }
+}
+
+/// Prints results of `doctor` checks.
+fn print_checks(checks: &[Check]) {
+    for check in checks {
+        let result = if check.passed { "ok" } else { "FAIL" };
+        println!("{result:<4}  {:<24}  {}", check.name, check.details);
+    }
+}
+
+/// Prints posts of a dry run as a table.
+fn print_plan(plan: &[PlannedPost]) {
+    println!("{:<16}  {:<24}  {:<16}  URL", "STATUS", "SOURCE", "DATE");
+    for planned in plan {
+        let status = match planned.status {
+            PlanStatus::New => "new".to_owned(),
+            PlanStatus::Duplicate => "duplicate".to_owned(),
+            PlanStatus::Mirrored => "mirrored".to_owned(),
+            PlanStatus::Skipped(reason) => reason.to_string(),
+        };
+        let date = i64::try_from(planned.post.date)
+            .ok()
+            .and_then(|date| Local.timestamp_opt(date, 0).single())
+            .map(|date| date.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_default();
+        println!(
+            "{status:<16}  {:<24}  {date:<16}  {}",
+            format!("{}/{}", planned.network, planned.account),
+            planned.post.url
+        );
+    }
+    for planned in plan {
+        if let Some(message) = &planned.message {
+            let json = serde_json::to_string_pretty(&message.to_json()).unwrap_or_default();
+            println!("\n{}\n{} {json}", planned.post.url, message.method);
+        }
+    }
 }
@@ -0,0 +1,233 @@
+//! Cache of downloaded media files, so a file used several times (checked,
+//! hashed, uploaded) is downloaded once.
+//!
+//! Files are stored in a directory, named by [`url_hash`] of their URL, so
+//! the same file with different signatures in query string is not
+//! downloaded again. When the directory grows over the size limit, the
+//! oldest files are removed.
+//!
+//! Downloaded files can also be checked by [`MediaCache::check`] before they
+//! are sent, by their first bytes and size.
+
+use std::{io, path::PathBuf, sync::Arc, time::SystemTime};
+
+use thiserror::Error;
+
+use crate::{
+    fingerprint::url_hash,
+    http::{HttpError, Request, Transport},
+    sources::Media,
+};
+
+/// Size limit of [`MediaCache::temp`], in bytes.
+pub const DEFAULT_CACHE_SIZE: u64 = 256 * 1024 * 1024;
+/// Largest photo a bot can upload, in bytes.
+pub const MAX_PHOTO_SIZE: u64 = 10 * 1024 * 1024;
+/// Largest file of other kinds a bot can upload, in bytes.
+pub const MAX_FILE_SIZE: u64 = 50 * 1024 * 1024;
+
+#[derive(Debug, Error)]
+pub enum MediaError {
+    #[error("failed to download {url}: {source}")]
+    Download { url: String, source: HttpError },
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// Why media can't be sent, see [`MediaCache::check`].
+#[derive(Debug, Error)]
+pub enum InvalidMedia {
+    #[error("not a media file")]
+    UnknownType,
+    #[error("{size} bytes is over the limit of {limit}")]
+    TooLarge { size: u64, limit: u64 },
+    #[error(transparent)]
+    Media(#[from] MediaError),
+}
+
+/// Type of a file, by its first bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    Jpeg,
+    Png,
+    Webp,
+    Gif,
+    Mp4,
+    Webm,
+    Mp3,
+    M4a,
+    /// HTML page, like an error page instead of the file.
+    Html,
+}
+
+impl FileType {
+    /// Recognizes the type by the signature of the file, if known.
+    pub fn sniff(bytes: &[u8]) -> Option<Self> {
+        let brand = bytes.get(8..12);
+        let html = match bytes.iter().position(|b| !b.is_ascii_whitespace()) {
+            Some(i) => {
+                let text = bytes[i..bytes.len().min(i + 15)].to_ascii_lowercase();
+                text.starts_with(b"<!doctype html") || text.starts_with(b"<html")
+            }
+            None => false,
+        };
+
+        match bytes {
+            [0xFF, 0xD8, 0xFF, ..] => Some(Self::Jpeg),
+            [0x89, b'P', b'N', b'G', ..] => Some(Self::Png),
+            [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some(Self::Webp),
+            [b'G', b'I', b'F', b'8', ..] => Some(Self::Gif),
+            [0x1A, 0x45, 0xDF, 0xA3, ..] => Some(Self::Webm),
+            [b'I', b'D', b'3', ..] => Some(Self::Mp3),
+            [0xFF, b, ..] if b & 0xE0 == 0xE0 => Some(Self::Mp3),
+            [_, _, _, _, b'f', b't', b'y', b'p', ..] if brand == Some(b"M4A ") => Some(Self::M4a),
+            [_, _, _, _, b'f', b't', b'y', b'p', ..] => Some(Self::Mp4),
+            _ if html => Some(Self::Html),
+            _ => None,
+        }
+    }
+}
+
+/// Size-bounded directory of downloaded files.
+pub struct MediaCache {
+    dir: PathBuf,
+    max_size: u64,
+    transport: Arc<dyn Transport>,
+}
+
+impl MediaCache {
+    /// Cache in `dir` keeping up to `max_size` bytes. Directory is created
+    /// on the first download.
+    pub fn new(dir: impl Into<PathBuf>, max_size: u64, transport: Arc<dyn Transport>) -> Self {
+        Self {
+            dir: dir.into(),
+            max_size,
+            transport,
+        }
+    }
+
+    /// Cache in the system temporary directory, shared between runs.
+    pub fn temp(transport: Arc<dyn Transport>) -> Self {
+        Self::new(
+            std::env::temp_dir().join("secretfop-media"),
+            DEFAULT_CACHE_SIZE,
+            transport,
+        )
+    }
+
+    /// Replaces size limit, in bytes.
+    pub fn with_max_size(mut self, max_size: u64) -> Self {
+        self.max_size = max_size;
+
+        self
+    }
+
+    /// Returns contents of file, downloading it if it is not cached.
+    pub async fn get(&self, url: &str) -> Result<Vec<u8>, MediaError> {
+        let path = self.dir.join(format!("{:016x}", url_hash(url)));
+        match tokio::fs::read(&path).await {
+            Ok(bytes) => return Ok(bytes),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        let bytes = self
+            .transport
+            .download(Request::get(url))
+            .await
+            .map_err(|source| MediaError::Download {
+                url: url.to_owned(),
+                source,
+            })?;
+
+        tokio::fs::create_dir_all(&self.dir).await?;
+        // renamed when complete, so concurrent runs never read a partial file
+        let partial = path.with_extension(format!("{}.part", std::process::id()));
+        tokio::fs::write(&partial, &bytes).await?;
+        tokio::fs::rename(&partial, &path).await?;
+        self.evict().await?;
+
+        Ok(bytes)
+    }
+
+    /// Downloads `media` and checks that Telegram can send it, by the type
+    /// of the file and the upload limits. Returns the media to send instead,
+    /// which differs if the file is of another kind: an image given as video
+    /// is sent as photo, a GIF or a video given as photo is sent as video,
+    /// and a photo over [`MAX_PHOTO_SIZE`] or an audio Telegram can't play
+    /// is sent as document. HTML pages are never sent.
+    pub async fn check(&self, media: &Media) -> Result<Media, InvalidMedia> {
+        let bytes = self.get(media.url()).await?;
+        let size = bytes.len() as u64;
+        let kind = FileType::sniff(&bytes);
+        let url = media.url().to_owned();
+        let document = |url| Media::Document {
+            url,
+            filename: None,
+            mime: None,
+        };
+
+        let checked = match (media, kind) {
+            (_, Some(FileType::Html)) => return Err(InvalidMedia::UnknownType),
+            (Media::Photo(_), Some(FileType::Jpeg | FileType::Png | FileType::Webp))
+                if size > MAX_PHOTO_SIZE =>
+            {
+                document(url)
+            }
+            (
+                Media::Photo(_) | Media::Video(_) | Media::Animation(_),
+                Some(FileType::Jpeg | FileType::Png | FileType::Webp),
+            ) => Media::Photo(url),
+            (Media::Photo(_), Some(FileType::Gif)) => Media::Animation(url),
+            (Media::Photo(_), Some(FileType::Mp4)) => Media::Video(url),
+            (Media::Video(_) | Media::Animation(_), Some(FileType::Mp4 | FileType::Gif)) => {
+                media.clone()
+            }
+            (Media::Photo(_) | Media::Video(_) | Media::Animation(_), Some(_)) => document(url),
+            (Media::Photo(_) | Media::Video(_) | Media::Animation(_), None) => {
+                return Err(InvalidMedia::UnknownType)
+            }
+            (Media::Audio { .. }, Some(FileType::Mp3 | FileType::M4a)) => media.clone(),
+            (Media::Audio { .. }, _) => document(url),
+            (Media::Document { .. }, _) => media.clone(),
+        };
+        let limit = match checked {
+            Media::Photo(_) => MAX_PHOTO_SIZE,
+            _ => MAX_FILE_SIZE,
+        };
+        if size > limit {
+            return Err(InvalidMedia::TooLarge { size, limit });
+        }
+
+        Ok(checked)
+    }
+
+    /// Removes the oldest files until the directory fits the size limit.
+    async fn evict(&self) -> io::Result<()> {
+        let mut files = Vec::new();
+        let mut entries = tokio::fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let meta = entry.metadata().await?;
+            if meta.is_file() {
+                let modified = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                files.push((modified, meta.len(), entry.path()));
+            }
+        }
+        files.sort();
+
+        let mut size: u64 = files.iter().map(|(_, len, _)| len).sum();
+        for (_, len, path) in files {
+            if size <= self.max_size {
+                break;
+            }
+            match tokio::fs::remove_file(&path).await {
+                Ok(()) => size -= len,
+                // removed by a concurrent run
+                Err(e) if e.kind() == io::ErrorKind::NotFound => size -= len,
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(())
+    }
+}
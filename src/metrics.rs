@@ -0,0 +1,128 @@
+//! Counters of a run pushed to a Prometheus Pushgateway when it ends, for
+//! runs started by cron that can't be scraped.
+//!
+//! Every push replaces the metrics of the previous run in the group of the
+//! URL, in the text exposition format:
+//!
+//! ```text
+//! # TYPE secretfop_fetched_posts gauge
+//! secretfop_fetched_posts{source="vk/apiclub"} 5
+//! # TYPE secretfop_skipped_posts gauge
+//! secretfop_skipped_posts{reason="too_old"} 1
+//! # TYPE secretfop_posted_posts gauge
+//! secretfop_posted_posts{chat="@foxart"} 3
+//! # TYPE secretfop_errors gauge
+//! secretfop_errors 0
+//! # TYPE secretfop_downloaded_bytes gauge
+//! secretfop_downloaded_bytes 20500
+//! # TYPE secretfop_uploaded_bytes gauge
+//! secretfop_uploaded_bytes 900
+//! # TYPE secretfop_last_run_timestamp_seconds gauge
+//! secretfop_last_run_timestamp_seconds 1677600004
+//! ```
+
+use std::{collections::BTreeMap, fmt::Write};
+
+use crate::{
+    events::Event,
+    http::{Bandwidth, HttpError, Request, Transport},
+};
+
+/// Counters of a run, collected from its [`Event`]s.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metrics {
+    /// Fetched posts by source, like `vk/apiclub`.
+    pub fetched: BTreeMap<String, usize>,
+    /// New posts rejected by filters by reason, like `too_old`.
+    pub skipped: BTreeMap<String, usize>,
+    /// Mirrored posts by chat.
+    pub posted: BTreeMap<String, usize>,
+    pub errors: usize,
+    /// Traffic of the whole run.
+    pub traffic: Bandwidth,
+}
+
+impl Metrics {
+    /// Counts `event`.
+    pub fn record(&mut self, event: &Event) {
+        match event {
+            Event::Fetch { source, posts } => {
+                *self.fetched.entry(source.clone()).or_default() += posts;
+            }
+            Event::Skip { reason, .. } => {
+                *self.skipped.entry(reason.to_string()).or_default() += 1;
+            }
+            Event::Post { chat, .. } => *self.posted.entry(chat.clone()).or_default() += 1,
+            Event::Error { .. } => self.errors += 1,
+            Event::Traffic {
+                source: None,
+                downloaded,
+                uploaded,
+            } => {
+                self.traffic = Bandwidth {
+                    downloaded: *downloaded,
+                    uploaded: *uploaded,
+                };
+            }
+            Event::Traffic { .. } => {}
+        }
+    }
+
+    /// Metrics in the text exposition format, with the run finished at
+    /// `finished_at`, a unix timestamp.
+    pub fn to_text(&self, finished_at: u64) -> String {
+        let mut text = String::new();
+        let labeled = [
+            ("fetched_posts", "source", &self.fetched),
+            ("skipped_posts", "reason", &self.skipped),
+            ("posted_posts", "chat", &self.posted),
+        ];
+        for (name, label, counts) in labeled {
+            let _ = writeln!(text, "# TYPE secretfop_{name} gauge");
+            for (value, count) in counts {
+                let value = escape_label(value);
+                let _ = writeln!(text, "secretfop_{name}{{{label}=\"{value}\"}} {count}");
+            }
+        }
+        let plain = [
+            ("errors", self.errors as u64),
+            ("downloaded_bytes", self.traffic.downloaded),
+            ("uploaded_bytes", self.traffic.uploaded),
+            ("last_run_timestamp_seconds", finished_at),
+        ];
+        for (name, value) in plain {
+            let _ = writeln!(
+                text,
+                "# TYPE secretfop_{name} gauge\nsecretfop_{name} {value}"
+            );
+        }
+
+        text
+    }
+}
+
+/// Replaces metrics of the group at `url`, like
+/// `http://localhost:9091/metrics/job/secretfop`, with `metrics` finished at
+/// `finished_at`. Pushgateway answers with an empty body on success, so any
+/// other answer is returned as the error.
+pub async fn push(
+    transport: &dyn Transport,
+    url: &str,
+    metrics: &Metrics,
+    finished_at: u64,
+) -> Result<(), HttpError> {
+    let request = Request::put(url).body(metrics.to_text(finished_at));
+    let res = transport.send(request).await?;
+    if !res.trim().is_empty() {
+        return Err(res.trim().into());
+    }
+
+    Ok(())
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,2271 @@
+//! Mirroring pipeline: fetches posts of configured accounts, filters them
+//! and posts new ones to Telegram.
+
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet, VecDeque},
+    ops::RangeInclusive,
+    sync::Arc,
+    time::Duration,
+};
+
+use chrono::{Local, Utc};
+use chrono_tz::Tz;
+use futures::future::join_all;
+use rand::seq::SliceRandom;
+use tokio::time;
+
+#[cfg(feature = "archive")]
+use crate::archive::Archive;
+use crate::{
+    config::{
+        Attribution, CacheRecords, CaptionMode, Config, DeadMedia, Destination, Digest,
+        DigestSelect, HeldPost, OnNewSource, PendingCopy, PostOrder, Posted, ResolvedId, Snowflake,
+        SocialAccount, SourceHealth, MAX_CHANNEL_POSTS, MAX_PENDING_COPIES,
+    },
+    error::{Error, Report},
+    events::{Event, EventLog},
+    fingerprint::{fingerprint, fnv1a, Fingerprint},
+    hooks::{Chain, SkipReason},
+    http::{MeteredTransport, Traffic, Transport},
+    media::MediaCache,
+    metrics::{self, Metrics},
+    sources::{channel, Comment, ForeignMedia, Media, Post, PostStats, Registry, Source},
+    telegram::{
+        self, ProtectedString, RenderedMessage, SendMessage, SentMessages, TelegramClient,
+        TelegramError, TelegramMessage,
+    },
+    text, unix_now,
+};
+
+/// Single run of the mirroring pipeline.
+///
+/// ```no_run
+/// # async fn example(config: secretfop::config::Config) {
+/// use secretfop::{config::CacheRecords, pipeline::Pipeline};
+///
+/// let mut cache = CacheRecords::new();
+/// for e in Pipeline::new(&config).run(&mut cache).await {
+///     eprintln!("{}", secretfop::error::Report(&e));
+/// }
+/// # }
+/// ```
+pub struct Pipeline<'a> {
+    config: &'a Config,
+    populate: bool,
+    transport: Arc<dyn Transport>,
+    sources: Option<Vec<Box<dyn Source>>>,
+    chain: Chain,
+    traffic: Arc<Traffic>,
+}
+
+impl<'a> Pipeline<'a> {
+    pub fn new(config: &'a Config) -> Self {
+        Self {
+            config,
+            populate: false,
+            transport: Arc::new(config.transport()),
+            sources: None,
+            chain: Chain::default()
+                .pipelines(&config.pipelines)
+                .media_blocklist(&config.media_blocklist),
+            traffic: Arc::default(),
+        }
+    }
+
+    /// Replaces HTTP transport of all clients.
+    pub fn transport(mut self, transport: Arc<dyn Transport>) -> Self {
+        self.transport = transport;
+
+        self
+    }
+
+    /// Replaces sources, by default they are created from config with
+    /// [`Registry::default`].
+    pub fn sources(mut self, sources: Vec<Box<dyn Source>>) -> Self {
+        self.sources = Some(sources);
+
+        self
+    }
+
+    /// Replaces transforms and filters applied to fetched posts,
+    /// [`Chain::default`] with `pipelines` of the config by default.
+    pub fn chain(mut self, chain: Chain) -> Self {
+        self.chain = chain;
+
+        self
+    }
+
+    /// Counts traffic of the run in `traffic`, for `max_bandwidth_per_run`
+    /// of the config. Sources given to [`Pipeline::sources`] are counted if
+    /// they were built by a [`Registry::metered`] with the same one.
+    pub fn traffic(mut self, traffic: Arc<Traffic>) -> Self {
+        self.traffic = traffic;
+
+        self
+    }
+
+    /// Only populate cache with fetched posts, but not post them.
+    pub fn populate(mut self, populate: bool) -> Self {
+        self.populate = populate;
+
+        self
+    }
+
+    /// Fetches, filters and posts new posts. Posted (or skipped) posts are
+    /// remembered in `cache`. A failed account or post does not stop the
+    /// run, all such errors are returned. Decisions are appended to
+    /// `event_log` of the config and counters of the run are pushed to
+    /// `pushgateway`, if set.
+    pub async fn run(self, cache: &mut CacheRecords) -> Vec<Error> {
+        let mut errors = Vec::new();
+        let mut log = open_event_log(self.config, &mut errors);
+
+        let (cfg, transport) = (self.config, self.transport.clone());
+        let traffic = self.traffic.clone();
+        errors.extend(self.mirror(cache, &mut log).await);
+        for i in 0..errors.len() {
+            let message = Report(&errors[i]).to_string();
+            log_event(&mut log, Event::Error { message }, &mut errors);
+        }
+        let by_source = traffic.by_source().into_iter().map(|(s, b)| (Some(s), b));
+        for (source, bandwidth) in by_source.chain([(None, traffic.total())]) {
+            let event = Event::Traffic {
+                source,
+                downloaded: bandwidth.downloaded,
+                uploaded: bandwidth.uploaded,
+            };
+            log_event(&mut log, event, &mut errors);
+        }
+        if let Some(url) = &cfg.pushgateway {
+            if let Err(source) = metrics::push(&*transport, url, &log.metrics, unix_now()).await {
+                errors.push(Error::PushMetrics {
+                    url: url.clone(),
+                    source,
+                });
+            }
+        }
+
+        errors
+    }
+
+    async fn mirror(mut self, cache: &mut CacheRecords, log: &mut RunLog) -> Vec<Error> {
+        let cfg = self.config;
+        let mut errors = Vec::new();
+
+        // rate limit of a previous run, posting now would only prolong it
+        if let (false, Some(until)) = (self.populate, cache.flood_until) {
+            let wait = until.saturating_sub(unix_now());
+            if wait > MAX_FLOOD_WAIT {
+                return vec![Error::FloodWait { seconds: wait }];
+            }
+            time::sleep(Duration::from_secs(wait)).await;
+            cache.flood_until = None;
+        }
+
+        let sources = match self.take_sources(&cache.paused, &mut errors).await {
+            Ok(sources) => sources,
+            Err(e) => return vec![e],
+        };
+
+        let transport: Arc<dyn Transport> = Arc::new(MeteredTransport::new(
+            self.transport.clone(),
+            self.traffic.clone(),
+        ));
+        let media_cache =
+            Arc::new(MediaCache::temp(transport.clone()).with_max_size(cfg.media_cache_size));
+        let telegram =
+            TelegramClient::new(cfg.telegram.token.clone(), cfg.telegram.channel.clone())
+                .with_transport(transport)
+                .with_media_cache(media_cache.clone());
+        let comments = cfg.telegram.discussion.is_some()
+            && sources.iter().any(|s| s.account().comments.is_some());
+        // channel posts of `telegram_copy` sources come in updates, so they
+        // are read before fetching
+        let channels: Vec<_> = sources.iter().filter_map(|s| s.channel()).collect();
+        if cfg.dedup || comments || cfg.admin_chat.is_some() || !channels.is_empty() {
+            match read_updates(&telegram, cfg.admin_chat.as_ref(), &channels, cache).await {
+                Ok(replies) => {
+                    for reply in replies {
+                        alert(&telegram, cfg.admin_chat.as_ref(), reply, &mut errors).await;
+                    }
+                }
+                Err(source) => errors.push(Error::Updates(source)),
+            }
+        }
+
+        let Fetched {
+            mut posts,
+            mirrored,
+            skipped,
+            stale,
+            counts,
+        } = self
+            .fetch(&sources, cache, !self.populate, &mut errors)
+            .await;
+        sort_posts(&mut posts, cfg.order);
+        for (source, posts) in counts {
+            log_event(log, Event::Fetch { source, posts }, &mut errors);
+        }
+        for (c, reason) in skipped {
+            let post = c.post.url;
+            log_event(log, Event::Skip { post, reason }, &mut errors);
+        }
+
+        for (account, network, idle) in stale {
+            alert_stale(
+                &telegram,
+                cfg.admin_chat.as_ref(),
+                account,
+                network,
+                idle,
+                &mut errors,
+            )
+            .await;
+        }
+
+        if self.populate {
+            for Candidate { network, post, .. } in &posts {
+                cache.remember(network, post);
+                if cfg.dedup {
+                    cache.remember_fingerprint(fingerprint(post));
+                }
+            }
+        } else {
+            let local_time = cfg.local_time();
+            let mut breaker = Breaker::new(cfg.max_failures);
+            #[cfg(feature = "archive")]
+            let archive = match cfg.archive.as_deref().map(Archive::open).transpose() {
+                Ok(archive) => archive,
+                Err(e) => {
+                    errors.push(Error::OpenArchive(e));
+                    None
+                }
+            };
+            let mut copies = std::mem::take(&mut cache.pending_copies);
+            // rights are checked up front, so a chat the bot can't post to is
+            // reported once instead of failing every post
+            let bot = if posts.is_empty() && copies.is_empty() {
+                None
+            } else {
+                // sending reports a bad token anyway
+                telegram.get_me().await.ok().map(|me| me.id)
+            };
+            let mut targets: Vec<&Snowflake> = Vec::new();
+            for c in &posts {
+                let chat = c.account.channel.as_ref().unwrap_or(&telegram.channel_id);
+                if !targets.contains(&chat) {
+                    targets.push(chat);
+                }
+            }
+            // chats nothing can be posted to this run
+            let mut stopped = match bot {
+                Some(bot) => check_rights(&telegram, bot, &targets, &mut errors).await,
+                None => Vec::new(),
+            };
+
+            for batch in batches(&posts) {
+                let (account, network) = (batch[0].account, batch[0].network);
+                let chat = account.channel.as_ref().unwrap_or(&telegram.channel_id);
+                // will be posted on the first run after quiet hours, or
+                // inside the posting window
+                let closed = cfg
+                    .posting_window(account)
+                    .map(|w| !w.contains(local_time))
+                    .unwrap_or(false);
+                if account.is_quiet(local_time) || closed {
+                    for Candidate { post, .. } in batch {
+                        cache.hold(network, account, post);
+                    }
+                    continue;
+                }
+                if stopped.contains(&chat) {
+                    continue;
+                }
+                if let Some(limit) = cfg.max_bandwidth_per_run {
+                    let used = self.traffic.total().total();
+                    if used >= limit {
+                        errors.push(Error::BandwidthExceeded { used, limit });
+                        break;
+                    }
+                }
+                let scope = format!("{network}/{}", account.id);
+                let checked = check_batch(cfg, &media_cache, cache, &batch, chat, &mut errors);
+                let Some(batch) = Traffic::scope(scope.clone(), checked).await else {
+                    continue;
+                };
+                let first = batch.members[0].0;
+                let res = Traffic::scope(scope, send_batch(&telegram, cache, &batch)).await;
+                let (sent, as_links) = match res {
+                    Ok(sent) => sent,
+                    // limited for long or still limited after waiting, the rest
+                    // waits for next runs
+                    Err(TelegramError::RateLimited { timeout }) => {
+                        cache.flood_until = Some(unix_now() + timeout.as_secs());
+                        errors.push(Error::Post {
+                            post: first.url.clone(),
+                            source: TelegramError::RateLimited { timeout },
+                        });
+                        break;
+                    }
+                    // nothing can be posted there, the rest is retried on next runs
+                    Err(
+                        source
+                        @ (TelegramError::ChatNotFound { .. } | TelegramError::Kicked { .. }),
+                    ) => {
+                        errors.push(Error::Post {
+                            post: first.url.clone(),
+                            source,
+                        });
+                        stopped.push(chat);
+                        continue;
+                    }
+                    // would fail again, so skipped
+                    Err(
+                        source @ (TelegramError::BadMedia { .. } | TelegramError::NothingCopied),
+                    ) => {
+                        for (post, _) in &batch.members {
+                            cache.remember(network, post);
+                        }
+                        errors.push(Error::Post {
+                            post: first.url.clone(),
+                            source,
+                        });
+                        continue;
+                    }
+                    Err(source) => {
+                        errors.push(Error::Post {
+                            post: first.url.clone(),
+                            source,
+                        });
+                        // the rest is retried on next runs
+                        if let Some(e) = breaker.record(chat, false) {
+                            trip(&telegram, cfg.admin_chat.as_ref(), e, &mut errors).await;
+                            stopped.push(chat);
+                        }
+                        continue;
+                    }
+                };
+                breaker.record(chat, true);
+                if as_links {
+                    errors.push(Error::MediaAsLinks {
+                        post: first.url.clone(),
+                        count: batch.media.len(),
+                    });
+                } else if !sent.dropped.is_empty() {
+                    errors.push(Error::DroppedMedia {
+                        post: first.url.clone(),
+                        count: sent.dropped.len(),
+                    });
+                }
+                record_batch(
+                    cfg,
+                    cache,
+                    log,
+                    #[cfg(feature = "archive")]
+                    archive.as_ref(),
+                    &batch,
+                    &sent,
+                    &mut errors,
+                )
+                .await;
+                copies.extend(batch_copies(&batch, &sent));
+                if account.pin {
+                    let message_id = sent.messages[0].message_id;
+                    if let Err(source) = pin(&telegram, cache, account, message_id).await {
+                        errors.push(Error::Pin {
+                            post: first.url.clone(),
+                            source,
+                        });
+                    }
+                }
+            }
+
+            send_copies(&telegram, cfg, &sources, bot, copies, cache, &mut errors).await;
+
+            if cache.flood_until.is_some() {
+                return errors;
+            }
+            refresh_stats(&mirrored, &telegram, cache, &mut errors).await;
+            if let Some(digest) = &cfg.digest {
+                if let Err(e) = post_digest(digest, cfg.timezone, &telegram, cache).await {
+                    errors.push(e);
+                }
+            }
+            if let (true, Some(discussion)) = (comments, &cfg.telegram.discussion) {
+                refresh_comments(&sources, &telegram, discussion, cache, &mut errors).await;
+            }
+        }
+
+        errors
+    }
+
+    /// Fetches and filters posts like [`Pipeline::run`], but posts nothing.
+    /// Returns posts that would be posted, with requests rendered by
+    /// [`SendMessage::render`] for the channel of their account. With `diff`, also returns already
+    /// mirrored posts and new ones rejected by filters, and checks new ones
+    /// against dedup fingerprints and channel history. `cache` should not be saved after it.
+    pub async fn dry_run(
+        mut self,
+        cache: &mut CacheRecords,
+        diff: bool,
+    ) -> (Vec<PlannedPost>, Vec<Error>) {
+        let mut errors = Vec::new();
+        let sources = match self.take_sources(&cache.paused, &mut errors).await {
+            Ok(sources) => sources,
+            Err(e) => return (Vec::new(), vec![e]),
+        };
+        let Fetched {
+            mut posts,
+            mirrored,
+            skipped,
+            ..
+        } = self.fetch(&sources, cache, false, &mut errors).await;
+        sort_posts(&mut posts, self.config.order);
+
+        let telegram = TelegramClient::new(
+            self.config.telegram.token.clone(),
+            self.config.telegram.channel.clone(),
+        )
+        .with_transport(self.transport.clone());
+        if diff {
+            if let Err(source) = read_updates(&telegram, None, &[], cache).await {
+                errors.push(Error::Updates(source));
+            }
+        }
+
+        let render = |c: &Candidate| {
+            let chat = c.account.channel.as_ref();
+            let chat = chat.unwrap_or(&self.config.telegram.channel);
+            let uploaded = cache.uploaded_media(&c.post.media);
+            let render = |summary| {
+                compose(
+                    &telegram, chat, c.account, &c.post, &uploaded, None, summary,
+                )
+                .render()
+            };
+            match render((c.account.caption, c.account.caption_length)) {
+                Err(TelegramError::CaptionTooLong) => {
+                    render((CaptionMode::Truncate, SHORT_CAPTION_LENGTH))
+                }
+                res => res,
+            }
+            .ok()
+        };
+        let planned = |c: Candidate, status| PlannedPost {
+            network: c.network,
+            account: c.account.id.to_string(),
+            message: (status == PlanStatus::New).then(|| render(&c)).flatten(),
+            post: c.post,
+            status,
+        };
+        let mut plan: Vec<_> = posts
+            .into_iter()
+            .map(|c| {
+                let seen = cache.is_seen(&fingerprint(&c.post)) || cache.is_linked(&c.post.url);
+                let status = if diff && seen {
+                    PlanStatus::Duplicate
+                } else {
+                    PlanStatus::New
+                };
+                planned(c, status)
+            })
+            .collect();
+        if diff {
+            plan.extend(
+                mirrored
+                    .into_iter()
+                    .map(|c| planned(c, PlanStatus::Mirrored)),
+            );
+            plan.extend(
+                skipped
+                    .into_iter()
+                    .map(|(c, reason)| planned(c, PlanStatus::Skipped(reason))),
+            );
+        }
+
+        (plan, errors)
+    }
+
+    /// Fetches `post` of a configured `source`, like `vk:apiclub` or
+    /// `vk/apiclub`, and renders it as it would be posted. Only latest posts
+    /// of the source are fetched, so older posts are not found.
+    pub async fn preview(mut self, source: &str, post: &str) -> Result<PostPreview, Error> {
+        let mut errors = Vec::new();
+        let sources = self.take_sources(&[], &mut errors).await?;
+        let key = source.replacen(':', "/", 1);
+        let source = sources
+            .iter()
+            .find(|s| source_key(s.as_ref()) == key)
+            .ok_or(Error::NoSuchSource(key))?;
+
+        let fetched = source
+            .fetch()
+            .await?
+            .into_iter()
+            .find(|p| p.id.to_string() == post)
+            .ok_or_else(|| Error::NoSuchPost {
+                account: source_key(source.as_ref()),
+                post: post.to_owned(),
+            })?;
+        let account = source.account();
+        let (post, accepted) = match self.chain.process(account, fetched.clone()) {
+            Some(post) => (post, true),
+            None => (fetched, false),
+        };
+        let caption = telegram::caption(
+            &post.as_foreign(),
+            account.caption,
+            account.caption_length,
+            Some(account.template(!post.media.is_empty())),
+        );
+
+        Ok(PostPreview {
+            network: source.network(),
+            account: account.id.to_string(),
+            post,
+            accepted,
+            caption,
+        })
+    }
+
+    /// Mirrors posts of a configured `source`, like `vk:apiclub`, with ids in
+    /// `ids`, oldest first, waiting `delay` between posts. The history of the
+    /// source is read page by page until posts older than the range, so old
+    /// ranges take many requests. Watermarks are not moved, so later runs
+    /// mirror new posts as usual. Links to backfilled posts are remembered
+    /// like seeded ones, so backfilling the range again posts only the rest.
+    pub async fn backfill(
+        mut self,
+        cache: &mut CacheRecords,
+        source: &str,
+        ids: RangeInclusive<u64>,
+        delay: Duration,
+    ) -> Vec<Error> {
+        let cfg = self.config;
+        let mut errors = Vec::new();
+        let sources = match self.take_sources(&[], &mut errors).await {
+            Ok(sources) => sources,
+            Err(e) => return vec![e],
+        };
+        let key = source.replacen(':', "/", 1);
+        let Some(source) = sources.iter().find(|s| source_key(s.as_ref()) == key) else {
+            return vec![Error::NoSuchSource(key)];
+        };
+
+        let mut posts = Vec::new();
+        for page in 0.. {
+            let history = match Traffic::scope(key.clone(), source.history(page)).await {
+                Ok(Some(history)) => history,
+                Ok(None) => return vec![Error::NoHistory(key)],
+                Err(e) => {
+                    errors.push(e);
+                    return errors;
+                }
+            };
+            let oldest = match history.first() {
+                Some(post) => post.id.as_ref().as_number(),
+                None => break,
+            };
+            posts.extend(history.into_iter().filter(|p| {
+                p.id.as_ref()
+                    .as_number()
+                    .map(|id| ids.contains(&id))
+                    .unwrap_or(false)
+            }));
+            if oldest.map(|id| id < *ids.start()).unwrap_or(true) {
+                break;
+            }
+        }
+        // pinned posts come on every page
+        posts.sort_by(|a, b| a.id.as_ref().cmp(&b.id.as_ref()));
+        posts.dedup_by(|a, b| a.id == b.id);
+
+        let mut log = open_event_log(cfg, &mut errors);
+        let transport: Arc<dyn Transport> = Arc::new(MeteredTransport::new(
+            self.transport.clone(),
+            self.traffic.clone(),
+        ));
+        let telegram =
+            TelegramClient::new(cfg.telegram.token.clone(), cfg.telegram.channel.clone())
+                .with_transport(transport.clone())
+                .with_media_cache(Arc::new(
+                    MediaCache::temp(transport).with_max_size(cfg.media_cache_size),
+                ));
+        let account = source.account();
+        let chat = account.channel.as_ref().unwrap_or(&telegram.channel_id);
+        let mut posted = false;
+        for mut post in posts {
+            if let Some(reason) = self.chain.check(account, &mut post) {
+                let post = post.url;
+                log_event(&mut log, Event::Skip { post, reason }, &mut errors);
+                continue;
+            }
+            let fingerprint = fingerprint(&post);
+            if cache.is_linked(&post.url)
+                || cache.posted.iter().any(|p| p.url == post.url)
+                || cfg.dedup && cache.is_seen(&fingerprint)
+            {
+                continue;
+            }
+
+            if posted {
+                time::sleep(delay).await;
+            }
+            let res = Traffic::scope(
+                key.clone(),
+                send_post(&telegram, cache, chat, account, &post, &post.media, None),
+            )
+            .await;
+            let (sent, as_links) = match res {
+                Ok(sent) => sent,
+                Err(TelegramError::RateLimited { timeout }) => {
+                    cache.flood_until = Some(unix_now() + timeout.as_secs());
+                    errors.push(Error::Post {
+                        post: post.url,
+                        source: TelegramError::RateLimited { timeout },
+                    });
+                    break;
+                }
+                Err(
+                    source @ (TelegramError::ChatNotFound { .. } | TelegramError::Kicked { .. }),
+                ) => {
+                    errors.push(Error::Post {
+                        post: post.url,
+                        source,
+                    });
+                    break;
+                }
+                Err(source) => {
+                    errors.push(Error::Post {
+                        post: post.url,
+                        source,
+                    });
+                    continue;
+                }
+            };
+            posted = true;
+            if as_links {
+                errors.push(Error::MediaAsLinks {
+                    post: post.url.clone(),
+                    count: post.media.len(),
+                });
+            } else if !sent.dropped.is_empty() {
+                errors.push(Error::DroppedMedia {
+                    post: post.url.clone(),
+                    count: sent.dropped.len(),
+                });
+            }
+            for (i, message) in sent.by_media() {
+                let Some(media) = post.media.get(i) else {
+                    continue;
+                };
+                if let Some(file) = message.file_of(media) {
+                    cache.remember_file_id(media, file.file_id.clone());
+                }
+            }
+
+            let event = Event::Post {
+                post: post.url.clone(),
+                chat: chat.to_string(),
+                message_ids: sent.messages.iter().map(|m| m.message_id).collect(),
+            };
+            log_event(&mut log, event, &mut errors);
+            cache.seed_link(&post.url);
+            if cfg.dedup {
+                cache.remember_fingerprint(fingerprint);
+            }
+        }
+
+        errors
+    }
+
+    /// Builds configured sources and finds more with them. Disabled sources
+    /// and `paused` ones, like `vk/apiclub`, are left out.
+    async fn take_sources(
+        &mut self,
+        paused: &[String],
+        errors: &mut Vec<Error>,
+    ) -> Result<Vec<Box<dyn Source>>, Error> {
+        let mut sources = match self.sources.take() {
+            Some(sources) => sources,
+            None if self.config.ignore_source_errors => {
+                let (sources, skipped) = Registry::default()
+                    .metered(self.traffic.clone())
+                    .build_skipping(self.config, self.transport.clone());
+                errors.extend(skipped);
+
+                sources
+            }
+            None => Registry::default()
+                .metered(self.traffic.clone())
+                .build(self.config, self.transport.clone())?,
+        };
+        sources.retain(|s| s.account().enabled && !paused.contains(&source_key(s.as_ref())));
+        // found sources go after configured ones, so a post of both is
+        // mirrored with settings of the configured account
+        for found in join_all(sources.iter().map(|source| source.discover())).await {
+            match found {
+                Ok(found) => sources.extend(found),
+                Err(e) => errors.push(e),
+            }
+        }
+
+        Ok(sources)
+    }
+
+    /// Fetches posts of `sources` and splits them to new and already mirrored
+    /// ones. Stale sources are only returned if `alert`.
+    async fn fetch<'s>(
+        &self,
+        sources: &'s [Box<dyn Source>],
+        cache: &mut CacheRecords,
+        alert: bool,
+        errors: &mut Vec<Error>,
+    ) -> Fetched<'s> {
+        let cfg = self.config;
+        // posts of `telegram_copy` channels mirrored on previous runs are forgotten
+        let received = std::mem::take(&mut cache.channel_posts);
+        cache.channel_posts = received
+            .into_iter()
+            .filter(|p| cache.is_new(channel::NETWORK, p))
+            .collect();
+        for source in sources {
+            if let Some(chat) = source.channel() {
+                let posts = cache.channel_posts.iter().filter(|p| p.source_id == *chat);
+                source.receive(posts.cloned().collect());
+            }
+        }
+
+        let mut skipped = resolve_ids(sources, cache, errors).await;
+        let now = unix_now();
+        for (i, source) in sources.iter().enumerate() {
+            if let Some(SourceHealth {
+                inaccessible_at: Some(at),
+                ..
+            }) = cache.health.get(&source_key(source.as_ref()))
+            {
+                if now.saturating_sub(*at) < INACCESSIBLE_RETRY {
+                    skipped.insert(i);
+                }
+            }
+            // held posts are posted first, new ones would only wait behind
+            // them, like during long quiet hours of a daemon
+            let held = cache
+                .held_posts
+                .iter()
+                .filter(|h| holds(source.as_ref(), h));
+            if held.count() >= MAX_HELD_PER_SOURCE {
+                skipped.insert(i);
+            }
+        }
+
+        let feeds = join_all(
+            sources
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| !skipped.contains(i))
+                .map(|(_, source)| async move {
+                    let scope = source_key(source.as_ref());
+                    (source, Traffic::scope(scope, source.fetch()).await)
+                }),
+        )
+        .await;
+        let fetched: Vec<_> = feeds
+            .iter()
+            .filter_map(|(source, posts)| Some((source.as_ref(), posts.as_deref().ok()?)))
+            .collect();
+        verify_watermarks(&fetched, cache, errors).await;
+        let mut candidates = Vec::new();
+        let mut stale = Vec::new();
+        let mut counts = Vec::new();
+        for (source, posts) in feeds {
+            match posts {
+                Ok(posts) => {
+                    counts.push((source_key(source.as_ref()), posts.len()));
+                    let stale_after = cfg.stale_after.filter(|_| alert);
+                    if let Some(idle) = check_health(source.as_ref(), &posts, stale_after, cache) {
+                        stale.push((source.account(), source.network(), idle));
+                    }
+                    candidates.extend(posts.into_iter().map(|post| Candidate {
+                        account: source.account(),
+                        network: source.network(),
+                        post,
+                    }));
+                }
+                Err(e) if e.is_inaccessible() => {
+                    let health =
+                        cache
+                            .health
+                            .entry(source_key(source.as_ref()))
+                            .or_insert(SourceHealth {
+                                last_post: now,
+                                alerted: false,
+                                inaccessible_at: None,
+                                verified_at: None,
+                            });
+                    // reported once, until the source can be read again
+                    if health.inaccessible_at.replace(now).is_none() {
+                        errors.push(e);
+                    }
+                }
+                Err(e) => errors.push(e),
+            }
+        }
+        let mut fetched = HashSet::new();
+        candidates.retain(|c| {
+            fetched.insert((
+                c.network,
+                c.post.source_id.to_string(),
+                c.post.id.to_string(),
+            ))
+        });
+
+        // already mirrored posts are kept to update their captions
+        let mut skipped = Vec::new();
+        let (posts, mut mirrored): (Vec<_>, Vec<_>) = candidates
+            .into_iter()
+            .filter_map(|mut c| match self.chain.check(c.account, &mut c.post) {
+                None => Some(c),
+                Some(reason) => {
+                    if cache.is_new(c.network, &c.post) {
+                        skipped.push((c, reason));
+                    }
+                    None
+                }
+            })
+            .partition(|c| cache.is_new(c.network, &c.post));
+        let mut posts = onboard(posts, cache);
+        hold_over(sources, cache, &mut posts, &mut mirrored);
+
+        Fetched {
+            posts,
+            mirrored,
+            skipped,
+            stale,
+            counts,
+        }
+    }
+}
+
+/// Adds posts held back on previous runs to `posts`, instead of their fetched
+/// copies, as their watermark may be passed already. Posts of sources removed
+/// from config are forgotten.
+fn hold_over<'s>(
+    sources: &'s [Box<dyn Source>],
+    cache: &mut CacheRecords,
+    posts: &mut Vec<Candidate<'s>>,
+    mirrored: &mut Vec<Candidate<'s>>,
+) {
+    let source = |h: &HeldPost| sources.iter().find(|s| holds(s.as_ref(), h));
+    cache.held_posts.retain(|h| source(h).is_some());
+    for held in &cache.held_posts {
+        let Some(source) = source(held) else {
+            continue;
+        };
+        let same = |c: &Candidate| {
+            c.network == held.network
+                && c.post.source_id == held.post.source_id
+                && c.post.id == held.post.id
+        };
+        posts.retain(|c| !same(c));
+        mirrored.retain(|c| !same(c));
+        posts.push(Candidate {
+            account: source.account(),
+            network: source.network(),
+            post: held.post.clone(),
+        });
+    }
+}
+
+/// Checks that `held` post comes from `source`.
+fn holds(source: &dyn Source, held: &HeldPost) -> bool {
+    source.network() == held.network && source.account().id.to_string() == held.account
+}
+
+/// Applies [`SocialAccount::on_new_source`] to posts of sources without a
+/// watermark yet: posts that should not be mirrored are remembered instead.
+fn onboard<'s>(posts: Vec<Candidate<'s>>, cache: &mut CacheRecords) -> Vec<Candidate<'s>> {
+    let mut new: HashMap<(&str, String), (OnNewSource, Vec<u64>)> = HashMap::new();
+    for c in &posts {
+        let policy = c.account.on_new_source;
+        if policy != OnNewSource::PostAll && cache.watermark(c.network, &c.post.source_id).is_none()
+        {
+            let (_, ids) = new
+                .entry((c.network, c.post.source_id.to_string()))
+                .or_insert((policy, Vec::new()));
+            ids.extend(c.post.id.as_ref().as_number());
+        }
+    }
+    // the oldest id to post of every new source, none to post nothing
+    let first_posted: HashMap<_, Option<u64>> = new
+        .into_iter()
+        .map(|(key, (policy, mut ids))| {
+            ids.sort_unstable_by_key(|&id| Reverse(id));
+            let first = match policy {
+                OnNewSource::PostLatest(n) if n > 0 => Some(ids.get(n - 1).copied().unwrap_or(0)),
+                _ => None,
+            };
+            (key, first)
+        })
+        .collect();
+
+    let (posts, onboarded): (Vec<_>, Vec<_>) = posts.into_iter().partition(|c| {
+        let key = (c.network, c.post.source_id.to_string());
+        match (first_posted.get(&key), c.post.id.as_ref().as_number()) {
+            (None, _) => true,
+            (Some(Some(first)), Some(id)) => id >= *first,
+            (Some(_), _) => false,
+        }
+    });
+    for c in onboarded {
+        cache.remember(c.network, &c.post);
+    }
+
+    posts
+}
+
+/// Post found by [`Pipeline::dry_run`].
+#[derive(Debug)]
+pub struct PlannedPost {
+    /// Cache namespace of the post source.
+    pub network: &'static str,
+    /// `id` of the configured account.
+    pub account: String,
+    pub post: Post,
+    pub status: PlanStatus,
+    /// Request the post would be sent with, for new posts.
+    pub message: Option<RenderedMessage>,
+}
+
+/// Post rendered by [`Pipeline::preview`].
+#[derive(Debug)]
+pub struct PostPreview {
+    /// Cache namespace of the post source.
+    pub network: &'static str,
+    /// `id` of the configured account.
+    pub account: String,
+    /// Post after transforms, or as fetched if rejected by filters.
+    pub post: Post,
+    /// Whether filters accept the post.
+    pub accepted: bool,
+    /// Caption HTML sent with the post.
+    pub caption: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlanStatus {
+    /// Would be posted.
+    New,
+    /// Same content is already in the channel, skipped with `dedup`.
+    Duplicate,
+    /// Already mirrored.
+    Mirrored,
+    /// Rejected by a filter.
+    Skipped(SkipReason),
+}
+
+/// Posts fetched by [`Pipeline::fetch`].
+struct Fetched<'s> {
+    posts: Vec<Candidate<'s>>,
+    mirrored: Vec<Candidate<'s>>,
+    /// New posts rejected by filters.
+    skipped: Vec<(Candidate<'s>, SkipReason)>,
+    /// Sources without posts for `stale_after`, with seconds since last post.
+    stale: Vec<(&'s SocialAccount, &'static str, u64)>,
+    /// Number of posts fetched from every source, by [`source_key`].
+    counts: Vec<(String, usize)>,
+}
+
+/// Copies mirrored posts to `chat` in order, independently of other chats.
+/// Short rate limits are waited out. Copies left by a longer one or by
+/// [`Breaker`] are returned to retry on next runs.
+async fn copy_lane(
+    telegram: &TelegramClient,
+    chat: &Snowflake,
+    copies: Vec<PendingCopy>,
+    cfg: &Config,
+) -> (Vec<PendingCopy>, Vec<Error>) {
+    let mut errors = Vec::new();
+    let mut breaker = Breaker::new(cfg.max_failures);
+
+    let mut copies = copies.into_iter();
+    while let Some(copy) = copies.next() {
+        let from = copy.from.as_ref().unwrap_or(&telegram.channel_id);
+        let res = telegram.copy_messages(chat, from, &copy.message_ids).await;
+        // messages deleted in the channel are not a failure of the chat
+        let sent = matches!(res, Ok(_) | Err(TelegramError::NothingCopied));
+        let tripped = breaker.record(chat, sent);
+        let source = match (res, &copy.caption) {
+            // the copy is made, so a failed edit is only reported
+            (Ok(ids), Some(caption)) => {
+                let Some(&first) = ids.first() else {
+                    continue;
+                };
+                if let Err(source) = telegram.edit_caption(chat, first, caption).await {
+                    errors.push(Error::Copy {
+                        post: copy.post.clone(),
+                        chat: chat.to_string(),
+                        source,
+                    });
+                }
+                continue;
+            }
+            (Ok(_), None) => continue,
+            (Err(source), _) => source,
+        };
+
+        let limited = matches!(source, TelegramError::RateLimited { .. });
+        errors.push(Error::Copy {
+            post: copy.post.clone(),
+            chat: chat.to_string(),
+            source,
+        });
+        if limited {
+            return ([copy].into_iter().chain(copies).collect(), errors);
+        }
+        if let Some(e) = tripped {
+            trip(telegram, cfg.admin_chat.as_ref(), e, &mut errors).await;
+            return (copies.collect(), errors);
+        }
+    }
+
+    (Vec::new(), errors)
+}
+
+/// Caption of a copy of `post` to a localized `destination`. Batches keep
+/// their `batch` caption with hashtags of the destination.
+fn copy_caption(
+    destination: &Destination,
+    account: &SocialAccount,
+    post: &Post,
+    batch: Option<&str>,
+) -> String {
+    let mut caption = match batch {
+        Some(batch) => batch.to_owned(),
+        None => telegram::caption(
+            &post.as_foreign(),
+            account.caption,
+            account.caption_length,
+            destination
+                .caption_template
+                .as_deref()
+                .or(Some(account.template(!post.media.is_empty()))),
+        ),
+    };
+    let hashtags: Vec<_> = destination
+        .hashtags
+        .iter()
+        .map(|tag| format!("#{}", ProtectedString(tag.trim_start_matches('#'))))
+        .collect();
+    if !hashtags.is_empty() {
+        caption.push_str("\n\n");
+        caption.push_str(&hashtags.join(" "));
+    }
+
+    caption
+}
+
+/// Checks that bot `bot` can post to `chats`, see
+/// [`telegram::TelegramChatMember::can_post`]. Returns chats it can't post to,
+/// reporting them. Chats whose rights fail to be checked are left to
+/// sending, unless they are not found.
+async fn check_rights<'c>(
+    telegram: &TelegramClient,
+    bot: u64,
+    chats: &[&'c Snowflake],
+    errors: &mut Vec<Error>,
+) -> Vec<&'c Snowflake> {
+    let mut refused = Vec::new();
+    for &chat in chats {
+        match telegram.get_chat_member(chat, bot).await {
+            Ok(member) if member.can_post() => {}
+            Ok(member) => {
+                errors.push(Error::CantPost {
+                    chat: chat.to_string(),
+                    status: member.status,
+                });
+                refused.push(chat);
+            }
+            Err(source @ (TelegramError::ChatNotFound { .. } | TelegramError::Kicked { .. })) => {
+                errors.push(Error::CheckRights {
+                    chat: chat.to_string(),
+                    source,
+                });
+                refused.push(chat);
+            }
+            Err(_) => {}
+        }
+    }
+
+    refused
+}
+
+/// Pins a message in the channel of `account` instead of the previously
+/// pinned one.
+async fn pin(
+    telegram: &TelegramClient,
+    cache: &mut CacheRecords,
+    account: &SocialAccount,
+    message_id: u64,
+) -> Result<(), TelegramError> {
+    let chat = account.channel.as_ref().unwrap_or(&telegram.channel_id);
+    telegram.pin_message(chat, message_id).await?;
+    let previous = match &account.channel {
+        Some(chat) => cache.pinned_in.insert(chat.to_string(), message_id),
+        None => cache.pinned.replace(message_id),
+    };
+    if let Some(previous) = previous {
+        telegram.unpin_message(chat, previous).await?;
+    }
+
+    Ok(())
+}
+
+/// Message of `post` to `chat` with `media`, with `caption` instead of the
+/// caption of the post if any, summarized by `(mode, length)` otherwise.
+fn compose<'a, 'b>(
+    telegram: &'a TelegramClient,
+    chat: &'b Snowflake,
+    account: &'b SocialAccount,
+    post: &'b Post,
+    media: &[ForeignMedia<'b>],
+    caption: Option<&str>,
+    (mode, length): (CaptionMode, usize),
+) -> SendMessage<'a, 'b> {
+    let message = telegram
+        .send_message()
+        .chat(chat)
+        .summary(mode, length)
+        .template(Some(account.template(!media.is_empty())))
+        .by_post(post);
+    let message = match account.attribution {
+        Attribution::InlineButton => message.button(&post.source, &post.url),
+        _ => message,
+    };
+    match caption {
+        Some(caption) => message.text(caption.to_owned()),
+        None => message,
+    }
+    .media(media.iter().copied())
+}
+
+/// Checks posts of `batch` before sending them to `chat`. Posts already seen by
+/// `dedup`, left out by `sample_rate` or whose media all failed
+/// [`check_media`] are remembered without posting. Returns [`None`] if no post
+/// is left.
+async fn check_batch<'p>(
+    cfg: &Config,
+    media_cache: &MediaCache,
+    cache: &mut CacheRecords,
+    batch: &[&'p Candidate<'p>],
+    chat: &'p Snowflake,
+    errors: &mut Vec<Error>,
+) -> Option<Batch<'p>> {
+    let (account, network) = (batch[0].account, batch[0].network);
+    let mut members = Vec::new();
+    let mut media: Vec<Media> = Vec::new();
+    for Candidate { post, .. } in batch {
+        let fingerprint = fingerprint(post);
+        let seen = cache.is_seen(&fingerprint) || cache.is_linked(&post.url);
+        if cfg.dedup && seen || !account.sample() {
+            cache.remember(network, post);
+            continue;
+        }
+        if !account.validate_media || !post.messages.is_empty() {
+            media.extend(post.media.iter().cloned());
+            members.push((post, fingerprint));
+            continue;
+        }
+        let valid = check_media(media_cache, cache, post, errors).await;
+        // every media was reported, so not retried
+        if valid.is_empty() && !post.media.is_empty() {
+            cache.remember(network, post);
+            continue;
+        }
+        media.extend(valid);
+        members.push((post, fingerprint));
+    }
+    if members.is_empty() {
+        return None;
+    }
+
+    // batched posts keep one media each, as dropped ones are left out
+    let caption = (members.len() > 1).then(|| {
+        let caption = batch_caption(members.iter().map(|&(post, _)| post));
+        telegram::truncate_html(&caption, telegram::MAX_CAPTION_LENGTH).into_owned()
+    });
+    Some(Batch {
+        account,
+        network,
+        chat,
+        members,
+        media,
+        caption,
+    })
+}
+
+/// Sends `batch` like [`send_post`]. Posts of own channels are copied as is,
+/// without uploading again.
+async fn send_batch(
+    telegram: &TelegramClient,
+    cache: &CacheRecords,
+    batch: &Batch<'_>,
+) -> Result<(SentMessages, bool), TelegramError> {
+    let Batch {
+        account,
+        chat,
+        ref media,
+        ref caption,
+        ..
+    } = *batch;
+    let first = batch.members[0].0;
+    if !first.messages.is_empty() {
+        return telegram
+            .copy_messages(chat, &first.source_id, &first.messages)
+            .await
+            .map(|sent| (SentMessages::copied(sent), false));
+    }
+
+    send_post(
+        telegram,
+        cache,
+        chat,
+        account,
+        first,
+        media,
+        caption.as_deref(),
+    )
+    .await
+}
+
+/// Remembers posts of `batch` sent as `sent` with their uploaded files, and
+/// passes them to `archive` and `exec_sinks`.
+async fn record_batch(
+    cfg: &Config,
+    cache: &mut CacheRecords,
+    log: &mut RunLog,
+    #[cfg(feature = "archive")] archive: Option<&Archive>,
+    batch: &Batch<'_>,
+    sent: &SentMessages,
+    errors: &mut Vec<Error>,
+) {
+    let Batch {
+        account,
+        network,
+        chat,
+        ref media,
+        ..
+    } = *batch;
+    // text posts are a single message too
+    let mut messages = vec![sent.messages[0].message_id; media.len().max(1)];
+    for (i, message) in sent.by_media() {
+        let Some(media) = media.get(i) else {
+            continue;
+        };
+        messages[i] = message.message_id;
+        if let Some(file) = message.file_of(media) {
+            cache.remember_file_id(media, file.file_id.clone());
+        }
+    }
+
+    // batched posts have a single media each
+    let batched = batch.members.len() > 1;
+    for (i, (post, fingerprint)) in batch.members.iter().enumerate() {
+        let message_id = messages.get(i).copied().unwrap_or(messages[0]);
+        let message_ids = if batched {
+            vec![message_id]
+        } else {
+            sent.messages.iter().map(|m| m.message_id).collect()
+        };
+        let event = Event::Post {
+            post: post.url.clone(),
+            chat: chat.to_string(),
+            message_ids,
+        };
+        log_event(log, event, errors);
+        cache.remember(network, post);
+        cache.remember_posted(Posted {
+            network: network.to_string(),
+            account: account.id.to_string(),
+            source_id: post.source_id.clone(),
+            id: post.id.clone(),
+            url: post.url.clone(),
+            posted_at: unix_now(),
+            chat: account.channel.clone(),
+            message_id,
+            discussion_id: None,
+            comments_id: None,
+            comments_hash: None,
+            stats: post.stats,
+            cover: post.media.first().cloned(),
+        });
+        if cfg.dedup {
+            cache.remember_fingerprint(fingerprint.clone());
+        }
+        #[cfg(feature = "archive")]
+        if let Some(archive) = archive {
+            if let Err(source) = archive.record(network, post, chat, message_id, unix_now()) {
+                errors.push(Error::Archive {
+                    post: post.url.clone(),
+                    source,
+                });
+            }
+        }
+
+        // sinks are best-effort, failures are only reported
+        #[cfg(feature = "exec")]
+        for sink in &cfg.exec_sinks {
+            if let Err(source) = sink.send(post).await {
+                errors.push(Error::ExecSink {
+                    post: post.url.clone(),
+                    source,
+                });
+            }
+        }
+    }
+}
+
+/// Copies of `batch` sent as `sent` to `copy_to` chats of its account.
+fn batch_copies(batch: &Batch<'_>, sent: &SentMessages) -> Vec<PendingCopy> {
+    let (account, first) = (batch.account, batch.members[0].0);
+    let ids: Vec<_> = sent.messages.iter().map(|m| m.message_id).collect();
+    account
+        .copy_to
+        .iter()
+        .map(|destination| PendingCopy {
+            chat: destination.chat.clone(),
+            post: first.url.clone(),
+            from: account.channel.clone(),
+            message_ids: ids.clone(),
+            caption: destination
+                .is_localized()
+                .then(|| copy_caption(destination, account, first, batch.caption.as_deref())),
+        })
+        .collect()
+}
+
+/// Makes `copies` in their chats, see [`copy_lane`]. Copies to chats outside
+/// of their posting window, the bot can't post to (checked if `bot` is known)
+/// or left by lanes wait in [`CacheRecords::pending_copies`]. Copies to chats
+/// removed from config are forgotten.
+async fn send_copies(
+    telegram: &TelegramClient,
+    cfg: &Config,
+    sources: &[Box<dyn Source>],
+    bot: Option<u64>,
+    mut copies: Vec<PendingCopy>,
+    cache: &mut CacheRecords,
+    errors: &mut Vec<Error>,
+) {
+    let local_time = cfg.local_time();
+    copies.retain(|copy| {
+        sources
+            .iter()
+            .any(|s| s.account().copy_to.iter().any(|d| d.chat == copy.chat))
+    });
+    let (copies, waiting): (Vec<_>, Vec<_>) = copies.into_iter().partition(|copy| {
+        sources
+            .iter()
+            .flat_map(|s| &s.account().copy_to)
+            .filter(|d| d.chat == copy.chat)
+            .all(|d| {
+                d.posting_window
+                    .map(|w| w.contains(local_time))
+                    .unwrap_or(true)
+            })
+    });
+    cache.pending_copies.extend(waiting);
+    let mut chats: Vec<&Snowflake> = Vec::new();
+    for copy in &copies {
+        if !chats.contains(&&copy.chat) {
+            chats.push(&copy.chat);
+        }
+    }
+    if let Some(bot) = bot {
+        for chat in check_rights(telegram, bot, &chats, errors).await {
+            let refused = copies.iter().filter(|c| c.chat == *chat);
+            cache.pending_copies.extend(refused.cloned());
+            chats.retain(|&c| c != chat);
+        }
+    }
+    let lanes = chats.iter().map(|&chat| {
+        let copies = copies.iter().filter(|c| c.chat == *chat).cloned().collect();
+        copy_lane(telegram, chat, copies, cfg)
+    });
+    for (left, lane_errors) in join_all(lanes).await {
+        cache.pending_copies.extend(left);
+        errors.extend(lane_errors);
+    }
+    if let Some(excess) = cache.pending_copies.len().checked_sub(MAX_PENDING_COPIES) {
+        cache.pending_copies.drain(..excess);
+    }
+}
+
+/// Sends `media` of `post` to `chat`, with `caption` instead of the caption
+/// of the post if set. Files uploaded before are sent by id. Returns also
+/// whether media were sent as links, see [`DeadMedia::Links`].
+async fn send_post(
+    telegram: &TelegramClient,
+    cache: &CacheRecords,
+    chat: &Snowflake,
+    account: &SocialAccount,
+    post: &Post,
+    media: &[Media],
+    caption: Option<&str>,
+) -> Result<(SentMessages, bool), TelegramError> {
+    let uploaded = cache.uploaded_media(media);
+    let send = |mode, length, links| {
+        let message = compose(
+            telegram,
+            chat,
+            account,
+            post,
+            &uploaded,
+            caption,
+            (mode, length),
+        );
+        if links {
+            message.media_as_links().send_messages()
+        } else {
+            message.send_messages()
+        }
+    };
+    let (mut mode, mut length) = (account.caption, account.caption_length);
+    let mut res = send(mode, length, false).await;
+    if let Err(TelegramError::CaptionTooLong) = res {
+        // counted by Telegram after parsing, so shorten the text more
+        (mode, length) = (CaptionMode::Truncate, SHORT_CAPTION_LENGTH);
+        res = send(mode, length, false).await;
+    }
+    if let (Err(TelegramError::BadMedia { .. }), DeadMedia::Links) = (&res, account.dead_media) {
+        return send(mode, length, true).await.map(|sent| (sent, true));
+    }
+
+    res.map(|sent| (sent, false))
+}
+
+/// Events of a run, appended to the event log and counted for metrics.
+#[derive(Default)]
+struct RunLog {
+    file: Option<EventLog>,
+    metrics: Metrics,
+}
+
+/// Media of `post` that Telegram can send, as checked by
+/// [`MediaCache::check`]. Media uploaded before is not checked again, but
+/// sent as the kind it was uploaded as. Dropped media are reported to
+/// `errors`.
+async fn check_media(
+    media_cache: &MediaCache,
+    cache: &CacheRecords,
+    post: &Post,
+    errors: &mut Vec<Error>,
+) -> Vec<Media> {
+    let mut checked = Vec::new();
+    for m in &post.media {
+        let url = m.url().to_owned();
+        // kinds the media may be checked to be
+        let kinds = [
+            m.clone(),
+            Media::Photo(url.clone()),
+            Media::Video(url.clone()),
+            Media::Document {
+                url,
+                filename: None,
+                mime: None,
+            },
+        ];
+        if let Some(uploaded) = kinds.into_iter().find(|m| cache.file_id(m).is_some()) {
+            checked.push(uploaded);
+            continue;
+        }
+        match media_cache.check(m).await {
+            Ok(m) => checked.push(m),
+            Err(source) => errors.push(Error::InvalidMedia {
+                post: post.url.clone(),
+                url: m.url().to_owned(),
+                source,
+            }),
+        }
+    }
+
+    checked
+}
+
+/// Opens `event_log` of the config, if set.
+fn open_event_log(config: &Config, errors: &mut Vec<Error>) -> RunLog {
+    let Some(path) = &config.event_log else {
+        return RunLog::default();
+    };
+    match EventLog::open(path) {
+        Ok(file) => RunLog {
+            file: Some(file),
+            metrics: Metrics::default(),
+        },
+        Err(source) => {
+            errors.push(Error::OpenEventLog {
+                path: path.clone(),
+                source,
+            });
+            RunLog::default()
+        }
+    }
+}
+
+/// Counts `event` and appends it to the event log, if there is one. The log
+/// is closed on the first failure, so it is reported once.
+fn log_event(log: &mut RunLog, event: Event, errors: &mut Vec<Error>) {
+    log.metrics.record(&event);
+    let Some(opened) = &mut log.file else {
+        return;
+    };
+    if let Err(source) = opened.record(&event) {
+        errors.push(Error::EventLog(source));
+        log.file = None;
+    }
+}
+
+/// Cache key of a configured source, like `vk/apiclub`.
+fn source_key(source: &dyn Source) -> String {
+    format!("{}/{}", source.network(), source.account().id)
+}
+
+/// Resolves alias ids of sources, reusing ids resolved less than
+/// [`RESOLVE_PERIOD`] ago. If resolving fails, the last known id is used.
+/// Returns indexes of sources without an id.
+async fn resolve_ids(
+    sources: &[Box<dyn Source>],
+    cache: &mut CacheRecords,
+    errors: &mut Vec<Error>,
+) -> HashSet<usize> {
+    let now = unix_now();
+
+    let mut pending = Vec::new();
+    for (i, source) in sources.iter().enumerate() {
+        match cache.resolved.get(&source_key(source.as_ref())) {
+            Some(known) if now.saturating_sub(known.resolved_at) < RESOLVE_PERIOD => {
+                source.set_resolved(known.id)
+            }
+            _ => pending.push((i, source)),
+        }
+    }
+    let results = join_all(pending.iter().map(|(_, source)| source.resolve())).await;
+
+    let mut unresolved = HashSet::new();
+    for ((i, source), res) in pending.into_iter().zip(results) {
+        let key = source_key(source.as_ref());
+        let known = cache.resolved.get(&key).map(|r| r.id);
+        match (res, known) {
+            (Ok(None), _) => {}
+            (Ok(Some(id)), _) => {
+                if let Some(old) = known.filter(|&old| old != id) {
+                    errors.push(Error::IdChanged {
+                        account: source.account().id.to_string(),
+                        old,
+                        new: id,
+                    });
+                }
+                source.set_resolved(id);
+                cache.resolved.insert(
+                    key,
+                    ResolvedId {
+                        id,
+                        resolved_at: now,
+                    },
+                );
+            }
+            (Err(e), Some(old)) => {
+                errors.push(e);
+                source.set_resolved(old);
+            }
+            (Err(e), None) => {
+                errors.push(e);
+                unresolved.insert(i);
+            }
+        }
+    }
+
+    unresolved
+}
+
+/// Checks watermarks of sources that are ahead of their newest fetched post,
+/// once per [`VERIFY_PERIOD`]. If the watermark post is gone, like a deleted
+/// pinned post, the watermark is moved back to the newest post, so new posts
+/// below it are not taken for mirrored ones.
+async fn verify_watermarks(
+    feeds: &[(&dyn Source, &[Post])],
+    cache: &mut CacheRecords,
+    errors: &mut Vec<Error>,
+) {
+    let now = unix_now();
+
+    let mut pending = Vec::new();
+    for &(source, posts) in feeds {
+        let Some((newest, source_id)) = posts
+            .iter()
+            .filter_map(|p| Some((p.id.as_ref().as_number()?, &p.source_id)))
+            .max_by_key(|&(id, _)| id)
+        else {
+            continue;
+        };
+        if let Some(SourceHealth {
+            verified_at: Some(at),
+            ..
+        }) = cache.health.get(&source_key(source))
+        {
+            if now.saturating_sub(*at) < VERIFY_PERIOD {
+                continue;
+            }
+        }
+        let watermark = cache.watermark(source.network(), source_id);
+        match watermark.and_then(|w| w.as_ref().as_number()) {
+            Some(watermark) if watermark > newest => {
+                pending.push((source, source_id, watermark, newest))
+            }
+            _ => {}
+        }
+    }
+    let results = join_all(
+        pending
+            .iter()
+            .map(|(source, source_id, watermark, _)| source.exists(source_id, *watermark)),
+    )
+    .await;
+
+    for ((source, source_id, watermark, newest), res) in pending.into_iter().zip(results) {
+        match res {
+            Ok(Some(false)) => {
+                cache.set_watermark(source.network(), source_id, newest);
+                errors.push(Error::WatermarkRepaired {
+                    account: source.account().id.to_string(),
+                    old: watermark,
+                    new: newest,
+                });
+            }
+            Ok(_) => {}
+            // checked again on next run
+            Err(e) => {
+                errors.push(e);
+                continue;
+            }
+        }
+        if let Some(health) = cache.health.get_mut(&source_key(source)) {
+            health.verified_at = Some(now);
+        }
+    }
+}
+
+/// Updates the newest post date of a source. Returns seconds since it if the
+/// source became stale after `stale_after` seconds, once until a new post.
+fn check_health(
+    source: &dyn Source,
+    posts: &[Post],
+    stale_after: Option<u64>,
+    cache: &mut CacheRecords,
+) -> Option<u64> {
+    let now = unix_now();
+    let newest = posts.iter().map(|p| p.date).max();
+    let health = cache
+        .health
+        .entry(source_key(source))
+        .or_insert(SourceHealth {
+            last_post: newest.unwrap_or(now),
+            alerted: false,
+            inaccessible_at: None,
+            verified_at: None,
+        });
+    health.inaccessible_at = None;
+    if let Some(newest) = newest.filter(|&d| d > health.last_post) {
+        health.last_post = newest;
+        health.alerted = false;
+    }
+
+    let idle = now.saturating_sub(health.last_post);
+    if health.alerted || idle <= stale_after? {
+        return None;
+    }
+    health.alerted = true;
+
+    Some(idle)
+}
+
+/// Reports a stale source, to the admin chat if there is one.
+async fn alert_stale(
+    telegram: &TelegramClient,
+    admin_chat: Option<&Snowflake>,
+    account: &SocialAccount,
+    network: &str,
+    idle: u64,
+    errors: &mut Vec<Error>,
+) {
+    let name = match &account.name {
+        Some(name) => format!("{network} account {name}"),
+        None => format!("{network} account {}", account.id),
+    };
+    let days = idle / (24 * 60 * 60);
+    let text = format!(
+        "⚠️ No new posts from {} for {days} days, it may be renamed, closed or banned",
+        ProtectedString(&name)
+    );
+    alert(telegram, admin_chat, text, errors).await;
+
+    errors.push(Error::StaleSource {
+        account: name,
+        days,
+    });
+}
+
+/// Sends HTML `text` to the admin chat, if there is one.
+async fn alert(
+    telegram: &TelegramClient,
+    admin_chat: Option<&Snowflake>,
+    text: String,
+    errors: &mut Vec<Error>,
+) {
+    let Some(chat) = admin_chat else {
+        return;
+    };
+    if let Err(source) = telegram.send_message().chat(chat).text(text).send().await {
+        errors.push(Error::Alert(source));
+    }
+}
+
+/// Counts consecutive failures of chats, to stop sending to a chat that
+/// keeps failing.
+struct Breaker {
+    limit: usize,
+    failures: HashMap<String, usize>,
+}
+
+impl Breaker {
+    fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            failures: HashMap::new(),
+        }
+    }
+
+    /// Records result of sending to `chat`. Returns [`Error::Breaker`] if
+    /// sending to it is stopped by this failure.
+    fn record(&mut self, chat: &Snowflake, ok: bool) -> Option<Error> {
+        let failures = self.failures.entry(chat.to_string()).or_default();
+        if ok {
+            *failures = 0;
+            return None;
+        }
+        *failures += 1;
+
+        (*failures >= self.limit).then(|| Error::Breaker {
+            chat: chat.to_string(),
+            failures: *failures,
+        })
+    }
+}
+
+/// Stops sending to a chat after `error` of [`Breaker::record`], reporting
+/// it to the admin chat.
+async fn trip(
+    telegram: &TelegramClient,
+    admin_chat: Option<&Snowflake>,
+    error: Error,
+    errors: &mut Vec<Error>,
+) {
+    let text = format!("⚠️ {}", ProtectedString(&error.to_string()));
+    alert(telegram, admin_chat, text, errors).await;
+
+    errors.push(error);
+}
+
+/// Orders posts, that come source after source, by `order` of the config.
+/// Posts of accounts with higher `priority` go first in any order.
+fn sort_posts(posts: &mut Vec<Candidate<'_>>, order: PostOrder) {
+    match order {
+        PostOrder::Source => {}
+        // stable, so posts of the same date keep source order
+        PostOrder::Date => posts.sort_by_key(|c| c.post.date),
+        PostOrder::Weighted => *posts = interleave(std::mem::take(posts)),
+    }
+    posts.sort_by_key(|c| Reverse(c.account.priority));
+}
+
+/// Interleaves posts of different accounts by smooth weighted round-robin,
+/// so an account with `weight: 3` gets 3 posts for every post of an account
+/// with `weight: 1`, spread evenly.
+fn interleave(posts: Vec<Candidate<'_>>) -> Vec<Candidate<'_>> {
+    // posts of account, its weight and current priority
+    let mut queues: Vec<(VecDeque<Candidate>, i64, i64)> = Vec::new();
+    let mut res = Vec::with_capacity(posts.len());
+    for candidate in posts {
+        match queues
+            .iter_mut()
+            .find(|(q, ..)| std::ptr::eq(q[0].account, candidate.account))
+        {
+            Some((queue, ..)) => queue.push_back(candidate),
+            None => {
+                let weight = i64::from(candidate.account.weight.max(1));
+                queues.push((VecDeque::from([candidate]), weight, 0));
+            }
+        }
+    }
+
+    loop {
+        let mut active: Vec<_> = queues.iter_mut().filter(|(q, ..)| !q.is_empty()).collect();
+        let total: i64 = active.iter().map(|(_, weight, _)| weight).sum();
+        for (_, weight, current) in active.iter_mut() {
+            *current += *weight;
+        }
+        // the first account wins ties
+        let Some((queue, _, current)) = active.into_iter().rev().max_by_key(|(.., c)| *c) else {
+            break;
+        };
+        *current -= total;
+        res.extend(queue.pop_front());
+    }
+
+    res
+}
+
+/// Groups posts to send together. Single-photo posts of a `batch` account
+/// are sent as albums of up to 10 posts, other posts alone.
+fn batches<'p, 'a>(posts: &'p [Candidate<'a>]) -> Vec<Vec<&'p Candidate<'a>>> {
+    let batchable = |c: &Candidate| {
+        c.account.batch
+            && c.post.messages.is_empty()
+            && matches!(&c.post.media[..], [Media::Photo(_)])
+    };
+
+    let mut batches: Vec<Vec<&Candidate>> = Vec::new();
+    for candidate in posts {
+        let open = batchable(candidate)
+            .then(|| {
+                batches.iter_mut().find(|b| {
+                    b.len() < 10
+                        && batchable(b[0])
+                        && std::ptr::eq(b[0].account, candidate.account)
+                        && b[0].post.source_id == candidate.post.source_id
+                })
+            })
+            .flatten();
+        match open {
+            Some(batch) => batch.push(candidate),
+            None => batches.push(vec![candidate]),
+        }
+    }
+
+    batches
+}
+
+/// Caption of a batch album, listing links to the original posts.
+fn batch_caption<'p>(posts: impl Iterator<Item = &'p Post>) -> String {
+    let mut source = String::new();
+    let links: Vec<_> = posts
+        .enumerate()
+        .map(|(i, post)| {
+            source = ProtectedString(&post.source).to_string();
+            let title = match post.text.trim() {
+                "" => format!("post {}", i + 1),
+                text => text::summarize(text, CaptionMode::FirstSentence, 0)
+                    .unwrap_or(text)
+                    .to_owned(),
+            };
+
+            format!(
+                "{}. <a href=\"{}\">{}</a>",
+                i + 1,
+                post.url,
+                ProtectedString(&title)
+            )
+        })
+        .collect();
+
+    format!("{}\n\nsrc: {source}", links.join("\n"))
+}
+
+/// Reads new Telegram updates. Posts of `channels` are kept for their
+/// `telegram_copy` sources, other channel posts not made by the bot are
+/// seeded to dedup, automatic forwards to the discussion group give ids of
+/// mirrored posts there, needed to reply with comments. Returns replies to
+/// commands of the admin chat.
+async fn read_updates(
+    telegram: &TelegramClient,
+    admin_chat: Option<&Snowflake>,
+    channels: &[&Snowflake],
+    cache: &mut CacheRecords,
+) -> Result<Vec<String>, TelegramError> {
+    let updates = telegram.get_updates(cache.telegram_offset).await?;
+
+    let mut replies = Vec::new();
+    for message in updates.iter().filter_map(|u| u.message.as_ref()) {
+        let (Some(admin_chat), Some(chat), Some(text)) = (admin_chat, &message.chat, &message.text)
+        else {
+            continue;
+        };
+        if chat.is(admin_chat) {
+            replies.extend(admin_command(cache, text));
+        }
+    }
+
+    let mut received: Vec<Vec<&TelegramMessage>> = vec![Vec::new(); channels.len()];
+    for post in updates.iter().filter_map(|u| u.channel_post.as_ref()) {
+        let from = post
+            .chat
+            .as_ref()
+            .and_then(|chat| channels.iter().position(|&c| chat.is(c)));
+        // ids of messages are only unique in their chat
+        let ours = |p: &Posted| {
+            let chat = p.chat.as_ref().unwrap_or(&telegram.channel_id);
+            p.message_id == post.message_id
+                && post.chat.as_ref().map(|c| c.is(chat)).unwrap_or(true)
+        };
+        match from {
+            Some(i) => received[i].push(post),
+            None if !cache.posted.iter().any(ours) => cache.seed(post.content()),
+            None => {}
+        }
+    }
+    for (channel, messages) in channels.iter().zip(received) {
+        cache
+            .channel_posts
+            .extend(channel::channel_posts(channel, &messages));
+    }
+    if let Some(excess) = cache.channel_posts.len().checked_sub(MAX_CHANNEL_POSTS) {
+        cache.channel_posts.drain(..excess);
+    }
+    for message in updates.iter().filter_map(|u| u.message.as_ref()) {
+        let Some(channel_id) = message
+            .forward_origin
+            .as_ref()
+            .and_then(|o| o.message_id)
+            .filter(|_| message.is_automatic_forward)
+        else {
+            continue;
+        };
+        // the discussion group belongs to the main channel
+        let posted = cache.posted.iter_mut();
+        if let Some(posted) = posted
+            .filter(|p| p.chat.is_none())
+            .find(|p| p.message_id == channel_id)
+        {
+            posted.discussion_id = Some(message.message_id);
+        }
+    }
+    if let Some(last) = updates.last() {
+        cache.telegram_offset = Some(last.update_id + 1);
+    }
+
+    Ok(replies)
+}
+
+/// Applies `/pause <source>` or `/resume <source>` sent to the admin chat,
+/// from the next run. Returns a reply, or [`None`] for other messages.
+fn admin_command(cache: &mut CacheRecords, text: &str) -> Option<String> {
+    let mut words = text.split_whitespace();
+    // commands in groups may be addressed to the bot, like /pause@foxbot
+    let command = words.next()?.split('@').next()?;
+    let source = words.next().map(|s| s.replacen(':', "/", 1));
+    let reply = match (command, source) {
+        ("/pause", Some(source)) if cache.pause(&source) => {
+            format!("⏸ {} is paused", ProtectedString(&source))
+        }
+        ("/pause", Some(source)) => format!("{} is already paused", ProtectedString(&source)),
+        ("/resume", Some(source)) if cache.resume(&source) => {
+            format!("▶️ {} is resumed", ProtectedString(&source))
+        }
+        ("/resume", Some(source)) => format!("{} is not paused", ProtectedString(&source)),
+        ("/pause" | "/resume", None) => format!("Usage: {command} vk/apiclub"),
+        _ => return None,
+    };
+
+    Some(reply)
+}
+
+/// Posts top comments of recently mirrored posts as replies in the
+/// discussion group, or updates them if comments changed.
+async fn refresh_comments(
+    sources: &[Box<dyn Source>],
+    telegram: &TelegramClient,
+    discussion: &Snowflake,
+    cache: &mut CacheRecords,
+    errors: &mut Vec<Error>,
+) {
+    let now = unix_now();
+    for posted in &mut cache.posted {
+        let Some(discussion_id) = posted.discussion_id else {
+            continue;
+        };
+        if now.saturating_sub(posted.posted_at) > COMMENTS_REFRESH {
+            continue;
+        }
+        let Some((source, limit)) = sources
+            .iter()
+            .filter(|s| s.network() == posted.network)
+            .find(|s| s.account().id.to_string() == posted.account)
+            .and_then(|s| Some((s, s.account().comments?)))
+        else {
+            continue;
+        };
+
+        let comments = match source.comments(&posted.source_id, &posted.id, limit).await {
+            Ok(comments) if comments.is_empty() => continue,
+            Ok(comments) => comments,
+            Err(e) => {
+                errors.push(e);
+                continue;
+            }
+        };
+        let text = comments_text(&comments);
+        let hash = fnv1a(text.as_bytes());
+        if posted.comments_hash == Some(hash) {
+            continue;
+        }
+
+        let res = match posted.comments_id {
+            Some(id) => telegram.edit_text(discussion, id, &text).await.map(|_| id),
+            None => {
+                telegram
+                    .send_message()
+                    .chat(discussion)
+                    .text(text)
+                    .reply_to(discussion_id)
+                    .silent(true)
+                    .send()
+                    .await
+            }
+        };
+        match res {
+            Ok(id) => {
+                posted.comments_id = Some(id);
+                posted.comments_hash = Some(hash);
+            }
+            Err(source) => errors.push(Error::PostComments {
+                post: posted.url.clone(),
+                source,
+            }),
+        }
+    }
+}
+
+/// Updates captions of mirrored posts whose counters shown in the caption
+/// changed significantly, see [`stats_changed`].
+async fn refresh_stats(
+    mirrored: &[Candidate<'_>],
+    telegram: &TelegramClient,
+    cache: &mut CacheRecords,
+    errors: &mut Vec<Error>,
+) {
+    for Candidate {
+        account,
+        network,
+        post,
+    } in mirrored
+    {
+        let Some(stats) = post.stats else {
+            continue;
+        };
+        let Some(posted) = cache
+            .posted
+            .iter_mut()
+            .find(|p| p.network == *network && p.source_id == post.source_id && p.id == post.id)
+        else {
+            continue;
+        };
+        // captions of batched posts are shared by the album
+        let batched = account.batch && matches!(&post.media[..], [Media::Photo(_)]);
+        let Some(template) = account
+            .caption_template
+            .as_deref()
+            .filter(|t| !batched && STAT_PLACEHOLDERS.iter().any(|p| t.contains(p)))
+        else {
+            // nothing to edit, but digest ranks posts by latest counters
+            posted.stats = Some(stats);
+            continue;
+        };
+        if matches!(posted.stats, Some(old) if !stats_changed(&old, &stats)) {
+            continue;
+        }
+
+        let caption = telegram::caption(
+            &post.as_foreign(),
+            account.caption,
+            account.caption_length,
+            Some(template),
+        );
+        match telegram
+            .edit_caption(
+                posted.chat.as_ref().unwrap_or(&telegram.channel_id),
+                posted.message_id,
+                &caption,
+            )
+            .await
+        {
+            Ok(()) => posted.stats = Some(stats),
+            Err(source) => errors.push(Error::EditCaption {
+                post: post.url.clone(),
+                source,
+            }),
+        }
+    }
+}
+
+/// Default caption of digest album.
+pub const DIGEST_TEMPLATE: &str = "🏆 <b>Best of the week</b>\n\n{posts}";
+
+/// Posts digest album if its time has come since the last one.
+async fn post_digest(
+    digest: &Digest,
+    timezone: Option<Tz>,
+    telegram: &TelegramClient,
+    cache: &mut CacheRecords,
+) -> Result<(), Error> {
+    let due = match timezone {
+        Some(tz) => digest
+            .at
+            .last_before(Utc::now().with_timezone(&tz))
+            .map(|d| d.timestamp()),
+        None => digest.at.last_before(Local::now()).map(|d| d.timestamp()),
+    };
+    let Some(due) = due else {
+        return Ok(());
+    };
+    let due = u64::try_from(due).unwrap_or_default();
+    if matches!(cache.digest_at, Some(at) if at >= due) {
+        return Ok(());
+    }
+
+    let now = unix_now();
+    let mut posts: Vec<_> = cache
+        .posted
+        .iter()
+        .filter(|p| p.posted_at >= now.saturating_sub(DIGEST_PERIOD))
+        // audio and documents can't be in an album with photos
+        .filter(|p| {
+            matches!(
+                p.cover,
+                Some(Media::Photo(_) | Media::Video(_) | Media::Animation(_))
+            )
+        })
+        // posts of other channels are not in this one
+        .filter(|p| p.chat.is_none())
+        .collect();
+    match digest.select {
+        // stable, so equally popular posts stay in posting order
+        DigestSelect::Top => posts.sort_by_key(|p| {
+            Reverse(
+                p.stats
+                    .map(|s| s.likes + s.reposts + s.comments)
+                    .unwrap_or_default(),
+            )
+        }),
+        DigestSelect::Random => posts.shuffle(&mut rand::thread_rng()),
+    }
+    // album holds up to 10 media
+    posts.truncate(digest.count.clamp(1, 10));
+
+    if !posts.is_empty() {
+        let links = posts
+            .iter()
+            .enumerate()
+            .map(|(i, p)| format!("<a href=\"{}\">{}</a>", p.url, i + 1))
+            .collect::<Vec<_>>()
+            .join(" · ");
+        let caption = text::render(
+            digest.template.as_deref().unwrap_or(DIGEST_TEMPLATE),
+            &[("posts", &links), ("count", &posts.len().to_string())],
+        );
+
+        let covers: Vec<Media> = posts.iter().filter_map(|p| p.cover.clone()).collect();
+        telegram
+            .send_message()
+            .text(caption)
+            .media(cache.uploaded_media(&covers))
+            .send()
+            .await
+            .map_err(Error::Digest)?;
+    }
+    // weeks without posts are skipped too
+    cache.digest_at = Some(now);
+
+    Ok(())
+}
+
+/// Period covered by digest, in seconds.
+const DIGEST_PERIOD: u64 = 7 * 24 * 60 * 60;
+
+/// Placeholders of caption template that show post counters.
+const STAT_PLACEHOLDERS: [&str; 4] = ["{likes}", "{reposts}", "{views}", "{comments}"];
+
+/// Checks that some counter changed by at least 10% and at least by 10.
+fn stats_changed(old: &PostStats, new: &PostStats) -> bool {
+    let counters = |s: &PostStats| [s.likes, s.reposts, s.views, s.comments];
+
+    counters(old)
+        .into_iter()
+        .zip(counters(new))
+        .any(|(old, new)| old.abs_diff(new) >= (old / 10).max(10))
+}
+
+/// Longest rate limit of a previous run to wait out, in seconds. Runs
+/// with longer limits end with [`Error::FloodWait`].
+const MAX_FLOOD_WAIT: u64 = 60;
+
+/// How long comments of a mirrored post are refreshed, in seconds.
+const COMMENTS_REFRESH: u64 = 24 * 60 * 60;
+
+/// Caption length of posts whose caption Telegram found too long, in UTF-16
+/// code units.
+const SHORT_CAPTION_LENGTH: usize = 700;
+
+/// How often inaccessible sources are fetched, in seconds.
+const INACCESSIBLE_RETRY: u64 = 24 * 60 * 60;
+
+/// Number of held posts of a source, see [`CacheRecords::held_posts`], above
+/// which it is not fetched until they are posted.
+const MAX_HELD_PER_SOURCE: usize = 50;
+
+/// How often watermarks of sources are verified, in seconds.
+const VERIFY_PERIOD: u64 = 24 * 60 * 60;
+
+/// How often aliases of accounts are resolved again, in seconds.
+const RESOLVE_PERIOD: u64 = 24 * 60 * 60;
+
+/// Telegram HTML of top comments reply.
+fn comments_text(comments: &[Comment]) -> String {
+    let mut text = "💬 <b>Top comments</b>".to_owned();
+    for c in comments {
+        let body = text::summarize(&c.text, CaptionMode::Truncate, COMMENT_LENGTH)
+            .map(|s| format!("{s}…"));
+        let body = body.as_deref().unwrap_or(&c.text);
+        text += &format!(
+            "\n\n<b>{}</b>: {}",
+            ProtectedString(&c.author),
+            ProtectedString(body)
+        );
+    }
+
+    text
+}
+
+/// Maximum length of a comment in top comments reply.
+const COMMENT_LENGTH: usize = 300;
+
+/// Fetched post waiting to be filtered and posted.
+struct Candidate<'a> {
+    account: &'a SocialAccount,
+    /// Cache namespace of the post source.
+    network: &'static str,
+    post: Post,
+}
+
+/// Posts of one of [`batches`] left by [`check_batch`], sent as one message.
+struct Batch<'p> {
+    account: &'p SocialAccount,
+    network: &'static str,
+    chat: &'p Snowflake,
+    /// Posts with their fingerprints, not empty.
+    members: Vec<(&'p Post, Fingerprint)>,
+    /// Media of all posts, in order.
+    media: Vec<Media>,
+    /// Caption listing the posts, if there are several.
+    caption: Option<String>,
+}
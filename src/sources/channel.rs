@@ -0,0 +1,136 @@
+//! Posts of the operator's own Telegram channels, copied to the channel of
+//! the bot with `copyMessages`, so media are not uploaded again.
+//!
+//! The Bot API can't read channel history, so posts come from updates: the
+//! bot should be an admin of the channel. New posts are kept in
+//! [`CacheRecords::channel_posts`] until they are mirrored.
+//!
+//! [`CacheRecords::channel_posts`]: crate::config::CacheRecords::channel_posts
+
+use std::sync::Mutex;
+
+use futures::future::BoxFuture;
+
+use crate::{
+    config::{Snowflake, SocialAccount},
+    error,
+    telegram::TelegramMessage,
+};
+
+use super::{Context, Media, Post, Source};
+
+/// Cache namespace of channel posts.
+pub const NETWORK: &str = "telegram";
+
+/// Telegram channel as a [`Source`], `id` of the account is the channel, like
+/// `@foxart_archive` or `"-1001234567890"`. Posts are copied with their
+/// captions, so `caption` and `attribution` settings do not apply.
+pub struct ChannelSource {
+    account: SocialAccount,
+    /// Posts received since the last run that were not mirrored yet.
+    inbox: Mutex<Vec<Post>>,
+}
+
+impl ChannelSource {
+    pub fn new(account: SocialAccount) -> Self {
+        Self {
+            account,
+            inbox: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// [`super::Constructor`] of `telegram_copy` source type, takes account
+    /// fields.
+    pub fn construct(
+        _ctx: &Context,
+        params: serde_yaml::Value,
+    ) -> Result<Box<dyn Source>, serde_yaml::Error> {
+        Ok(Box::new(Self::new(serde_yaml::from_value(params)?)))
+    }
+}
+
+impl Source for ChannelSource {
+    fn account(&self) -> &SocialAccount {
+        &self.account
+    }
+
+    fn network(&self) -> &'static str {
+        NETWORK
+    }
+
+    fn fetch(&self) -> BoxFuture<'_, error::Result<Vec<Post>>> {
+        Box::pin(async move {
+            let mut posts = std::mem::take(&mut *self.inbox.lock().expect("inbox is not poisoned"));
+            posts.sort_by_key(|p| p.messages.first().copied());
+
+            Ok(posts)
+        })
+    }
+
+    fn channel(&self) -> Option<&Snowflake> {
+        Some(&self.account.id)
+    }
+
+    fn receive(&self, posts: Vec<Post>) {
+        *self.inbox.lock().expect("inbox is not poisoned") = posts;
+    }
+}
+
+/// Posts of `messages` received from `channel`, oldest first. Messages of an
+/// album make one post.
+pub fn channel_posts(channel: &Snowflake, messages: &[&TelegramMessage]) -> Vec<Post> {
+    let mut posts: Vec<Post> = Vec::new();
+    let mut album = None;
+    for message in messages {
+        let media = message
+            .photo
+            .last()
+            .map(|p| Media::Photo(p.file_id.clone()))
+            .or_else(|| {
+                message
+                    .video
+                    .as_ref()
+                    .map(|v| Media::Video(v.file_id.clone()))
+            });
+        let text = message.content();
+
+        match posts.last_mut() {
+            Some(post) if message.media_group_id.is_some() && message.media_group_id == album => {
+                post.messages.push(message.message_id);
+                post.media.extend(media);
+                if post.text.is_empty() {
+                    post.text = text.to_owned();
+                }
+            }
+            _ => {
+                let chat = message.chat.as_ref();
+                let url = match chat.and_then(|c| c.username.as_deref()) {
+                    Some(username) => format!("https://t.me/{username}/{}", message.message_id),
+                    None => {
+                        let id = chat.map(|c| c.id.to_string()).unwrap_or_default();
+                        let id = id.strip_prefix("-100").unwrap_or(&id);
+                        format!("https://t.me/c/{id}/{}", message.message_id)
+                    }
+                };
+                let title = chat
+                    .and_then(|c| c.title.clone())
+                    .unwrap_or_else(|| channel.to_string());
+                posts.push(Post {
+                    id: Snowflake::Number(message.message_id),
+                    source_id: channel.clone(),
+                    date: message.date,
+                    text: text.to_owned(),
+                    media: media.into_iter().collect(),
+                    extra: Vec::new(),
+                    stats: None,
+                    source: format!("telegram // {title}"),
+                    url,
+                    messages: vec![message.message_id],
+                });
+            }
+        }
+        album = message.media_group_id.clone();
+    }
+
+    posts
+}
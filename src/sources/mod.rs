@@ -1,4 +1,9 @@
+mod registry;
 mod source;
+pub use registry::*;
 pub use source::*;
 
+pub mod channel;
+
+#[cfg(feature = "vk")]
 pub mod vk;
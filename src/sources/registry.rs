@@ -0,0 +1,191 @@
+use std::{collections::HashMap, sync::Arc};
+
+use serde::Deserialize;
+
+use crate::{
+    config::Config,
+    error::{Error, Result},
+    http::{MeteredTransport, Traffic, Transport},
+};
+
+use super::Source;
+
+/// Entry of the `sources` config list. `type` selects constructor in
+/// [`Registry`], other fields are passed to it.
+#[derive(Deserialize, Clone)]
+pub struct SourceEntry {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(flatten)]
+    pub params: serde_yaml::Mapping,
+}
+
+/// Shared state available to source constructors.
+pub struct Context<'a> {
+    pub config: &'a Config,
+    pub transport: Arc<dyn Transport>,
+}
+
+/// Creates a source from its config entry.
+pub type Constructor =
+    fn(&Context, serde_yaml::Value) -> Result<Box<dyn Source>, serde_yaml::Error>;
+
+/// Maps source types to constructors.
+///
+/// ```no_run
+/// # fn example(config: secretfop::config::Config) -> secretfop::error::Result<()> {
+/// use std::sync::Arc;
+/// use secretfop::{http::ReqwestTransport, sources::Registry};
+///
+/// let sources = Registry::default().build(&config, Arc::new(ReqwestTransport::default()))?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Registry {
+    constructors: HashMap<&'static str, Constructor>,
+    traffic: Option<Arc<Traffic>>,
+}
+
+impl Registry {
+    /// Registry without any source types.
+    pub fn empty() -> Self {
+        Self {
+            constructors: HashMap::new(),
+            traffic: None,
+        }
+    }
+
+    /// Adds or replaces source type.
+    pub fn register(mut self, kind: &'static str, constructor: Constructor) -> Self {
+        self.constructors.insert(kind, constructor);
+
+        self
+    }
+
+    /// Counts traffic of created sources in `traffic`, see
+    /// [`Traffic::scope`].
+    pub fn metered(mut self, traffic: Arc<Traffic>) -> Self {
+        self.traffic = Some(traffic);
+
+        self
+    }
+
+    /// Creates all sources of the config: entries of `sources` list followed
+    /// by dedicated `vk`, `vk_discover` and `exec` sections. Source types with
+    /// `proxies` in config send requests through them instead of `transport`.
+    pub fn build(
+        &self,
+        config: &Config,
+        transport: Arc<dyn Transport>,
+    ) -> Result<Vec<Box<dyn Source>>> {
+        let (sources, errors) = self.build_skipping(config, transport);
+
+        match errors.into_iter().next() {
+            Some(e) => Err(e),
+            None => Ok(sources),
+        }
+    }
+
+    /// Like [`Registry::build`], but skips sources that fail to be created
+    /// and returns their errors, see `ignore_source_errors` of [`Config`].
+    pub fn build_skipping(
+        &self,
+        config: &Config,
+        transport: Arc<dyn Transport>,
+    ) -> (Vec<Box<dyn Source>>, Vec<Error>) {
+        // shared by sources of a type, so they rotate and evict together
+        let meter = |transport: Arc<dyn Transport>| -> Arc<dyn Transport> {
+            match &self.traffic {
+                Some(traffic) => Arc::new(MeteredTransport::new(transport, traffic.clone())),
+                None => transport,
+            }
+        };
+        let pools: HashMap<&str, Arc<dyn Transport>> = config
+            .proxies
+            .keys()
+            .filter_map(|kind| Some((kind.as_str(), meter(Arc::new(config.proxy_pool(kind)?)))))
+            .collect();
+        let transport = meter(transport);
+        let ctx = |kind: &str| Context {
+            config,
+            transport: pools
+                .get(kind)
+                .cloned()
+                .unwrap_or_else(|| transport.clone()),
+        };
+
+        let mut errors = Vec::new();
+        #[allow(unused_mut)] // without any source features
+        let mut sources: Vec<_> = config
+            .sources
+            .iter()
+            .filter_map(|entry| {
+                let constructor = match self.constructors.get(entry.kind.as_str()) {
+                    Some(constructor) => constructor,
+                    None => {
+                        errors.push(Error::UnknownSource(entry.kind.clone()));
+                        return None;
+                    }
+                };
+
+                constructor(
+                    &ctx(&entry.kind),
+                    serde_yaml::Value::Mapping(entry.params.clone()),
+                )
+                .map_err(|source| {
+                    errors.push(Error::SourceConfig {
+                        kind: entry.kind.clone(),
+                        source,
+                    })
+                })
+                .ok()
+            })
+            .collect();
+
+        // dedicated sections, checked by `Config::load`
+        #[cfg(feature = "vk")]
+        sources.extend(config.vk.iter().map(|account| -> Box<dyn Source> {
+            Box::new(super::vk::VKSource::new(&ctx("vk"), account.clone()))
+        }));
+        #[cfg(feature = "vk")]
+        if let Some(params) = &config.vk_discover {
+            match super::vk::VKDiscoverSource::construct(&ctx("vk_discover"), params.clone()) {
+                Ok(source) => sources.push(source),
+                Err(source) => errors.push(Error::SourceConfig {
+                    kind: "vk_discover".to_owned(),
+                    source,
+                }),
+            }
+        }
+        #[cfg(feature = "exec")]
+        sources.extend(
+            config
+                .exec
+                .iter()
+                .map(|source| -> Box<dyn Source> { Box::new(source.clone()) }),
+        );
+
+        (sources, errors)
+    }
+}
+
+impl Default for Registry {
+    /// Registry with all source types enabled at compile time.
+    fn default() -> Self {
+        #[allow(unused_mut)] // without any source features
+        let mut registry =
+            Self::empty().register("telegram_copy", super::channel::ChannelSource::construct);
+        #[cfg(feature = "vk")]
+        {
+            registry = registry
+                .register("vk", super::vk::VKSource::construct)
+                .register("vk_discover", super::vk::VKDiscoverSource::construct);
+        }
+        #[cfg(feature = "exec")]
+        {
+            registry = registry.register("exec", crate::config::ExecSource::construct);
+        }
+
+        registry
+    }
+}
@@ -1,4 +1,12 @@
-use crate::config::SnowflakeRef;
+use std::borrow::Cow;
+
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{Snowflake, SnowflakeRef, SocialAccount},
+    error::Result,
+};
 
 /// Foreign post
 // ⚠️ BLAZINGLY FAST ⚠️
@@ -8,11 +16,17 @@ pub struct ForeignPost<'a, T: std::fmt::Display> {
     pub id: SnowflakeRef<'a>,
     /// Post source ID.
     pub source_id: SnowflakeRef<'a>,
+    /// Post publication date, unix timestamp
+    pub date: u64,
 
     /// Post text. May be empty
-    pub text: &'a str,
+    pub text: Cow<'a, str>,
     /// Post media.
     pub media: Vec<ForeignMedia<'a>>,
+    /// Kinds of attachments that can't be mirrored (polls, documents, etc).
+    pub extra: Vec<ContentKind>,
+    /// Popularity counters, if source provides them
+    pub stats: Option<PostStats>,
 
     /// Source name
     pub source: &'a str,
@@ -21,11 +35,389 @@ pub struct ForeignPost<'a, T: std::fmt::Display> {
 }
 
 /// Foreign media info
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum ForeignMedia<'a> {
     /// A photo URL. JPEG, PNG, etc. NOT GIF
     Photo(&'a str),
     /// A video URL. MP4 or GIF only
-    #[allow(dead_code)] // allowed for future
     Video(&'a str),
+    /// A GIF animation URL. Posted as video
+    Animation(&'a str),
+    /// An audio file, MP3 or M4A, with tags shown by Telegram
+    Audio {
+        url: &'a str,
+        title: Option<&'a str>,
+        performer: Option<&'a str>,
+    },
+    /// Any other file. Telegram names it by `filename`, or by the URL if
+    /// not set
+    Document {
+        url: &'a str,
+        filename: Option<&'a str>,
+        mime: Option<&'a str>,
+    },
+}
+
+/// Owned version of [`ForeignPost`]. Unlike foreign post it does not borrow
+/// from a feed, so it can be queued, stored or sent across threads.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Post {
+    pub id: Snowflake,
+    pub source_id: Snowflake,
+    /// Publication date, unix timestamp
+    pub date: u64,
+    pub text: String,
+    pub media: Vec<Media>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extra: Vec<ContentKind>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats: Option<PostStats>,
+    pub source: String,
+    pub url: String,
+    /// Messages of a Telegram channel the post is copied from, see
+    /// [`super::channel`]. Such posts are copied with their captions instead
+    /// of sending media.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub messages: Vec<u64>,
+}
+
+/// Owned version of [`ForeignMedia`]. Serialized as `type` and `url` with
+/// metadata next to them, like
+/// `{"type": "audio", "url": "https://...", "title": "Song"}`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "MediaEntry", into = "MediaEntry")]
+pub enum Media {
+    Photo(String),
+    Video(String),
+    Animation(String),
+    Audio {
+        url: String,
+        title: Option<String>,
+        performer: Option<String>,
+    },
+    Document {
+        url: String,
+        filename: Option<String>,
+        mime: Option<String>,
+    },
+}
+
+/// Serialized form of [`Media`].
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum MediaEntry {
+    Photo {
+        url: String,
+    },
+    Video {
+        url: String,
+    },
+    Animation {
+        url: String,
+    },
+    Audio {
+        url: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        title: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        performer: Option<String>,
+    },
+    Document {
+        url: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        filename: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        mime: Option<String>,
+    },
+}
+
+impl From<MediaEntry> for Media {
+    fn from(entry: MediaEntry) -> Self {
+        match entry {
+            MediaEntry::Photo { url } => Self::Photo(url),
+            MediaEntry::Video { url } => Self::Video(url),
+            MediaEntry::Animation { url } => Self::Animation(url),
+            MediaEntry::Audio {
+                url,
+                title,
+                performer,
+            } => Self::Audio {
+                url,
+                title,
+                performer,
+            },
+            MediaEntry::Document {
+                url,
+                filename,
+                mime,
+            } => Self::Document {
+                url,
+                filename,
+                mime,
+            },
+        }
+    }
+}
+impl From<Media> for MediaEntry {
+    fn from(media: Media) -> Self {
+        match media {
+            Media::Photo(url) => Self::Photo { url },
+            Media::Video(url) => Self::Video { url },
+            Media::Animation(url) => Self::Animation { url },
+            Media::Audio {
+                url,
+                title,
+                performer,
+            } => Self::Audio {
+                url,
+                title,
+                performer,
+            },
+            Media::Document {
+                url,
+                filename,
+                mime,
+            } => Self::Document {
+                url,
+                filename,
+                mime,
+            },
+        }
+    }
+}
+
+/// Popularity counters of a post.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PostStats {
+    pub likes: u64,
+    pub reposts: u64,
+    pub views: u64,
+    pub comments: u64,
+}
+
+/// Comment under a post.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comment {
+    /// Display name of the author
+    pub author: String,
+    pub text: String,
+    pub likes: u64,
+}
+
+/// Kind of post content
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentKind {
+    Photos,
+    Videos,
+    Gifs,
+    Docs,
+    Audio,
+    Polls,
+    Text,
+}
+
+impl<'a> ForeignMedia<'a> {
+    pub fn kind(&self) -> ContentKind {
+        match self {
+            Self::Photo(_) => ContentKind::Photos,
+            Self::Video(_) => ContentKind::Videos,
+            Self::Animation(_) => ContentKind::Gifs,
+            Self::Audio { .. } => ContentKind::Audio,
+            Self::Document { .. } => ContentKind::Docs,
+        }
+    }
+
+    pub fn url(&self) -> &'a str {
+        match *self {
+            Self::Photo(url) | Self::Video(url) | Self::Animation(url) => url,
+            Self::Audio { url, .. } | Self::Document { url, .. } => url,
+        }
+    }
+
+    /// Same media at another `url`, like Telegram file id, with the same
+    /// metadata.
+    pub fn with_url(self, url: &'a str) -> Self {
+        match self {
+            Self::Photo(_) => Self::Photo(url),
+            Self::Video(_) => Self::Video(url),
+            Self::Animation(_) => Self::Animation(url),
+            Self::Audio {
+                title, performer, ..
+            } => Self::Audio {
+                url,
+                title,
+                performer,
+            },
+            Self::Document { filename, mime, .. } => Self::Document {
+                url,
+                filename,
+                mime,
+            },
+        }
+    }
+}
+
+impl<'a> From<&ForeignMedia<'a>> for Media {
+    fn from(media: &ForeignMedia<'a>) -> Self {
+        let owned = |s: Option<&str>| s.map(str::to_owned);
+        match *media {
+            ForeignMedia::Photo(url) => Self::Photo(url.to_owned()),
+            ForeignMedia::Video(url) => Self::Video(url.to_owned()),
+            ForeignMedia::Animation(url) => Self::Animation(url.to_owned()),
+            ForeignMedia::Audio {
+                url,
+                title,
+                performer,
+            } => Self::Audio {
+                url: url.to_owned(),
+                title: owned(title),
+                performer: owned(performer),
+            },
+            ForeignMedia::Document {
+                url,
+                filename,
+                mime,
+            } => Self::Document {
+                url: url.to_owned(),
+                filename: owned(filename),
+                mime: owned(mime),
+            },
+        }
+    }
+}
+impl Media {
+    pub fn kind(&self) -> ContentKind {
+        self.as_foreign().kind()
+    }
+
+    pub fn url(&self) -> &str {
+        self.as_foreign().url()
+    }
+
+    pub fn as_foreign(&self) -> ForeignMedia<'_> {
+        match self {
+            Self::Photo(url) => ForeignMedia::Photo(url),
+            Self::Video(url) => ForeignMedia::Video(url),
+            Self::Animation(url) => ForeignMedia::Animation(url),
+            Self::Audio {
+                url,
+                title,
+                performer,
+            } => ForeignMedia::Audio {
+                url,
+                title: title.as_deref(),
+                performer: performer.as_deref(),
+            },
+            Self::Document {
+                url,
+                filename,
+                mime,
+            } => ForeignMedia::Document {
+                url,
+                filename: filename.as_deref(),
+                mime: mime.as_deref(),
+            },
+        }
+    }
+}
+
+impl<'a, T: std::fmt::Display> From<&ForeignPost<'a, T>> for Post {
+    fn from(post: &ForeignPost<'a, T>) -> Self {
+        Self {
+            id: post.id.to_owned(),
+            source_id: post.source_id.to_owned(),
+            date: post.date,
+            text: post.text.to_string(),
+            media: post.media.iter().map(Media::from).collect(),
+            extra: post.extra.clone(),
+            stats: post.stats,
+            source: post.source.to_owned(),
+            url: post.url.to_string(),
+            messages: Vec::new(),
+        }
+    }
+}
+impl Post {
+    /// Borrows post as [`ForeignPost`].
+    pub fn as_foreign(&self) -> ForeignPost<'_, &str> {
+        ForeignPost {
+            id: self.id.as_ref(),
+            source_id: self.source_id.as_ref(),
+            date: self.date,
+            text: Cow::Borrowed(&self.text),
+            media: self.media.iter().map(Media::as_foreign).collect(),
+            extra: self.extra.clone(),
+            stats: self.stats,
+            source: &self.source,
+            url: &self.url,
+        }
+    }
+}
+
+/// Configured source of posts.
+pub trait Source: Send + Sync {
+    /// Account settings used to filter and caption posts.
+    fn account(&self) -> &SocialAccount;
+
+    /// Cache namespace of the source, like `vk`.
+    fn network(&self) -> &'static str;
+
+    /// Fetches latest posts, oldest first.
+    fn fetch(&self) -> BoxFuture<'_, Result<Vec<Post>>>;
+
+    /// Fetches up to `limit` most liked comments of a post. Sources without
+    /// comments return nothing.
+    fn comments<'a>(
+        &'a self,
+        _source_id: &'a Snowflake,
+        _id: &'a Snowflake,
+        _limit: usize,
+    ) -> BoxFuture<'a, Result<Vec<Comment>>> {
+        Box::pin(async { Ok(Vec::new()) })
+    }
+
+    /// Fetches `page` of older posts, the first page being the latest posts,
+    /// oldest first. A page without posts is the end of the history. Sources
+    /// that can't page through older posts return [`None`].
+    fn history(&self, _page: usize) -> BoxFuture<'_, Result<Option<Vec<Post>>>> {
+        Box::pin(async { Ok(None) })
+    }
+
+    /// Checks whether post `id` of `source_id` still exists, to verify cached
+    /// watermarks. Sources that can't look up posts return [`None`].
+    fn exists<'a>(
+        &'a self,
+        _source_id: &'a Snowflake,
+        _id: u64,
+    ) -> BoxFuture<'a, Result<Option<bool>>> {
+        Box::pin(async { Ok(None) })
+    }
+
+    /// Telegram channel whose posts the bot receives in updates for this
+    /// source, see [`Source::receive`]. Most sources fetch their posts.
+    fn channel(&self) -> Option<&Snowflake> {
+        None
+    }
+
+    /// Takes posts of [`Source::channel`] that were received and not
+    /// mirrored yet, before [`Source::fetch`].
+    fn receive(&self, _posts: Vec<Post>) {}
+
+    /// Resolves alias `id` of the account, like a VK screen name, to its
+    /// stable numeric id. Sources without aliases return [`None`].
+    fn resolve(&self) -> BoxFuture<'_, Result<Option<u64>>> {
+        Box::pin(async { Ok(None) })
+    }
+
+    /// Makes [`Source::fetch`] use `id` resolved before instead of resolving
+    /// the alias again.
+    fn set_resolved(&self, _id: u64) {}
+
+    /// Sources found by this one, like communities a user is subscribed to.
+    /// Called on every run before fetching, found sources are fetched as
+    /// configured ones. Most sources find nothing.
+    fn discover(&self) -> BoxFuture<'_, Result<Vec<Box<dyn Source>>>> {
+        Box::pin(async { Ok(Vec::new()) })
+    }
 }
@@ -1,45 +1,108 @@
-use reqwest::Client;
-use serde::Deserialize;
+use std::{borrow::Cow, sync::Arc};
 
-use crate::config::SnowflakeRef;
+use futures::future::BoxFuture;
+use once_cell::sync::OnceCell;
+use serde::{de::DeserializeOwned, Deserialize};
+use thiserror::Error;
 
-use super::{ForeignMedia, ForeignPost};
+use crate::{
+    config::{Snowflake, SnowflakeRef, SocialAccount, VKTokenKind},
+    error::{self, Error as CrateError},
+    http::{HttpError, Request, ReqwestTransport, Transport},
+    unix_now,
+};
 
+use super::{Comment, ContentKind, Context, ForeignMedia, ForeignPost, Post, PostStats, Source};
+
+/// Version of VK API used by default.
+pub const API_VERSION: &str = "5.131";
+
+/// Number of posts in a page of [`Source::history`], the most `wall.get`
+/// returns.
+pub const HISTORY_PAGE: u8 = 100;
+
+/// API methods not available with [`VKTokenKind::Service`] keys.
+pub const USER_TOKEN_METHODS: [&str; 2] = ["groups.get", "video.get"];
+
+/// VKontakte API client.
+#[derive(Clone)]
 pub struct VKClient {
     pub token: String,
+    pub token_kind: VKTokenKind,
+    /// API endpoint, `https://api.vk.com/method` by default.
+    pub base_url: String,
+    transport: Arc<dyn Transport>,
+}
+/// VK group as a [`Source`].
+pub struct VKSource {
+    client: VKClient,
+    account: SocialAccount,
+    /// Numeric id of the screen name in `account`.
+    resolved: OnceCell<u64>,
+}
+/// Communities a VK user is subscribed to as a [`Source`], refreshed on
+/// every run. Found communities are mirrored with settings of this source, it
+/// does not fetch posts itself.
+///
+/// Takes `user` (numeric id), optional `include_tags` and the same fields
+/// as `vk` accounts except `id`. With `include_tags` only communities whose
+/// name or topic contains any of tags (case-insensitive) are mirrored.
+pub struct VKDiscoverSource {
+    client: VKClient,
+    user: u64,
+    include_tags: Vec<String>,
+    account: SocialAccount,
 }
+/// Community a user is subscribed to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VKSubscription {
+    pub id: u64,
+    pub name: String,
+    pub screen_name: String,
+    /// Topic of the community, like `Art`. May be empty
+    pub activity: String,
+}
+/// Builder of `wall.get` request. Created by [`VKClient::get_posts`].
 pub struct VKGetPosts<'a> {
     limit: u8,
+    offset: u32,
+    api_version: &'a str,
     id: SnowflakeRef<'a>,
-    token: &'a str,
+    client: &'a VKClient,
 }
 //pub struct VKFetchVideos<'client, 'data> {
 //    token: &'client str,
 //    videos: Vec<&'data mut String>,
 //}
-#[derive(Debug)]
+#[derive(Debug, Error)]
 pub enum VKError {
-    Http(reqwest::Error),
+    #[error("{0}")]
+    Http(HttpError),
+    #[error(transparent)]
     Scheme(serde_json::Error),
+    #[error("API returned error {error_code}: {error_msg}")]
     Server { error_code: u32, error_msg: String },
+    /// Page is private, deleted, banned or access to it is denied.
+    #[error("page is inaccessible, API returned error {error_code}: {error_msg}")]
+    Inaccessible { error_code: u32, error_msg: String },
+    #[error("API does not returned any groups")]
     Content,
+    #[error("screen name {0:?} does not belong to any group")]
+    UnknownScreenName(String),
+    #[error("{0} requires a user token, but vk_token is a service key")]
+    NeedsUserToken(String),
 }
 
-impl std::fmt::Display for VKError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::Http(v) => v.fmt(f),
-            Self::Scheme(v) => v.fmt(f),
-            Self::Server {
-                error_code,
-                error_msg,
-            } => write!(f, "API returned error {error_code}: {error_msg}"),
-            Self::Content => write!(f, "API does not returned any groups"),
-        }
-    }
+#[derive(Deserialize)]
+struct VKDiscover {
+    user: u64,
+    #[serde(default)]
+    include_tags: Vec<String>,
+    #[serde(flatten)]
+    account: serde_yaml::Mapping,
 }
-impl std::error::Error for VKError {}
 
+/// Latest posts of a VK group.
 pub struct VKGroupFeed {
     pub group_source_name: String,
     pub group_id: u64,
@@ -47,12 +110,18 @@ pub struct VKGroupFeed {
 }
 pub struct VKItem {
     pub id: u64,
+    pub date: u64,
     pub text: String,
     pub media: Vec<VKMedia>,
+    /// Attachments that can't be mirrored.
+    pub extra: Vec<ContentKind>,
+    pub stats: PostStats,
 }
 pub enum VKMedia {
     /// Photo, contains url to image.
     Photo(String),
+    /// GIF document, contains url to file.
+    Animation(String),
 }
 
 //#[deprecated = "Please do not use this iter because it so cringe"]
@@ -68,22 +137,117 @@ pub struct VKItemURL {
 
 #[derive(Deserialize)]
 #[serde(rename_all = "lowercase")]
-enum VKResponse {
-    Response {
-        items: Vec<VKResponseItem>,
-        groups: Vec<VKGroup>,
-    },
-    Error {
-        error_code: u32,
-        error_msg: String,
+enum VKResponse<T> {
+    Response(T),
+    Error { error_code: u32, error_msg: String },
+}
+/// Value that is skipped instead of failing the whole response, e.g. a post
+/// with unexpected structure.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Lenient<T> {
+    Valid(T),
+    Invalid(serde::de::IgnoredAny),
+}
+#[derive(Deserialize)]
+struct VKWall {
+    items: Vec<Lenient<VKResponseItem>>,
+    #[serde(default)]
+    groups: Vec<VKGroup>,
+}
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum VKScreenName {
+    Object {
+        object_id: u64,
+        r#type: String,
     },
+    // VK returns empty array for unknown names
+    #[allow(dead_code)]
+    Unknown(Vec<serde_json::Value>),
+}
+#[derive(Deserialize)]
+struct VKGroups {
+    items: Vec<Lenient<VKResponseGroup>>,
+}
+#[derive(Deserialize)]
+struct VKResponseGroup {
+    id: u64,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    screen_name: String,
+    #[serde(default)]
+    r#type: String,
+    #[serde(default)]
+    is_closed: i32,
+    #[serde(default)]
+    deactivated: Option<String>,
+    #[serde(default)]
+    activity: String,
+}
+#[derive(Deserialize)]
+struct VKComments {
+    items: Vec<Lenient<VKResponseComment>>,
+    #[serde(default)]
+    profiles: Vec<Lenient<VKProfile>>,
+    #[serde(default)]
+    groups: Vec<Lenient<VKGroup>>,
+}
+#[derive(Deserialize)]
+struct VKResponseComment {
+    #[serde(default)]
+    from_id: i64,
+    #[serde(default)]
+    text: String,
+    #[serde(default)]
+    likes: Option<VKCount>,
+}
+#[derive(Deserialize)]
+struct VKProfile {
+    id: u64,
+    first_name: String,
+    last_name: String,
+}
+#[derive(Deserialize)]
+struct VKCount {
+    count: u64,
 }
 #[derive(Deserialize)]
 struct VKResponseItem {
     id: u64,
+    #[serde(default)]
+    date: u64,
+    #[serde(default)]
     marked_as_ads: i32,
+    /// `1` for scheduled posts, seen by admins of the group.
+    #[serde(default)]
+    postponed: i32,
+    /// `post`, `copy`, `reply`, or `postpone` and `suggest` for posts that are
+    /// not published yet.
+    #[serde(default)]
+    post_type: Option<String>,
+    #[serde(default)]
     text: String,
-    attachments: Vec<VKResponseMedia>,
+    #[serde(default)]
+    attachments: Vec<Lenient<VKResponseMedia>>,
+    #[serde(default)]
+    likes: Option<VKCount>,
+    #[serde(default)]
+    reposts: Option<VKCount>,
+    #[serde(default)]
+    views: Option<VKCount>,
+    #[serde(default)]
+    comments: Option<VKCount>,
+}
+impl VKResponseItem {
+    /// Checks that the post is not scheduled or suggested, those leak to
+    /// `wall.get` with tokens of group admins.
+    fn is_published(&self, now: u64) -> bool {
+        let unpublished = matches!(self.post_type.as_deref(), Some("postpone" | "suggest"));
+
+        self.postponed == 0 && !unpublished && self.date <= now
+    }
 }
 #[derive(Deserialize)]
 struct VKGroup {
@@ -92,22 +256,57 @@ struct VKGroup {
 }
 #[derive(Deserialize)]
 struct VKResponseMedia {
+    r#type: String,
     #[serde(default)]
     photo: Option<VKResponsePhoto>,
+    #[serde(default)]
+    doc: Option<VKResponseDoc>,
 }
 #[derive(Deserialize)]
 struct VKResponsePhoto {
-    sizes: Vec<VKPhotoSizes>,
+    #[serde(default)]
+    sizes: Vec<Lenient<VKPhotoSizes>>,
 }
 #[derive(Deserialize)]
 struct VKPhotoSizes {
     r#type: char,
     url: String,
 }
+#[derive(Deserialize)]
+struct VKResponseDoc {
+    ext: String,
+    url: String,
+}
 
 impl VKClient {
     pub fn new(token: String) -> Self {
-        Self { token }
+        Self {
+            token,
+            token_kind: VKTokenKind::User,
+            base_url: "https://api.vk.com/method".to_owned(),
+            transport: Arc::new(ReqwestTransport::default()),
+        }
+    }
+
+    /// Replaces API endpoint, e.g. with mock server address.
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into();
+
+        self
+    }
+
+    /// Replaces HTTP transport.
+    pub fn with_transport(mut self, transport: Arc<dyn Transport>) -> Self {
+        self.transport = transport;
+
+        self
+    }
+
+    /// Sets kind of the token, [`VKTokenKind::User`] by default.
+    pub fn with_token_kind(mut self, token_kind: VKTokenKind) -> Self {
+        self.token_kind = token_kind;
+
+        self
     }
 
     //    pub fn fetch_videos<'a>(&self) -> VKFetchVideos<'_, 'a> {
@@ -117,27 +316,457 @@ impl VKClient {
     //        }
     //    }
 
+    /// Calls API method and returns raw response body.
+    async fn request(&self, method: &str, params: Vec<(&str, String)>) -> Result<String, VKError> {
+        let request = Request::get(format!("{}/{method}", self.base_url));
+        let request = match self.token_kind {
+            VKTokenKind::User => request.bearer_auth(&self.token),
+            VKTokenKind::Service if USER_TOKEN_METHODS.contains(&method) => {
+                return Err(VKError::NeedsUserToken(method.to_owned()))
+            }
+            VKTokenKind::Service => request.query("access_token", &self.token),
+        };
+        let request = params
+            .into_iter()
+            .fold(request, |r, (key, value)| r.query(key, value));
+
+        self.transport.send(request).await.map_err(VKError::Http)
+    }
+
+    /// Calls API method and returns its response.
+    async fn call<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: Vec<(&str, String)>,
+    ) -> Result<T, VKError> {
+        parse_response(&self.request(method, params).await?)
+    }
+
+    /// Current time of VK servers, unix timestamp. Checks that the API is
+    /// reachable.
+    pub async fn server_time(&self) -> Result<u64, VKError> {
+        self.call("utils.getServerTime", vec![("v", API_VERSION.to_owned())])
+            .await
+    }
+
+    /// Resolves screen name of a group (like `apiclub`) to its numeric id.
+    pub async fn resolve_screen_name(&self, name: &str) -> Result<u64, VKError> {
+        let res: VKScreenName = self
+            .call(
+                "utils.resolveScreenName",
+                vec![
+                    ("screen_name", name.to_owned()),
+                    ("v", API_VERSION.to_owned()),
+                ],
+            )
+            .await?;
+
+        match res {
+            VKScreenName::Object { object_id, r#type }
+                if matches!(r#type.as_str(), "group" | "page" | "event") =>
+            {
+                Ok(object_id)
+            }
+            _ => Err(VKError::UnknownScreenName(name.to_owned())),
+        }
+    }
+
+    /// Fetches up to 100 comments of a group post with authors names.
+    pub async fn get_comments(&self, group_id: u64, post_id: u64) -> Result<Vec<Comment>, VKError> {
+        let res: VKComments = self
+            .call(
+                "wall.getComments",
+                vec![
+                    ("owner_id", format!("-{group_id}")),
+                    ("post_id", post_id.to_string()),
+                    ("count", "100".to_owned()),
+                    ("need_likes", "1".to_owned()),
+                    ("extended", "1".to_owned()),
+                    ("v", API_VERSION.to_owned()),
+                ],
+            )
+            .await?;
+
+        let profiles: Vec<_> = res
+            .profiles
+            .into_iter()
+            .filter_map(Lenient::into_valid)
+            .collect();
+        let groups: Vec<_> = res
+            .groups
+            .into_iter()
+            .filter_map(Lenient::into_valid)
+            .collect();
+        let author = |from_id: i64| {
+            let id = from_id.unsigned_abs();
+            if from_id < 0 {
+                groups.iter().find(|g| g.id == id).map(|g| g.name.clone())
+            } else {
+                profiles
+                    .iter()
+                    .find(|p| p.id == id)
+                    .map(|p| format!("{} {}", p.first_name, p.last_name))
+            }
+        };
+
+        Ok(res
+            .items
+            .into_iter()
+            .filter_map(Lenient::into_valid)
+            // deleted comments have neither author nor text
+            .filter(|c| c.from_id != 0 && !c.text.is_empty())
+            .map(|c| Comment {
+                author: author(c.from_id).unwrap_or_else(|| "Anonymous".to_owned()),
+                text: c.text,
+                likes: c.likes.map(|l| l.count).unwrap_or_default(),
+            })
+            .collect())
+    }
+
+    /// Checks whether a group post exists. Deleted posts are not returned by
+    /// `wall.getById`.
+    pub async fn post_exists(&self, group_id: u64, post_id: u64) -> Result<bool, VKError> {
+        let res: Vec<serde::de::IgnoredAny> = self
+            .call(
+                "wall.getById",
+                vec![
+                    ("posts", format!("-{group_id}_{post_id}")),
+                    ("v", API_VERSION.to_owned()),
+                ],
+            )
+            .await?;
+
+        Ok(!res.is_empty())
+    }
+
+    /// Fetches up to 1000 open communities a user is subscribed to. Closed,
+    /// private and deleted ones are skipped, their walls can't be read.
+    pub async fn get_subscriptions(&self, user: u64) -> Result<Vec<VKSubscription>, VKError> {
+        let res: VKGroups = self
+            .call(
+                "groups.get",
+                vec![
+                    ("user_id", user.to_string()),
+                    ("extended", "1".to_owned()),
+                    ("fields", "activity".to_owned()),
+                    ("count", "1000".to_owned()),
+                    ("v", API_VERSION.to_owned()),
+                ],
+            )
+            .await?;
+
+        Ok(res
+            .items
+            .into_iter()
+            .filter_map(Lenient::into_valid)
+            .filter(|g| {
+                matches!(g.r#type.as_str(), "group" | "page" | "event")
+                    && g.is_closed == 0
+                    && g.deactivated.is_none()
+            })
+            .map(|g| VKSubscription {
+                id: g.id,
+                name: g.name,
+                screen_name: g.screen_name,
+                activity: g.activity,
+            })
+            .collect())
+    }
+
+    /// Fetches latest posts of a group by numeric id or screen name.
     pub fn get_posts<'a>(&'a self, id: SnowflakeRef<'a>) -> VKGetPosts<'a> {
         VKGetPosts {
             limit: 5,
+            offset: 0,
+            api_version: API_VERSION,
             id,
-            token: &self.token,
+            client: self,
+        }
+    }
+}
+impl VKSource {
+    pub fn new(ctx: &Context, account: SocialAccount) -> Self {
+        Self {
+            client: VKClient::new(ctx.config.vk_token.clone())
+                .with_token_kind(ctx.config.vk_token_kind)
+                .with_transport(ctx.transport.clone()),
+            account,
+            resolved: OnceCell::new(),
+        }
+    }
+
+    /// [`super::Constructor`] of `vk` source type, takes account fields.
+    pub fn construct(
+        ctx: &Context,
+        params: serde_yaml::Value,
+    ) -> Result<Box<dyn Source>, serde_yaml::Error> {
+        Ok(Box::new(Self::new(ctx, serde_yaml::from_value(params)?)))
+    }
+
+    /// Fetches latest posts, or [`HISTORY_PAGE`] posts of `page` of the
+    /// history, oldest first.
+    async fn posts(&self, page: Option<usize>) -> error::Result<Vec<Post>> {
+        let account = || self.account.id.to_string();
+        let id = match (self.account.id.as_ref().flatten(), self.resolved.get()) {
+            (SnowflakeRef::Number(id), _) | (_, Some(&id)) => id,
+            (SnowflakeRef::String(name), None) => self
+                .client
+                .resolve_screen_name(name)
+                .await
+                .map_err(|source| CrateError::Resolve {
+                    account: account(),
+                    source,
+                })?,
+        };
+
+        let request = self.client.get_posts(SnowflakeRef::Number(id));
+        let request = match page {
+            Some(page) => request
+                .limit(HISTORY_PAGE)
+                .offset((page * HISTORY_PAGE as usize) as u32),
+            None => request,
+        };
+        let feed = request.send().await.map_err(|source| CrateError::Fetch {
+            account: account(),
+            source,
+        })?;
+
+        // oldest first
+        Ok(feed.as_iter().rev().map(|f| Post::from(&f)).collect())
+    }
+}
+impl VKDiscoverSource {
+    /// [`super::Constructor`] of `vk_discover` source type.
+    pub fn construct(
+        ctx: &Context,
+        params: serde_yaml::Value,
+    ) -> Result<Box<dyn Source>, serde_yaml::Error> {
+        let VKDiscover {
+            user,
+            include_tags,
+            mut account,
+        } = serde_yaml::from_value(params)?;
+        // found communities replace it, the user id only names this source
+        account.insert("id".into(), user.into());
+
+        Ok(Box::new(Self {
+            client: VKClient::new(ctx.config.vk_token.clone())
+                .with_token_kind(ctx.config.vk_token_kind)
+                .with_transport(ctx.transport.clone()),
+            user,
+            include_tags: include_tags.iter().map(|t| t.to_lowercase()).collect(),
+            account: serde_yaml::from_value(serde_yaml::Value::Mapping(account))?,
+        }))
+    }
+
+    fn includes(&self, group: &VKSubscription) -> bool {
+        let name = group.name.to_lowercase();
+        let activity = group.activity.to_lowercase();
+
+        self.include_tags.is_empty()
+            || self
+                .include_tags
+                .iter()
+                .any(|tag| name.contains(tag) || activity.contains(tag))
+    }
+}
+impl Source for VKDiscoverSource {
+    fn account(&self) -> &SocialAccount {
+        &self.account
+    }
+
+    fn network(&self) -> &'static str {
+        "vk"
+    }
+
+    fn fetch(&self) -> BoxFuture<'_, error::Result<Vec<Post>>> {
+        Box::pin(async { Ok(Vec::new()) })
+    }
+
+    fn discover(&self) -> BoxFuture<'_, error::Result<Vec<Box<dyn Source>>>> {
+        Box::pin(async move {
+            let groups = self
+                .client
+                .get_subscriptions(self.user)
+                .await
+                .map_err(|source| CrateError::Discover {
+                    user: self.user,
+                    source,
+                })?;
+
+            Ok(groups
+                .into_iter()
+                .filter(|g| self.includes(g))
+                .map(|g| -> Box<dyn Source> {
+                    Box::new(VKSource {
+                        client: self.client.clone(),
+                        account: SocialAccount {
+                            id: Snowflake::Number(g.id),
+                            ..self.account.clone()
+                        },
+                        resolved: OnceCell::new(),
+                    })
+                })
+                .collect())
+        })
+    }
+}
+impl Source for VKSource {
+    fn account(&self) -> &SocialAccount {
+        &self.account
+    }
+
+    fn network(&self) -> &'static str {
+        "vk"
+    }
+
+    fn resolve(&self) -> BoxFuture<'_, error::Result<Option<u64>>> {
+        Box::pin(async move {
+            let SnowflakeRef::String(name) = self.account.id.as_ref().flatten() else {
+                return Ok(None);
+            };
+
+            self.client
+                .resolve_screen_name(name)
+                .await
+                .map(Some)
+                .map_err(|source| CrateError::Resolve {
+                    account: self.account.id.to_string(),
+                    source,
+                })
+        })
+    }
+
+    fn set_resolved(&self, id: u64) {
+        // resolved once per run
+        let _ = self.resolved.set(id);
+    }
+
+    fn fetch(&self) -> BoxFuture<'_, error::Result<Vec<Post>>> {
+        Box::pin(self.posts(None))
+    }
+
+    fn history(&self, page: usize) -> BoxFuture<'_, error::Result<Option<Vec<Post>>>> {
+        Box::pin(async move { self.posts(Some(page)).await.map(Some) })
+    }
+
+    fn exists<'a>(
+        &'a self,
+        source_id: &'a Snowflake,
+        id: u64,
+    ) -> BoxFuture<'a, error::Result<Option<bool>>> {
+        Box::pin(async move {
+            let Some(group_id) = source_id.as_ref().as_number() else {
+                return Ok(None);
+            };
+
+            self.client
+                .post_exists(group_id, id)
+                .await
+                .map(Some)
+                .map_err(|source| CrateError::Exists {
+                    post: format!("https://vk.com/wall-{group_id}_{id}"),
+                    source,
+                })
+        })
+    }
+
+    fn comments<'a>(
+        &'a self,
+        source_id: &'a Snowflake,
+        id: &'a Snowflake,
+        limit: usize,
+    ) -> BoxFuture<'a, error::Result<Vec<Comment>>> {
+        Box::pin(async move {
+            let (Some(group_id), Some(post_id)) =
+                (source_id.as_ref().as_number(), id.as_ref().as_number())
+            else {
+                return Ok(Vec::new());
+            };
+
+            let mut comments =
+                self.client
+                    .get_comments(group_id, post_id)
+                    .await
+                    .map_err(|source| CrateError::FetchComments {
+                        post: format!("https://vk.com/wall-{group_id}_{post_id}"),
+                        source,
+                    })?;
+            // stable, so equally liked comments stay in order
+            comments.sort_by_key(|c| std::cmp::Reverse(c.likes));
+            comments.truncate(limit);
+
+            Ok(comments)
+        })
+    }
+}
+
+/// Parses response body of any API method, like `{"response": ...}` or
+/// `{"error": ...}`.
+pub fn parse_response<T: DeserializeOwned>(json: &str) -> Result<T, VKError> {
+    match serde_json::from_str(json).map_err(VKError::Scheme)? {
+        VKResponse::Response(v) => Ok(v),
+        VKResponse::Error {
+            error_code,
+            error_msg,
+        } if matches!(error_code, 15 | 18 | 19 | 30) => Err(VKError::Inaccessible {
+            error_code,
+            error_msg,
+        }),
+        VKResponse::Error {
+            error_code,
+            error_msg,
+        } => Err(VKError::Server {
+            error_code,
+            error_msg,
+        }),
+    }
+}
+
+impl VKResponsePhoto {
+    fn into_best_url(self) -> Option<String> {
+        self.sizes
+            .into_iter()
+            .filter_map(Lenient::into_valid)
+            .rev() // better first (maybe)
+            .max_by_key(|p| match p.r#type {
+                's' => 1,
+                'm' => 2,
+                'x' => 3,
+                'y' => 4,
+                'z' => 5,
+                'w' => 6,
+                _ => 0,
+            })
+            .map(|f| f.url)
+    }
+}
+impl<T> Lenient<T> {
+    fn into_valid(self) -> Option<T> {
+        match self {
+            Self::Valid(v) => Some(v),
+            Self::Invalid(_) => None,
         }
     }
 }
 impl VKGroupFeed {
+    /// Iterates over posts, oldest last.
     pub fn as_iter(&self) -> impl DoubleEndedIterator<Item = ForeignPost<'_, VKItemURL>> {
         self.items.iter().map(|item: &VKItem| ForeignPost {
             id: SnowflakeRef::Number(item.id),
             source_id: SnowflakeRef::Number(self.group_id),
-            text: &item.text,
+            date: item.date,
+            text: Cow::Borrowed(&item.text),
             media: item
                 .media
                 .iter()
                 .map(|f| match f {
                     VKMedia::Photo(v) => ForeignMedia::Photo(v),
+                    VKMedia::Animation(v) => ForeignMedia::Animation(v),
                 })
                 .collect(),
+            extra: item.extra.clone(),
+            stats: Some(item.stats),
             source: &self.group_source_name,
             url: VKItemURL {
                 group_id: self.group_id,
@@ -182,78 +811,101 @@ impl VKGroupFeed {
 //    }
 //}
 impl<'a> VKGetPosts<'a> {
+    /// Number of posts to fetch, up to 100. Default is 5.
+    pub fn limit(mut self, limit: u8) -> Self {
+        self.limit = limit;
+
+        self
+    }
+
+    /// Number of latest posts to skip.
+    pub fn offset(mut self, offset: u32) -> Self {
+        self.offset = offset;
+
+        self
+    }
+
+    /// Overrides API version, [`API_VERSION`] by default.
+    pub fn api_version(mut self, version: &'a str) -> Self {
+        self.api_version = version;
+
+        self
+    }
+
     pub async fn send(self) -> Result<VKGroupFeed, VKError> {
-        let client = Client::new();
         let id = match self.id.flatten() {
             SnowflakeRef::Number(v) => ("owner_id", format!("-{v}")), // 140 IQ negative ids
             SnowflakeRef::String(s) => ("domain", s.to_owned()),
         };
-        let res = client
-            .get("https://api.vk.com/method/wall.get")
-            .bearer_auth(self.token)
-            .query(&[
-                ("count", self.limit.to_string()),
-                id,
-                ("extended", "1".to_string()),
-                ("v", "5.131".to_string()),
-            ])
-            .send()
-            .await
-            .map_err(VKError::Http)?
-            .text()
-            .await
-            .map_err(VKError::Http)?;
+        let raw = self
+            .client
+            .request(
+                "wall.get",
+                vec![
+                    ("count", self.limit.to_string()),
+                    ("offset", self.offset.to_string()),
+                    id,
+                    ("extended", "1".to_owned()),
+                    ("v", self.api_version.to_owned()),
+                ],
+            )
+            .await?;
 
-        let raw: VKResponse = serde_json::from_str(&res).map_err(VKError::Scheme)?;
+        VKGroupFeed::from_response(&raw)
+    }
+}
 
-        let (items, group_id, source) = match raw {
-            VKResponse::Response { items, groups } if !groups.is_empty() => {
+impl VKGroupFeed {
+    /// Parses `wall.get` response body. Posts and attachments with unexpected
+    /// structure are skipped.
+    pub fn from_response(json: &str) -> Result<Self, VKError> {
+        let (items, group_id, source) = match parse_response(json)? {
+            VKWall { items, groups } if !groups.is_empty() => {
                 (items, groups[0].id, format!("vk // {}", groups[0].name))
             }
-            VKResponse::Error {
-                error_code,
-                error_msg,
-            } => {
-                return Err(VKError::Server {
-                    error_code,
-                    error_msg,
-                })
-            }
             _ => return Err(VKError::Content),
         };
 
+        let now = unix_now();
         let feed = VKGroupFeed {
             group_source_name: source,
             group_id,
             items: items
                 .into_iter()
-                .filter(|i| i.marked_as_ads == 0)
-                .map(|i| VKItem {
-                    id: i.id,
-                    text: i.text,
-                    media: i
-                        .attachments
-                        .into_iter()
-                        .filter_map(|r| r.photo)
-                        .map(|r| {
-                            VKMedia::Photo(
-                                r.sizes
-                                    .into_iter()
-                                    .rev() // better first (maybe)
-                                    .max_by_key(|p| match p.r#type {
-                                        's' => 1,
-                                        'm' => 2,
-                                        'x' => 3,
-                                        'y' => 4,
-                                        'z' => 5,
-                                        'w' => 6,
-                                        _ => 0,
-                                    })
-                                    .map(|f| f.url)
-                                    .expect("api should return at least one size for media"),
-                            )
-                        })
-                        .collect(),
+                .filter_map(Lenient::into_valid)
+                .filter(|i| i.marked_as_ads == 0 && i.is_published(now))
+                .map(|i| {
+                    let mut media = Vec::new();
+                    let mut extra = Vec::new();
+                    for attachment in i.attachments.into_iter().filter_map(Lenient::into_valid) {
+                        match (attachment.r#type.as_str(), attachment.photo, attachment.doc) {
+                            ("photo", Some(photo), _) => {
+                                media.extend(photo.into_best_url().map(VKMedia::Photo))
+                            }
+                            ("doc", _, Some(doc)) if doc.ext == "gif" => {
+                                media.push(VKMedia::Animation(doc.url))
+                            }
+                            ("doc", ..) => extra.push(ContentKind::Docs),
+                            ("video", ..) => extra.push(ContentKind::Videos),
+                            ("poll", ..) => extra.push(ContentKind::Polls),
+                            _ => {}
+                        }
+                    }
+
+                    let count = |c: Option<VKCount>| c.map(|c| c.count).unwrap_or_default();
+                    VKItem {
+                        id: i.id,
+                        date: i.date,
+                        text: i.text,
+                        media,
+                        extra,
+                        stats: PostStats {
+                            likes: count(i.likes),
+                            reposts: count(i.reposts),
+                            views: count(i.views),
+                            comments: count(i.comments),
+                        },
+                    }
                 })
                 .collect(),
         };
@@ -1,70 +1,168 @@
 use std::{
+    borrow::Cow,
     fmt::{Display, Write},
-    time::Duration,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
 
-use reqwest::Client;
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use thiserror::Error;
+use tokio::time;
 
 use crate::{
-    config::Snowflake,
-    sources::{ForeignMedia, ForeignPost},
+    config::{CaptionMode, Snowflake},
+    http::{HttpError, Request, RequestFile, ReqwestTransport, Transport},
+    media::{MediaCache, MediaError},
+    sources::{ForeignMedia, ForeignPost, Media, Post, PostStats},
+    text,
 };
 
+/// Telegram Bot API client, bound to a single channel.
 pub struct TelegramClient {
     pub token: String,
     pub channel_id: Snowflake,
+    /// API endpoint, `https://api.telegram.org` by default.
+    pub base_url: String,
+    transport: Arc<dyn Transport>,
+    media_cache: Option<Arc<MediaCache>>,
+    max_flood_wait: Duration,
+    /// End of the last rate limit, no call is sent before it.
+    flood_until: Mutex<Option<Instant>>,
 }
-#[derive(Debug)]
+#[derive(Debug, Error)]
 pub enum TelegramError {
-    Http(reqwest::Error),
+    #[error("{0}")]
+    Http(HttpError),
+    #[error(transparent)]
     Scheme(serde_json::Error),
+    #[error("API returned error {error_code}: {description}")]
     Server {
         error_code: u32,
         description: String,
     },
-    RateLimited {
-        timeout: Duration,
-    },
+    #[error("ratelimited for {} seconds", timeout.as_secs())]
+    RateLimited { timeout: Duration },
+    /// The chat does not exist or the bot never joined it.
+    #[error("chat not found: {description}")]
+    ChatNotFound { description: String },
+    /// The bot was removed from the chat or can't write there.
+    #[error("bot can't write to the chat: {description}")]
+    Kicked { description: String },
+    /// Telegram could not get or process media, see [`MEDIA_URL_ERRORS`].
+    #[error("media rejected: {description}")]
+    BadMedia { description: String },
+    #[error("message caption is too long")]
+    CaptionTooLong,
+    /// Telegram copied none of the messages, like when they were deleted.
+    #[error("no messages were copied")]
+    NothingCopied,
+    #[error(transparent)]
+    Media(MediaError),
 }
 
-impl Display for TelegramError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::Http(e) => e.fmt(f),
-            Self::Scheme(e) => e.fmt(f),
-            Self::Server {
-                error_code,
-                description,
-            } => write!(f, "API returned error {error_code}: {description}"),
-            Self::RateLimited { timeout } => {
-                write!(f, "ratelimited for {} seconds", timeout.as_secs())
-            }
-        }
-    }
+/// Messages sent by [`SendMessage::send_messages`].
+#[derive(Debug)]
+pub struct SentMessages {
+    /// Sent messages, one per media in order of sending. Never empty.
+    pub messages: Vec<TelegramMessage>,
+    /// Index of the media of every message.
+    pub indexes: Vec<usize>,
+    /// Indexes of media dropped because Telegram rejected them, ascending.
+    pub dropped: Vec<usize>,
 }
-impl std::error::Error for TelegramError {}
 
+/// Request rendered by [`SendMessage::render`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderedMessage {
+    /// API method, like `sendMediaGroup`.
+    pub method: &'static str,
+    /// Parameters of the request, with JSON of the media, if any.
+    pub params: Vec<(&'static str, String)>,
+}
+
+impl RenderedMessage {
+    /// Parameters as a JSON object, with nested JSON parameters like `media`
+    /// parsed.
+    pub fn to_json(&self) -> serde_json::Value {
+        let params = self.params.iter().map(|(name, value)| {
+            let value = serde_json::from_str(value)
+                .ok()
+                .filter(|v: &serde_json::Value| v.is_object() || v.is_array())
+                .unwrap_or_else(|| serde_json::Value::String(value.clone()));
+            (name.to_string(), value)
+        });
+
+        serde_json::Value::Object(params.collect())
+    }
+}
+/// Builder of a message. Created by [`TelegramClient::send_message`].
 pub struct SendMessage<'a, 'b> {
-    token: &'a str,
-    channel_id: &'a Snowflake,
+    client: &'a TelegramClient,
+    chat: Option<&'b Snowflake>,
     text: String,
     media: Vec<TelegramMedia<'b>>,
+    silent: bool,
+    thread: Option<u64>,
+    reply_to: Option<u64>,
+    caption_mode: CaptionMode,
+    caption_length: usize,
+    template: Option<&'b str>,
+    button: Option<(String, String)>,
 }
 #[derive(Serialize)]
 pub struct TelegramMedia<'a> {
     pub r#type: TelegramMediaType,
-    pub media: &'a str,
+    /// URL, file id or `attach://<field>` of uploaded file.
+    pub media: Cow<'a, str>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub caption: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub parse_mode: Option<String>,
+    /// Title of an audio.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<&'a str>,
+    /// Performer of an audio.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub performer: Option<&'a str>,
+    /// Name and MIME type a document is uploaded with, if it is uploaded.
+    #[serde(skip)]
+    pub file: (Option<&'a str>, Option<&'a str>),
 }
-#[derive(Serialize)]
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum TelegramMediaType {
     Photo,
     Video,
+    Audio,
+    Document,
+}
+
+impl<'a> From<ForeignMedia<'a>> for TelegramMedia<'a> {
+    fn from(media: ForeignMedia<'a>) -> Self {
+        let r#type = match media {
+            ForeignMedia::Photo(_) => TelegramMediaType::Photo,
+            ForeignMedia::Video(_) | ForeignMedia::Animation(_) => TelegramMediaType::Video,
+            ForeignMedia::Audio { .. } => TelegramMediaType::Audio,
+            ForeignMedia::Document { .. } => TelegramMediaType::Document,
+        };
+        let (title, performer, file) = match media {
+            ForeignMedia::Audio {
+                title, performer, ..
+            } => (title, performer, (None, None)),
+            ForeignMedia::Document { filename, mime, .. } => (None, None, (filename, mime)),
+            _ => (None, None, (None, None)),
+        };
+
+        Self {
+            r#type,
+            media: Cow::Borrowed(media.url()),
+            caption: None,
+            parse_mode: None,
+            title,
+            performer,
+            file,
+        }
+    }
 }
 
 #[derive(Deserialize)]
@@ -75,6 +173,7 @@ enum TelegramResponse<T> {
     },
     Err {
         error_code: u32,
+        #[serde(default)]
         description: String,
         #[serde(default)]
         parameters: Option<TelegramRateLimitError>,
@@ -85,11 +184,121 @@ struct TelegramRateLimitError {
     retry_after: u64,
 }
 
-#[derive(Deserialize)]
+/// Received update, see [`TelegramClient::get_updates`].
+#[derive(Deserialize, Debug)]
+#[non_exhaustive]
+pub struct TelegramUpdate {
+    pub update_id: i64,
+    #[serde(default)]
+    pub message: Option<TelegramMessage>,
+    /// New post of a channel where the bot is an admin.
+    #[serde(default)]
+    pub channel_post: Option<TelegramMessage>,
+}
+#[derive(Deserialize, Debug, Default)]
+#[non_exhaustive]
+pub struct TelegramMessage {
+    pub message_id: u64,
+    #[serde(default)]
+    pub chat: Option<TelegramChat>,
+    /// Unix timestamp.
+    #[serde(default)]
+    pub date: u64,
+    /// Album the message belongs to.
+    #[serde(default)]
+    pub media_group_id: Option<String>,
+    /// Message is a channel post automatically forwarded to the discussion group.
+    #[serde(default)]
+    pub is_automatic_forward: bool,
+    #[serde(default)]
+    pub forward_origin: Option<TelegramMessageOrigin>,
+    #[serde(default)]
+    pub text: Option<String>,
+    /// Caption of a media message.
+    #[serde(default)]
+    pub caption: Option<String>,
+    /// Sizes of a photo, smallest first.
+    #[serde(default)]
+    pub photo: Vec<TelegramFile>,
+    #[serde(default)]
+    pub video: Option<TelegramFile>,
+    #[serde(default)]
+    pub audio: Option<TelegramFile>,
+    #[serde(default)]
+    pub document: Option<TelegramFile>,
+}
+#[derive(Deserialize, Debug)]
+#[non_exhaustive]
+pub struct TelegramChat {
+    pub id: i64,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub title: Option<String>,
+}
+/// User or bot, see [`TelegramClient::get_me`].
+#[derive(Deserialize, Debug)]
+#[non_exhaustive]
+pub struct TelegramUser {
+    pub id: u64,
+    #[serde(default)]
+    pub username: Option<String>,
+}
+/// Membership of a user in a chat, see [`TelegramClient::get_chat_member`].
+#[derive(Deserialize, Debug)]
+#[non_exhaustive]
+pub struct TelegramChatMember {
+    /// `creator`, `administrator`, `member`, `restricted`, `left` or
+    /// `kicked`.
+    pub status: String,
+    /// Whether an administrator of a channel can post there.
+    #[serde(default)]
+    pub can_post_messages: Option<bool>,
+}
+/// File uploaded to Telegram. Its id can be sent instead of URL.
+#[derive(Deserialize, Debug)]
+#[non_exhaustive]
+pub struct TelegramFile {
+    pub file_id: String,
+}
+#[derive(Deserialize, Debug)]
 #[non_exhaustive]
-struct TelegramMessage {
+pub struct TelegramMessageOrigin {
+    /// Id of the original message, for messages forwarded from channels.
+    #[serde(default)]
+    pub message_id: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct TelegramMessageId {
     message_id: u64,
 }
+#[derive(Deserialize)]
+struct TelegramExport {
+    messages: Vec<TelegramExportMessage>,
+}
+#[derive(Deserialize)]
+struct TelegramExportMessage {
+    #[serde(default)]
+    text: TelegramExportText,
+}
+/// Text of exported message, plain or split into formatted parts.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TelegramExportText {
+    Plain(String),
+    Parts(Vec<TelegramExportPart>),
+}
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TelegramExportPart {
+    Plain(String),
+    Formatted {
+        text: String,
+        #[serde(default)]
+        href: Option<String>,
+    },
+}
 
 pub struct ProtectedString<'a>(pub &'a str);
 
@@ -108,107 +317,1037 @@ impl<'a> Display for ProtectedString<'a> {
     }
 }
 
+/// Tags supported by Telegram HTML parse mode.
+pub const HTML_TAGS: [&str; 16] = [
+    "b",
+    "strong",
+    "i",
+    "em",
+    "u",
+    "ins",
+    "s",
+    "strike",
+    "del",
+    "span",
+    "tg-spoiler",
+    "a",
+    "code",
+    "pre",
+    "blockquote",
+    "tg-emoji",
+];
+
+/// Checks that `html` is accepted by Telegram: only [`HTML_TAGS`], every tag
+/// closed in order, and `<`, `>` and `&` only as part of tags and entities.
+pub fn validate_html(html: &str) -> Result<(), String> {
+    let mut open: Vec<&str> = Vec::new();
+    let mut rest = html;
+
+    while let Some(i) = rest.find(['<', '>', '&']) {
+        let tail = &rest[i..];
+        if tail.starts_with('>') {
+            return Err(format!("unescaped > at {}", html.len() - tail.len()));
+        }
+        if let Some(after) = tail.strip_prefix('&') {
+            let name = after
+                .split_once(';')
+                .map(|(name, _)| name)
+                .filter(|name| is_entity(name))
+                .ok_or_else(|| format!("unescaped & at {}", html.len() - tail.len()))?;
+            rest = &after[name.len() + 1..];
+            continue;
+        }
+
+        let end = tail
+            .find('>')
+            .ok_or_else(|| format!("unclosed tag at {}", html.len() - tail.len()))?;
+        let tag = &tail[1..end];
+        match tag.strip_prefix('/') {
+            Some(name) => match open.pop() {
+                Some(last) if last == name.trim() => {}
+                Some(last) => return Err(format!("</{name}> closes <{last}>")),
+                None => return Err(format!("</{name}> closes nothing")),
+            },
+            None => {
+                let name = tag.split_whitespace().next().unwrap_or_default();
+                if !HTML_TAGS.contains(&name) {
+                    return Err(format!("unsupported tag <{name}>"));
+                }
+                open.push(name);
+            }
+        }
+        rest = &tail[end + 1..];
+    }
+
+    match open.pop() {
+        Some(name) => Err(format!("<{name}> is not closed")),
+        None => Ok(()),
+    }
+}
+
+/// Whether `name` between `&` and `;` is an entity known to Telegram.
+fn is_entity(name: &str) -> bool {
+    match name.strip_prefix('#') {
+        Some(code) => match code.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).is_ok(),
+            None => code.parse::<u32>().is_ok(),
+        },
+        None => matches!(name, "lt" | "gt" | "amp" | "quot"),
+    }
+}
+
+/// Longest rate limit that [`TelegramClient`] calls wait out by default, in
+/// seconds.
+pub const MAX_FLOOD_WAIT: u64 = 60;
+
+/// Length limit of media captions, in UTF-16 code units after parsing.
+pub const MAX_CAPTION_LENGTH: usize = 1024;
+
+/// Length of `html` as Telegram counts it against [`MAX_CAPTION_LENGTH`]:
+/// UTF-16 code units of the text without tags, every entity is one unit.
+pub fn html_len(html: &str) -> usize {
+    let mut len = 0;
+    let mut rest = html;
+    while let Some(i) = rest.find(['<', '&']) {
+        len += text::utf16_len(&rest[..i]);
+        let tail = &rest[i..];
+        let end = match tail.strip_prefix('&') {
+            Some(after) => {
+                len += 1;
+                match after.split_once(';') {
+                    Some((name, _)) if is_entity(name) => name.len() + 2,
+                    _ => 1,
+                }
+            }
+            None => tail.find('>').map(|end| end + 1).unwrap_or(tail.len()),
+        };
+        rest = &tail[end..];
+    }
+
+    len + text::utf16_len(rest)
+}
+
+/// Shortens valid `html` to `units` as counted by [`html_len`], ending it with
+/// `…`. Never cuts inside a tag or an entity and closes tags left open.
+pub fn truncate_html(html: &str, units: usize) -> Cow<'_, str> {
+    if html_len(html) <= units {
+        return Cow::Borrowed(html);
+    }
+
+    let budget = units.saturating_sub(1);
+    let mut out = String::with_capacity(html.len());
+    let mut open: Vec<&str> = Vec::new();
+    let mut len = 0;
+    let mut rest = html;
+    while let Some(c) = rest.chars().next() {
+        let (token, width) = match c {
+            '<' => {
+                let end = rest.find('>').map(|end| end + 1).unwrap_or(rest.len());
+                let tag = rest[1..end].trim_end_matches('>');
+                match tag.strip_prefix('/') {
+                    Some(_) => {
+                        open.pop();
+                    }
+                    None => open.push(tag.split_whitespace().next().unwrap_or_default()),
+                }
+                (&rest[..end], 0)
+            }
+            '&' => {
+                let end = rest.find(';').map(|end| end + 1).unwrap_or(1);
+                (&rest[..end], 1)
+            }
+            c => (&rest[..c.len_utf8()], c.len_utf16()),
+        };
+        if len + width > budget {
+            break;
+        }
+        len += width;
+        out.push_str(token);
+        rest = &rest[token.len()..];
+    }
+
+    let kept = out.trim_end().len();
+    out.truncate(kept);
+    out.push('…');
+    for name in open.into_iter().rev() {
+        out.push_str("</");
+        out.push_str(name);
+        out.push('>');
+    }
+
+    Cow::Owned(out)
+}
+
+/// Returns `html` if it is valid for Telegram, see [`validate_html`].
+/// Otherwise, returns its text without tags, escaped, so the message is still
+/// sent as plain text.
+pub fn sanitize_html(html: &str) -> Cow<'_, str> {
+    if validate_html(html).is_ok() {
+        return Cow::Borrowed(html);
+    }
+
+    let mut plain = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        plain.push_str(&rest[..start]);
+        match rest[start..].find('>') {
+            Some(end) => rest = &rest[start + end + 1..],
+            None => {
+                plain.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+    plain.push_str(rest);
+    let plain = plain
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&amp;", "&");
+
+    Cow::Owned(ProtectedString(&plain).to_string())
+}
+
+/// Caption template used when account does not set one.
+pub const DEFAULT_TEMPLATE: &str = "{text}\n\nsrc: <a href=\"{url}\">{source}</a>";
+
+/// Builds caption of a foreign post. Template is Telegram HTML with
+/// placeholders `{text}` (text shortened by `mode`), `{source}`, `{url}`,
+/// `{likes}`, `{reposts}`, `{views}` and `{comments}`. Counters are empty if
+/// source does not provide them.
+pub fn caption<T: Display>(
+    foreign: &ForeignPost<'_, T>,
+    mode: CaptionMode,
+    length: usize,
+    template: Option<&str>,
+) -> String {
+    let url = foreign.url.to_string();
+    let body = match text::summarize(&foreign.text, mode, length) {
+        Some(summary) => format!(
+            "{}{} <a href=\"{}\">full text</a>",
+            ProtectedString(summary),
+            if summary.ends_with(['.', '!', '?', '…']) {
+                ""
+            } else {
+                "…"
+            },
+            url
+        ),
+        None => ProtectedString(&foreign.text).to_string(),
+    };
+    let source = ProtectedString(foreign.source).to_string();
+    let stat = |f: fn(&PostStats) -> u64| {
+        foreign
+            .stats
+            .as_ref()
+            .map(|s| f(s).to_string())
+            .unwrap_or_default()
+    };
+
+    text::render(
+        template.unwrap_or(DEFAULT_TEMPLATE),
+        &[
+            ("text", &body),
+            ("source", &source),
+            ("url", &url),
+            ("likes", &stat(|s| s.likes)),
+            ("reposts", &stat(|s| s.reposts)),
+            ("views", &stat(|s| s.views)),
+            ("comments", &stat(|s| s.comments)),
+        ],
+    )
+}
+
+/// Reads texts of messages from chat history exported by Telegram Desktop
+/// as JSON (`result.json`). Messages without text are skipped.
+pub fn export_texts(json: &str) -> Result<Vec<String>, serde_json::Error> {
+    let export: TelegramExport = serde_json::from_str(json)?;
+
+    Ok(export
+        .messages
+        .into_iter()
+        .map(|m| match m.text {
+            TelegramExportText::Plain(text) => text,
+            TelegramExportText::Parts(parts) => parts
+                .into_iter()
+                .map(|p| match p {
+                    TelegramExportPart::Plain(text)
+                    | TelegramExportPart::Formatted { text, .. } => text,
+                })
+                .collect(),
+        })
+        .filter(|text| !text.is_empty())
+        .collect())
+}
+
+/// Reads URLs of text links, like `src:` links to original posts, from chat
+/// history exported by Telegram Desktop as JSON.
+pub fn export_links(json: &str) -> Result<Vec<String>, serde_json::Error> {
+    let export: TelegramExport = serde_json::from_str(json)?;
+
+    Ok(export
+        .messages
+        .into_iter()
+        .flat_map(|m| match m.text {
+            TelegramExportText::Plain(_) => Vec::new(),
+            TelegramExportText::Parts(parts) => parts,
+        })
+        .filter_map(|p| match p {
+            TelegramExportPart::Formatted { href, .. } => href,
+            TelegramExportPart::Plain(_) => None,
+        })
+        .collect())
+}
+
+/// Errors of Telegram failing to get or process media by URL. Uploaded
+/// files may still be accepted.
+pub const MEDIA_URL_ERRORS: [&str; 6] = [
+    "wrong file identifier/HTTP URL specified",
+    "failed to get HTTP URL content",
+    "wrong type of the web page content",
+    "WEBPAGE_CURL_FAILED",
+    "WEBPAGE_MEDIA_EMPTY",
+    "IMAGE_PROCESS_FAILED",
+];
+
+/// Index of failed media in errors like `failed to send message #2 with the
+/// error message "WEBPAGE_CURL_FAILED"`, numbered from 1.
+fn failed_item(description: &str) -> Option<usize> {
+    let (_, rest) = description.split_once("message #")?;
+    let number = rest.split(|c: char| !c.is_ascii_digit()).next()?;
+
+    number.parse::<usize>().ok()?.checked_sub(1)
+}
+
+/// Parses response body of any Bot API method, like `{"ok": true, "result": ...}`.
+pub fn parse_response<T: DeserializeOwned>(json: &str) -> Result<T, TelegramError> {
+    let res: TelegramResponse<T> = serde_json::from_str(json).map_err(TelegramError::Scheme)?;
+
+    match res {
+        TelegramResponse::Ok { result } => Ok(result),
+
+        TelegramResponse::Err {
+            error_code: 429,
+            parameters: Some(TelegramRateLimitError { retry_after }),
+            ..
+        } => Err(TelegramError::RateLimited {
+            timeout: Duration::from_secs(retry_after),
+        }),
+
+        TelegramResponse::Err {
+            error_code,
+            description,
+            ..
+        } => Err(match error_code {
+            400 if description.contains("chat not found") => {
+                TelegramError::ChatNotFound { description }
+            }
+            403 => TelegramError::Kicked { description },
+            400 if description.contains("need administrator rights") => {
+                TelegramError::Kicked { description }
+            }
+            400 if MEDIA_URL_ERRORS.iter().any(|e| description.contains(e)) => {
+                TelegramError::BadMedia { description }
+            }
+            400 if description.contains("caption is too long") => TelegramError::CaptionTooLong,
+            _ => TelegramError::Server {
+                error_code,
+                description,
+            },
+        }),
+    }
+}
+
 impl TelegramClient {
     pub fn new(token: String, channel_id: Snowflake) -> Self {
-        Self { token, channel_id }
+        Self {
+            token,
+            channel_id,
+            base_url: "https://api.telegram.org".to_owned(),
+            transport: Arc::new(ReqwestTransport::default()),
+            media_cache: None,
+            max_flood_wait: Duration::from_secs(MAX_FLOOD_WAIT),
+            flood_until: Mutex::new(None),
+        }
+    }
+
+    /// Replaces API endpoint, e.g. with mock server address.
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into();
+
+        self
+    }
+
+    /// Replaces HTTP transport.
+    pub fn with_transport(mut self, transport: Arc<dyn Transport>) -> Self {
+        self.transport = transport;
+
+        self
+    }
+
+    /// Enables uploading media that Telegram fails to get by URL, see
+    /// [`SendMessage::send_messages`].
+    pub fn with_media_cache(mut self, media_cache: Arc<MediaCache>) -> Self {
+        self.media_cache = Some(media_cache);
+
+        self
+    }
+
+    /// Calls API method and returns its result.
+    async fn call<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: Vec<(&str, String)>,
+    ) -> Result<T, TelegramError> {
+        self.call_with_files(method, params, Vec::new()).await
+    }
+
+    /// Replaces the longest rate limit that calls wait out, see
+    /// [`MAX_FLOOD_WAIT`].
+    pub fn with_max_flood_wait(mut self, max_flood_wait: Duration) -> Self {
+        self.max_flood_wait = max_flood_wait;
+
+        self
+    }
+
+    /// Calls API method with uploaded files and returns its result. A rate
+    /// limit up to `max_flood_wait` is waited out and the call is repeated
+    /// once, other calls wait for it to end too.
+    async fn call_with_files<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: Vec<(&str, String)>,
+        files: Vec<RequestFile>,
+    ) -> Result<T, TelegramError> {
+        let mut request = params.into_iter().fold(
+            Request::post(format!("{}/bot{}/{method}", self.base_url, self.token)),
+            |r, (key, value)| r.query(key, value),
+        );
+        request.files = files;
+
+        let until = *self.flood_until.lock().expect("limit is not poisoned");
+        if let Some(until) = until {
+            time::sleep_until(until.into()).await;
+        }
+        match self.send(request.clone()).await {
+            Err(TelegramError::RateLimited { timeout }) if timeout <= self.max_flood_wait => {
+                let until = Instant::now() + timeout;
+                *self.flood_until.lock().expect("limit is not poisoned") = Some(until);
+                time::sleep_until(until.into()).await;
+                self.send(request).await
+            }
+            res => res,
+        }
+    }
+
+    async fn send<T: DeserializeOwned>(&self, request: Request) -> Result<T, TelegramError> {
+        let res = self
+            .transport
+            .send(request)
+            .await
+            .map_err(TelegramError::Http)?;
+
+        parse_response(&res)
     }
 
     pub fn send_message<'b>(&self) -> SendMessage<'_, 'b> {
         SendMessage {
-            token: &self.token,
-            channel_id: &self.channel_id,
+            client: self,
+            chat: None,
             text: String::new(),
             media: Vec::new(),
+            silent: false,
+            thread: None,
+            reply_to: None,
+            caption_mode: CaptionMode::Full,
+            caption_length: 0,
+            template: None,
+            button: None,
         }
     }
+
+    /// Replaces caption of a media message.
+    pub async fn edit_caption(
+        &self,
+        chat: &Snowflake,
+        message_id: u64,
+        caption: &str,
+    ) -> Result<(), TelegramError> {
+        let _: serde::de::IgnoredAny = self
+            .call(
+                "editMessageCaption",
+                vec![
+                    ("chat_id", chat.to_string()),
+                    ("message_id", message_id.to_string()),
+                    (
+                        "caption",
+                        truncate_html(&sanitize_html(caption), MAX_CAPTION_LENGTH).into_owned(),
+                    ),
+                    ("parse_mode", "HTML".to_owned()),
+                ],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Replaces text of a text message.
+    pub async fn edit_text(
+        &self,
+        chat: &Snowflake,
+        message_id: u64,
+        text: &str,
+    ) -> Result<(), TelegramError> {
+        let _: serde::de::IgnoredAny = self
+            .call(
+                "editMessageText",
+                vec![
+                    ("chat_id", chat.to_string()),
+                    ("message_id", message_id.to_string()),
+                    ("text", sanitize_html(text).into_owned()),
+                    ("parse_mode", "HTML".to_owned()),
+                    ("link_preview_options", "{\"is_disabled\":true}".to_owned()),
+                ],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Copies messages, like all messages of an album, to another chat
+    /// without re-uploading media. Returns ids of the copies, never empty:
+    /// copying nothing is [`TelegramError::NothingCopied`].
+    pub async fn copy_messages(
+        &self,
+        chat: &Snowflake,
+        from_chat: &Snowflake,
+        message_ids: &[u64],
+    ) -> Result<Vec<u64>, TelegramError> {
+        let res: Vec<TelegramMessageId> = self
+            .call(
+                "copyMessages",
+                vec![
+                    ("chat_id", chat.to_string()),
+                    ("from_chat_id", from_chat.to_string()),
+                    (
+                        "message_ids",
+                        serde_json::to_string(message_ids).map_err(TelegramError::Scheme)?,
+                    ),
+                ],
+            )
+            .await?;
+
+        if res.is_empty() {
+            return Err(TelegramError::NothingCopied);
+        }
+
+        Ok(res.into_iter().map(|m| m.message_id).collect())
+    }
+
+    /// Pins a message without notifying members.
+    pub async fn pin_message(
+        &self,
+        chat: &Snowflake,
+        message_id: u64,
+    ) -> Result<(), TelegramError> {
+        let _: serde::de::IgnoredAny = self
+            .call(
+                "pinChatMessage",
+                vec![
+                    ("chat_id", chat.to_string()),
+                    ("message_id", message_id.to_string()),
+                    ("disable_notification", "true".to_owned()),
+                ],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Unpins a pinned message.
+    pub async fn unpin_message(
+        &self,
+        chat: &Snowflake,
+        message_id: u64,
+    ) -> Result<(), TelegramError> {
+        let _: serde::de::IgnoredAny = self
+            .call(
+                "unpinChatMessage",
+                vec![
+                    ("chat_id", chat.to_string()),
+                    ("message_id", message_id.to_string()),
+                ],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Fetches the bot account, which also checks the token.
+    pub async fn get_me(&self) -> Result<TelegramUser, TelegramError> {
+        self.call("getMe", Vec::new()).await
+    }
+
+    /// Fetches membership of user `user_id` in a chat.
+    pub async fn get_chat_member(
+        &self,
+        chat: &Snowflake,
+        user_id: u64,
+    ) -> Result<TelegramChatMember, TelegramError> {
+        self.call(
+            "getChatMember",
+            vec![
+                ("chat_id", chat.to_string()),
+                ("user_id", user_id.to_string()),
+            ],
+        )
+        .await
+    }
+
+    /// Fetches new messages of chats with the bot, starting from `offset`.
+    pub async fn get_updates(
+        &self,
+        offset: Option<i64>,
+    ) -> Result<Vec<TelegramUpdate>, TelegramError> {
+        let mut params = vec![(
+            "allowed_updates",
+            "[\"message\",\"channel_post\"]".to_owned(),
+        )];
+        if let Some(offset) = offset {
+            params.push(("offset", offset.to_string()));
+        }
+
+        self.call("getUpdates", params).await
+    }
 }
+impl Default for TelegramExportText {
+    fn default() -> Self {
+        Self::Plain(String::new())
+    }
+}
+
+impl SentMessages {
+    /// Messages copied by [`TelegramClient::copy_messages`], `message_ids`
+    /// should not be empty.
+    pub fn copied(message_ids: Vec<u64>) -> Self {
+        Self {
+            indexes: (0..message_ids.len()).collect(),
+            messages: message_ids
+                .into_iter()
+                .map(|message_id| TelegramMessage {
+                    message_id,
+                    ..Default::default()
+                })
+                .collect(),
+            dropped: Vec::new(),
+        }
+    }
+
+    /// Sent messages with indexes of their media.
+    pub fn by_media(&self) -> impl Iterator<Item = (usize, &TelegramMessage)> {
+        self.indexes.iter().copied().zip(&self.messages)
+    }
+}
+
+impl TelegramChat {
+    /// Whether `chat`, given by id or `@username`, is this chat.
+    pub fn is(&self, chat: &Snowflake) -> bool {
+        let chat = chat.to_string();
+        match (chat.strip_prefix('@'), &self.username) {
+            (Some(name), Some(username)) => name.eq_ignore_ascii_case(username),
+            _ => chat == self.id.to_string(),
+        }
+    }
+}
+
+impl TelegramChatMember {
+    /// Whether the member can post to a channel: its creator or an
+    /// administrator allowed to post messages.
+    pub fn can_post(&self) -> bool {
+        match self.status.as_str() {
+            "creator" => true,
+            "administrator" => self.can_post_messages.unwrap_or(true),
+            _ => false,
+        }
+    }
+}
+
+impl TelegramMessage {
+    /// Text or caption of the message.
+    pub fn content(&self) -> &str {
+        self.text
+            .as_deref()
+            .or(self.caption.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// Uploaded file of the message that was sent as `media`, the largest
+    /// size of a photo.
+    pub fn file_of(&self, media: &Media) -> Option<&TelegramFile> {
+        match media {
+            Media::Photo(_) => self.photo.last(),
+            Media::Video(_) | Media::Animation(_) => self.video.as_ref(),
+            Media::Audio { .. } => self.audio.as_ref(),
+            Media::Document { .. } => self.document.as_ref(),
+        }
+    }
+}
+
 impl<'a, 'b> SendMessage<'a, 'b> {
-    pub fn by_foreign<T: Display>(mut self, foreign: &ForeignPost<'b, T>) -> Self {
-        self.text = format!(
-            "{}\n\nsrc: <a href=\"{}\">{}</a>",
-            ProtectedString(foreign.text),
-            foreign.url,
-            ProtectedString(foreign.source)
-        );
-        self.media = foreign
+    /// Sends the message to another chat instead of the channel.
+    pub fn chat(mut self, chat: &'b Snowflake) -> Self {
+        self.chat = Some(chat);
+
+        self
+    }
+
+    /// Sets how much of the foreign post text goes to the caption. Should be
+    /// called before [`Self::by_foreign`].
+    pub fn summary(mut self, mode: CaptionMode, length: usize) -> Self {
+        self.caption_mode = mode;
+        self.caption_length = length;
+
+        self
+    }
+
+    /// Sets caption template, see [`caption`]. Should be called before
+    /// [`Self::by_foreign`].
+    pub fn template(mut self, template: Option<&'b str>) -> Self {
+        self.template = template;
+
+        self
+    }
+
+    /// Sets caption. Should be valid Telegram HTML, see [`ProtectedString`].
+    pub fn text(mut self, text: impl Into<String>) -> Self {
+        self.text = text.into();
+
+        self
+    }
+
+    /// Sets media of the message. Telegram groups audio only with audio and
+    /// documents only with documents, so they are sent in their own groups,
+    /// see [`SendMessage::send_messages`].
+    pub fn media(mut self, media: impl IntoIterator<Item = ForeignMedia<'b>>) -> Self {
+        self.media = media.into_iter().map(TelegramMedia::from).collect();
+
+        self
+    }
+
+    /// Adds a button with a link under a text message. Media groups can not
+    /// have buttons, so it is ignored for them.
+    pub fn button(mut self, text: impl Into<String>, url: impl Into<String>) -> Self {
+        self.button = Some((text.into(), url.into()));
+
+        self
+    }
+
+    /// Replaces media given by URL with links to them at the end of the text,
+    /// for posts whose media Telegram can not get. Media given by file id is
+    /// dropped.
+    pub fn media_as_links(mut self) -> Self {
+        let links: Vec<String> = self
             .media
-            .iter()
-            .map(|f| match f {
-                ForeignMedia::Photo(media) => TelegramMedia {
-                    r#type: TelegramMediaType::Photo,
-                    media,
-                    caption: None,
-                    parse_mode: None,
-                },
-                ForeignMedia::Video(media) => TelegramMedia {
-                    r#type: TelegramMediaType::Video,
-                    media,
-                    caption: None,
-                    parse_mode: None,
-                },
+            .drain(..)
+            .filter(|m| m.media.starts_with("http"))
+            .map(|m| {
+                let kind = match m.r#type {
+                    TelegramMediaType::Photo => "photo",
+                    TelegramMediaType::Video => "video",
+                    TelegramMediaType::Audio => "audio",
+                    TelegramMediaType::Document => "file",
+                };
+                format!("<a href=\"{}\">{kind}</a>", ProtectedString(&m.media))
             })
             .collect();
+        if !links.is_empty() {
+            self.text.push_str("\n\n");
+            self.text.push_str(&links.join(" · "));
+        }
 
         self
     }
 
-    pub async fn send(mut self) -> Result<u64, TelegramError> {
+    /// Sends the message without notification.
+    pub fn silent(mut self, silent: bool) -> Self {
+        self.silent = silent;
+
+        self
+    }
+
+    /// Sends the message to a forum topic.
+    pub fn thread(mut self, thread_id: u64) -> Self {
+        self.thread = Some(thread_id);
+
+        self
+    }
+
+    /// Sends the message as a reply.
+    pub fn reply_to(mut self, message_id: u64) -> Self {
+        self.reply_to = Some(message_id);
+
+        self
+    }
+
+    /// Fills caption and media from a post.
+    pub fn by_post(self, post: &'b Post) -> Self {
+        self.by_foreign(&post.as_foreign())
+    }
+
+    /// Fills caption and media from a foreign post.
+    pub fn by_foreign<T: Display>(self, foreign: &ForeignPost<'b, T>) -> Self {
+        let text = caption(
+            foreign,
+            self.caption_mode,
+            self.caption_length,
+            self.template,
+        );
+
+        self.text(text).media(foreign.media.iter().copied())
+    }
+
+    /// Sends the message as a media group, or as a text message if there is no
+    /// media. Returns id of the first message.
+    pub async fn send(self) -> Result<u64, TelegramError> {
+        Ok(self.send_messages().await?.messages[0].message_id)
+    }
+
+    /// Renders the request the message would be sent with, without sending
+    /// it. Media that Telegram fails to get are not uploaded, so they are
+    /// given as by [`SendMessage::send_messages`] at first. Media of different
+    /// groups are rendered in one request.
+    pub fn render(mut self) -> Result<RenderedMessage, TelegramError> {
+        let mut rendered = self.params()?;
+        if !self.media.is_empty() {
+            let media = serde_json::to_string(&self.media).map_err(TelegramError::Scheme)?;
+            rendered.params.push(("media", media));
+        }
+
+        Ok(rendered)
+    }
+
+    /// Like [`SendMessage::send`], but returns all sent messages.
+    ///
+    /// Photos and videos are sent in one group, audio and documents in groups
+    /// of their own after it, in order of their first media. If a group after
+    /// the first one fails, its media are dropped, as the post is sent already.
+    ///
+    /// If Telegram fails to get media by URL and the client has a media
+    /// cache, the failed media (or all media given by URL, if Telegram does not
+    /// tell which one failed) are downloaded and uploaded instead. Media that
+    /// Telegram still rejects is dropped and the rest is sent.
+    pub async fn send_messages(mut self) -> Result<SentMessages, TelegramError> {
+        let RenderedMessage { method, params } = self.params()?;
         if self.media.is_empty() {
-            unimplemented!("Sending text-only messages is not supported");
+            let res: TelegramMessage = self.client.call(method, params).await?;
+
+            return Ok(SentMessages {
+                messages: vec![res],
+                indexes: vec![0],
+                dropped: Vec::new(),
+            });
         }
 
+        // indexes of media of every group, photos and videos go together
+        let mut groups: Vec<(TelegramMediaType, Vec<usize>)> = Vec::new();
+        for (i, media) in self.media.iter().enumerate() {
+            let kind = match media.r#type {
+                TelegramMediaType::Video => TelegramMediaType::Photo,
+                kind => kind,
+            };
+            match groups.iter_mut().find(|(k, _)| *k == kind) {
+                Some((_, group)) => group.push(i),
+                None => groups.push((kind, vec![i])),
+            }
+        }
+        let mut media: Vec<_> = self.media.drain(..).map(Some).collect();
+        let mut sent = SentMessages {
+            messages: Vec::new(),
+            indexes: Vec::new(),
+            dropped: Vec::new(),
+        };
+        for (n, (_, indexes)) in groups.into_iter().enumerate() {
+            let group = indexes.iter().filter_map(|&i| media[i].take()).collect();
+            match self
+                .send_group(method, &params, group, indexes.clone())
+                .await
+            {
+                Ok(group) => {
+                    sent.messages.extend(group.messages);
+                    sent.indexes.extend(group.indexes);
+                    sent.dropped.extend(group.dropped);
+                }
+                Err(err) if n == 0 => return Err(err),
+                Err(_) => sent.dropped.extend(indexes),
+            }
+        }
+        sent.dropped.sort_unstable();
+
+        Ok(sent)
+    }
+
+    /// Sends `media` with original `indexes` as one media group, see
+    /// [`SendMessage::send_messages`].
+    async fn send_group(
+        &self,
+        method: &str,
+        params: &[(&'static str, String)],
+        mut media: Vec<TelegramMedia<'_>>,
+        indexes: Vec<usize>,
+    ) -> Result<SentMessages, TelegramError> {
+        // original index of every media left
+        let mut left = indexes;
+        let mut files = Vec::new();
+        let mut dropped = Vec::new();
+        let messages: Vec<TelegramMessage> = loop {
+            let json = serde_json::to_string(&media).map_err(TelegramError::Scheme)?;
+            let res = self
+                .client
+                .call_with_files(
+                    method,
+                    params.iter().cloned().chain([("media", json)]).collect(),
+                    files.clone(),
+                )
+                .await;
+            let err = match res {
+                Ok(messages) => break messages,
+                Err(err) => err,
+            };
+            let failed = match &err {
+                TelegramError::BadMedia { description }
+                | TelegramError::Server { description, .. } => {
+                    failed_item(description).filter(|&i| i < media.len())
+                }
+                _ => return Err(err),
+            };
+
+            // first try to upload media that Telegram could not get by URL
+            if let (TelegramError::BadMedia { .. }, Some(media_cache)) =
+                (&err, &self.client.media_cache)
+            {
+                let targets = match failed {
+                    Some(i) => vec![i],
+                    None => (0..media.len()).collect(),
+                };
+                match upload(media_cache, &mut media, &left, &targets).await {
+                    Ok(uploaded) if !uploaded.is_empty() => {
+                        files.extend(uploaded);
+                        continue;
+                    }
+                    Ok(_) => {}
+                    Err(e) if failed.is_none() => return Err(TelegramError::Media(e)),
+                    // not available at all, dropped below
+                    Err(_) => {}
+                }
+            }
+
+            // then drop the rejected media, if something is left
+            match failed {
+                Some(i) if media.len() > 1 => {
+                    let item = media.remove(i);
+                    let original = left.remove(i);
+                    files.retain(|f: &RequestFile| f.field != format!("file{original}"));
+                    if i == 0 {
+                        media[0].caption = item.caption;
+                        media[0].parse_mode = item.parse_mode;
+                    }
+                    dropped.push(original);
+                }
+                _ => return Err(err),
+            }
+        };
+        if messages.is_empty() {
+            return Err(TelegramError::Scheme(serde::de::Error::custom(
+                "empty list of sent messages",
+            )));
+        }
+        dropped.sort_unstable();
+
+        Ok(SentMessages {
+            messages,
+            indexes: left,
+            dropped,
+        })
+    }
+
+    /// API method and parameters of the message, without the media of a
+    /// media group, which are set on `self.media` with the caption.
+    fn params(&mut self) -> Result<RenderedMessage, TelegramError> {
+        let chat = self.chat.unwrap_or(&self.client.channel_id).to_string();
+        let mut params = vec![("chat_id", chat)];
+        if self.silent {
+            params.push(("disable_notification", "true".to_owned()));
+        }
+        if let Some(thread) = self.thread {
+            params.push(("message_thread_id", thread.to_string()));
+        }
+        if let Some(reply_to) = self.reply_to {
+            params.push(("reply_parameters", format!("{{\"message_id\":{reply_to}}}")));
+        }
+
+        if self.media.is_empty() {
+            params.extend([
+                ("text", sanitize_html(&self.text).into_owned()),
+                ("parse_mode", "HTML".to_owned()),
+                ("link_preview_options", "{\"is_disabled\":true}".to_owned()),
+            ]);
+            if let Some((text, url)) = &self.button {
+                let markup =
+                    serde_json::json!({ "inline_keyboard": [[{ "text": text, "url": url }]] });
+                params.push(("reply_markup", markup.to_string()));
+            }
+
+            return Ok(RenderedMessage {
+                method: "sendMessage",
+                params,
+            });
+        }
+
+        let text = sanitize_html(&self.text).into_owned();
+        if html_len(&text) > MAX_CAPTION_LENGTH {
+            return Err(TelegramError::CaptionTooLong);
+        }
         if let Some(TelegramMedia {
             caption,
             parse_mode,
             ..
         }) = self.media.get_mut(0)
         {
-            *caption = Some(self.text);
+            *caption = Some(text);
             *parse_mode = Some("HTML".to_owned());
         }
 
-        let client = Client::new();
-        let res = client
-            .post(format!(
-                "https://api.telegram.org/bot{}/sendMediaGroup",
-                self.token
-            ))
-            .query(&[
-                ("chat_id", self.channel_id.to_string()),
-                (
-                    "media",
-                    serde_json::to_string(&self.media).map_err(TelegramError::Scheme)?,
-                ),
-            ])
-            .send()
-            .await
-            .map_err(TelegramError::Http)?
-            .text()
-            .await
-            .map_err(TelegramError::Http)?;
-
-        let res: TelegramResponse<Vec<TelegramMessage>> =
-            serde_json::from_str(&res).map_err(TelegramError::Scheme)?;
-
-        match res {
-            TelegramResponse::Ok { result } => Ok(result[0].message_id),
-
-            TelegramResponse::Err {
-                error_code: 429,
-                parameters: Some(TelegramRateLimitError { retry_after }),
-                ..
-            } => Err(TelegramError::RateLimited {
-                timeout: Duration::from_secs(retry_after),
-            }),
+        Ok(RenderedMessage {
+            method: "sendMediaGroup",
+            params,
+        })
+    }
+}
 
-            TelegramResponse::Err {
-                error_code,
-                description,
-                ..
-            } => Err(TelegramError::Server {
-                error_code,
-                description,
-            }),
+/// Replaces media given by URL with uploaded files, returns the files.
+async fn upload(
+    media_cache: &MediaCache,
+    media: &mut [TelegramMedia<'_>],
+    left: &[usize],
+    targets: &[usize],
+) -> Result<Vec<RequestFile>, MediaError> {
+    let mut files = Vec::new();
+    for &i in targets {
+        if !media[i].media.starts_with("http") {
+            continue;
         }
+
+        let bytes = media_cache.get(&media[i].media).await?;
+        let field = format!("file{}", left[i]);
+        media[i].media = Cow::Owned(format!("attach://{field}"));
+        let (name, mime) = media[i].file;
+        files.push(RequestFile {
+            field,
+            bytes,
+            name: name.map(str::to_owned),
+            mime: mime.map(str::to_owned),
+        });
     }
+
+    Ok(files)
 }
@@ -0,0 +1,391 @@
+use std::borrow::Cow;
+
+use encoding_rs::{Encoding, WINDOWS_1251, WINDOWS_1252};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use unicode_normalization::{is_nfc_quick, IsNormalized, UnicodeNormalization};
+#[cfg(feature = "lang-detect")]
+use whatlang::Lang;
+
+use crate::config::{CaptionMode, Cleanup, Strip};
+
+/// VK wiki-mentions (`[club1|Name]`) and plain `@mentions`.
+static MENTIONS: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"\[(?:id|club|public|event)\d+\|[^\]]*\]|\B[@*][A-Za-z0-9_.]+(?: \([^)\n]*\))?")
+        .expect("valid regex")
+});
+/// External URLs, with or without scheme for well-known shorteners.
+static LINKS: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?:https?://|\bwww\.|\b(?:t\.me|vk\.cc|vk\.com)/)\S+").expect("valid regex")
+});
+/// Single emoji with modifiers.
+const EMOJI: &str = r"\p{Extended_Pictographic}(?:\x{FE0F}|\x{200D}\p{Extended_Pictographic}|[\x{1F3FB}-\x{1F3FF}])*";
+/// More than [`MAX_EMOJI_RUN`] emoji in a row.
+static EMOJI_SPAM: Lazy<Regex> =
+    Lazy::new(|| Regex::new(&format!(r"(?:{EMOJI}[ \t]?){{4,}}")).expect("valid regex"));
+static SINGLE_EMOJI: Lazy<Regex> = Lazy::new(|| Regex::new(EMOJI).expect("valid regex"));
+/// Lines at the end of text that contain nothing but emoji.
+static TRAILING_EMOJI: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(&format!(r"(?:\n[ \t]*(?:{EMOJI}[ \t]*)+)+\s*$")).expect("valid regex")
+});
+/// Two or more blank lines.
+static BLANK_LINES: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\n[ \t]*\n(?:[ \t]*\n)+").expect("valid regex"));
+const MAX_EMOJI_RUN: usize = 3;
+/// Runs of two or more `\xNN` byte escapes.
+static BYTE_ESCAPES: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?:\\x[0-9A-Fa-f]{2}){2,}").expect("valid regex"));
+/// Spaces left around removed fragments.
+static SPACES: Lazy<Regex> = Lazy::new(|| Regex::new(r"[ \t]{2,}").expect("valid regex"));
+
+/// Removes parts of the text selected by `what`. Returns text unchanged if
+/// nothing was removed.
+pub fn strip<'a>(text: Cow<'a, str>, what: &[Strip]) -> Cow<'a, str> {
+    let mut res = text;
+
+    for strip in what {
+        let re = match strip {
+            Strip::Mentions => &MENTIONS,
+            Strip::Links => &LINKS,
+        };
+        if let Cow::Owned(s) = re.replace_all(&res, "") {
+            res = Cow::Owned(tidy(&s));
+        }
+    }
+
+    res
+}
+
+/// Applies cleanup passes selected by `what`. Returns text unchanged if
+/// nothing was cleaned.
+pub fn cleanup<'a>(text: Cow<'a, str>, what: &[Cleanup]) -> Cow<'a, str> {
+    let mut res = text;
+
+    if what.contains(&Cleanup::Emoji) {
+        if let Cow::Owned(s) = TRAILING_EMOJI.replace(&res, "") {
+            res = Cow::Owned(s);
+        }
+        if let Cow::Owned(s) = EMOJI_SPAM.replace_all(&res, |c: &regex::Captures| {
+            let run = &c[0];
+            let emoji = SINGLE_EMOJI
+                .find_iter(run)
+                .take(MAX_EMOJI_RUN)
+                .map(|m| m.as_str());
+            // keep the space separating the run from next word
+            let space = &run[run.trim_end_matches([' ', '\t']).len()..];
+            emoji.chain([space]).collect::<String>()
+        }) {
+            res = Cow::Owned(s);
+        }
+    }
+    if what.contains(&Cleanup::Quotes) && res.contains(['“', '”', '„', '‟', '‘', '’', '‚', '‛'])
+    {
+        res = Cow::Owned(
+            res.chars()
+                .map(|c| match c {
+                    '“' | '”' | '„' | '‟' => '"',
+                    '‘' | '’' | '‚' | '‛' => '\'',
+                    c => c,
+                })
+                .collect(),
+        );
+    }
+    if what.contains(&Cleanup::TrailingWhitespace)
+        && (res.ends_with(char::is_whitespace) || res.lines().any(|l| l.ends_with([' ', '\t'])))
+    {
+        res = Cow::Owned(
+            res.lines()
+                .map(str::trim_end)
+                .collect::<Vec<_>>()
+                .join("\n")
+                .trim_end()
+                .to_owned(),
+        );
+    }
+    if what.contains(&Cleanup::BlankLines) {
+        if let Cow::Owned(s) = BLANK_LINES.replace_all(&res, "\n\n") {
+            res = Cow::Owned(s);
+        }
+    }
+
+    res
+}
+
+/// Repairs text mangled by sources: decodes runs of `\xNN` byte escapes,
+/// as UTF-8 or else as Windows-1251, fixes words of UTF-8 that were decoded
+/// as Windows-1251 or Windows-1252 (like `РџСЂРёРІРµС‚` or `cafÃ©`) and
+/// normalizes the text to NFC. Returns text unchanged if it was fine.
+pub fn normalize(text: Cow<'_, str>) -> Cow<'_, str> {
+    let mut res = text;
+
+    if let Cow::Owned(s) = BYTE_ESCAPES.replace_all(&res, |c: &regex::Captures| {
+        let bytes: Vec<u8> = c[0]
+            .split("\\x")
+            .skip(1)
+            .filter_map(|hex| u8::from_str_radix(hex, 16).ok())
+            .collect();
+        match String::from_utf8(bytes) {
+            Ok(s) => s,
+            Err(e) => WINDOWS_1251.decode(e.as_bytes()).0.into_owned(),
+        }
+    }) {
+        res = Cow::Owned(s);
+    }
+    if !res.is_ascii() {
+        let mut fixed = false;
+        let words: Vec<Cow<str>> = res
+            .split_inclusive(char::is_whitespace)
+            .map(|word| match unmangle(word) {
+                Some(s) => {
+                    fixed = true;
+                    Cow::Owned(s)
+                }
+                None => Cow::Borrowed(word),
+            })
+            .collect();
+        if fixed {
+            res = Cow::Owned(words.concat());
+        }
+    }
+    if is_nfc_quick(res.chars()) != IsNormalized::Yes {
+        let nfc: String = res.nfc().collect();
+        if nfc != res {
+            res = Cow::Owned(nfc);
+        }
+    }
+
+    res
+}
+
+/// Original of `word` if it is UTF-8 decoded as Windows-1251 or
+/// Windows-1252. Real words of these encodings are almost never valid UTF-8,
+/// as letters outside ASCII are not followed by continuation bytes.
+fn unmangle(word: &str) -> Option<String> {
+    if word.is_ascii() {
+        return None;
+    }
+    [WINDOWS_1251, WINDOWS_1252]
+        .into_iter()
+        .find_map(|encoding: &Encoding| {
+            let (bytes, _, unmappable) = encoding.encode(word);
+            if unmappable {
+                return None;
+            }
+            String::from_utf8(bytes.into_owned()).ok()
+        })
+        .filter(|s| s != word)
+}
+
+/// Collapses repeated spaces and trims every line.
+fn tidy(text: &str) -> String {
+    let text = SPACES.replace_all(text, " ");
+
+    text.lines()
+        .map(str::trim)
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_owned()
+}
+
+/// Replaces `{name}` placeholders of the template with values. Unknown
+/// placeholders are kept as is.
+pub fn render(template: &str, vars: &[(&str, &str)]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let value = rest.find('}').and_then(|end| {
+            let name = &rest[1..end];
+            vars.iter()
+                .find(|(k, _)| *k == name)
+                .map(|(_, v)| (*v, end + 1))
+        });
+        match value {
+            Some((value, len)) => {
+                out.push_str(value);
+                rest = &rest[len..];
+            }
+            None => {
+                out.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+
+    out
+}
+
+/// Returns the beginning of the text selected by `mode`, or [`None`] if the
+/// text is short enough to be posted as is.
+pub fn summarize(text: &str, mode: CaptionMode, length: usize) -> Option<&str> {
+    let end = match mode {
+        CaptionMode::Full => return None,
+        CaptionMode::FirstSentence => first_sentence_end(text)?,
+        CaptionMode::Truncate => {
+            let end = utf16_boundary(text, length)?;
+            // prefer to cut at word boundary
+            text[..end]
+                .rfind(char::is_whitespace)
+                .filter(|&i| i > end / 2)
+                .unwrap_or(end)
+        }
+    };
+
+    let summary = text[..end].trim_end();
+    (!summary.is_empty() && summary.len() < text.trim_end().len()).then_some(summary)
+}
+
+/// Length of the text as Telegram counts it, in UTF-16 code units. Most emoji
+/// take two units.
+pub fn utf16_len(text: &str) -> usize {
+    text.encode_utf16().count()
+}
+
+/// Finds index of the first char past `units` UTF-16 code units, or [`None`]
+/// if the whole text fits.
+fn utf16_boundary(text: &str, units: usize) -> Option<usize> {
+    let mut len = 0;
+    text.char_indices().find_map(|(i, c)| {
+        len += c.len_utf16();
+        (len > units).then_some(i)
+    })
+}
+
+/// Abbreviations followed by a dot that do not end a sentence, besides
+/// initials.
+const ABBREVIATIONS: &[&str] = &[
+    "mr", "mrs", "ms", "dr", "prof", "st", "vs", "e.g", "i.e", "т.е",
+];
+
+/// Finds end of the first sentence or line.
+fn first_sentence_end(text: &str) -> Option<usize> {
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        let end = c == '\n'
+            || (matches!(c, '.' | '!' | '?' | '…')
+                && chars
+                    .peek()
+                    .map(|(_, next)| next.is_whitespace())
+                    .unwrap_or(true)
+                && !(c == '.' && is_abbreviation(&text[..i])));
+        if end {
+            return Some(i + c.len_utf8());
+        }
+    }
+
+    None
+}
+
+/// Whether the last word of `text` is an abbreviation or an initial.
+fn is_abbreviation(text: &str) -> bool {
+    let word = text
+        .rsplit(|c: char| c.is_whitespace() || c == '(')
+        .next()
+        .unwrap_or(text)
+        .to_lowercase();
+    let mut chars = word.chars();
+
+    matches!((chars.next(), chars.next()), (Some(c), None) if c.is_alphabetic())
+        || ABBREVIATIONS.contains(&word.as_str())
+}
+
+/// ISO 639-1 codes of languages known to [`whatlang`].
+#[cfg(feature = "lang-detect")]
+const LANG_CODES: &[(&str, &str)] = &[
+    ("eo", "epo"),
+    ("en", "eng"),
+    ("ru", "rus"),
+    ("zh", "cmn"),
+    ("es", "spa"),
+    ("pt", "por"),
+    ("it", "ita"),
+    ("bn", "ben"),
+    ("fr", "fra"),
+    ("de", "deu"),
+    ("uk", "ukr"),
+    ("ka", "kat"),
+    ("ar", "ara"),
+    ("hi", "hin"),
+    ("ja", "jpn"),
+    ("he", "heb"),
+    ("yi", "yid"),
+    ("pl", "pol"),
+    ("am", "amh"),
+    ("jv", "jav"),
+    ("ko", "kor"),
+    ("nb", "nob"),
+    ("da", "dan"),
+    ("sv", "swe"),
+    ("fi", "fin"),
+    ("tr", "tur"),
+    ("nl", "nld"),
+    ("hu", "hun"),
+    ("cs", "ces"),
+    ("el", "ell"),
+    ("bg", "bul"),
+    ("be", "bel"),
+    ("mr", "mar"),
+    ("kn", "kan"),
+    ("ro", "ron"),
+    ("sl", "slv"),
+    ("hr", "hrv"),
+    ("sr", "srp"),
+    ("mk", "mkd"),
+    ("lt", "lit"),
+    ("lv", "lav"),
+    ("et", "est"),
+    ("ta", "tam"),
+    ("vi", "vie"),
+    ("ur", "urd"),
+    ("th", "tha"),
+    ("gu", "guj"),
+    ("uz", "uzb"),
+    ("pa", "pan"),
+    ("az", "aze"),
+    ("id", "ind"),
+    ("te", "tel"),
+    ("fa", "pes"),
+    ("ml", "mal"),
+    ("or", "ori"),
+    ("my", "mya"),
+    ("ne", "nep"),
+    ("si", "sin"),
+    ("km", "khm"),
+    ("tk", "tuk"),
+    ("ak", "aka"),
+    ("zu", "zul"),
+    ("sn", "sna"),
+    ("af", "afr"),
+    ("la", "lat"),
+    ("sk", "slk"),
+    ("ca", "cat"),
+    ("tl", "tgl"),
+    ("hy", "hye"),
+    ("cy", "cym"),
+];
+
+/// Finds language by ISO 639-1 (`ru`) or ISO 639-3 (`rus`) code.
+#[cfg(feature = "lang-detect")]
+pub fn lang_from_code(code: &str) -> Option<Lang> {
+    let code = code.to_lowercase();
+
+    LANG_CODES
+        .iter()
+        .find(|(short, _)| *short == code)
+        .and_then(|&(_, long)| Lang::from_code(long))
+        .or_else(|| Lang::from_code(code))
+}
+
+/// Detects language of the text. Returns [`None`] if detection is not reliable.
+#[cfg(feature = "lang-detect")]
+pub fn detect_language(text: &str) -> Option<Lang> {
+    whatlang::detect(text)
+        .filter(|info| info.is_reliable())
+        .map(|info| info.lang())
+}
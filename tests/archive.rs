@@ -0,0 +1,61 @@
+#![cfg(feature = "archive")]
+
+use secretfop::{
+    archive::Archive,
+    config::Snowflake,
+    sources::{Media, Post},
+};
+
+fn post(id: u64, text: &str) -> Post {
+    Post {
+        id: Snowflake::Number(id),
+        source_id: Snowflake::Number(123456),
+        date: 1677600000 + id,
+        text: text.to_owned(),
+        media: vec![Media::Photo(format!("https://sun9-1.userapi.com/{id}.jpg"))],
+        extra: Vec::new(),
+        stats: None,
+        source: "vk // Fox Art".to_owned(),
+        url: format!("https://vk.com/wall-123456_{id}"),
+        messages: Vec::new(),
+    }
+}
+
+#[test]
+fn search_finds_archived_posts() {
+    let archive = Archive::in_memory().expect("archive opens");
+    let channel = Snowflake::String("@foxart".to_owned());
+    archive
+        .record(
+            "vk",
+            &post(1, "Fresh art by Pavel"),
+            &channel,
+            101,
+            1677700000,
+        )
+        .expect("post is archived");
+    archive
+        .record(
+            "vk",
+            &post(2, "Sketch 100% done"),
+            &channel,
+            102,
+            1677800000,
+        )
+        .expect("post is archived");
+
+    let found = archive.search("PAVEL", 10).expect("search works");
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].message_id, 101);
+    assert_eq!(found[0].media, ["https://sun9-1.userapi.com/1.jpg"]);
+    assert_eq!(found[0].chat, "@foxart");
+
+    // newest first, `%` is not a wildcard
+    let found = archive.search("fox art", 10).expect("search works");
+    assert_eq!(
+        found.iter().map(|p| p.message_id).collect::<Vec<_>>(),
+        [102, 101]
+    );
+    assert_eq!(archive.search("0%", 10).expect("search works").len(), 1);
+    assert!(archive.search("1%", 10).expect("search works").is_empty());
+}
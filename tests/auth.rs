@@ -0,0 +1,123 @@
+use secretfop::auth::{set_config_keys, AuthError, Pkce, TwitterOAuth};
+use wiremock::{
+    matchers::{body_string_contains, method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+#[test]
+fn config_keys_are_replaced_in_place() {
+    let text = "# tokens\nvk_token: old\ntelegram_token: token\n";
+
+    let text = set_config_keys(text, &[("vk_token", "new"), ("vk_token_kind", "user")]);
+
+    assert_eq!(
+        text,
+        "# tokens\nvk_token: \"new\"\ntelegram_token: token\nvk_token_kind: \"user\"\n"
+    );
+}
+
+#[test]
+fn pkce_challenge_is_s256_of_verifier() {
+    // RFC 7636, appendix B
+    let pkce = Pkce::from_verifier("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk");
+
+    assert_eq!(
+        pkce.challenge,
+        "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+    );
+}
+
+#[tokio::test]
+async fn twitter_code_is_exchanged_with_verifier() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/2/oauth2/token"))
+        .and(body_string_contains("grant_type=authorization_code"))
+        .and(body_string_contains("code=c0de"))
+        .and(body_string_contains("code_verifier=verifier"))
+        .and(body_string_contains("client_id=app"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(
+            r#"{"token_type":"bearer","expires_in":7200,"access_token":"abc","refresh_token":"def"}"#,
+        ))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let oauth = TwitterOAuth::new("app")
+        .with_pkce(Pkce::from_verifier("verifier"), "st4te")
+        .with_token_url(format!("{}/2/oauth2/token", server.uri()));
+
+    let mismatch = oauth
+        .token("http://127.0.0.1/callback?state=other&code=c0de")
+        .await;
+    let token = oauth
+        .token("http://127.0.0.1/callback?state=st4te&code=c0de")
+        .await
+        .expect("code is exchanged");
+
+    assert!(matches!(mismatch, Err(AuthError::StateMismatch)));
+    assert_eq!(token.access_token, "abc");
+    assert_eq!(token.refresh_token.as_deref(), Some("def"));
+}
+
+#[cfg(feature = "vk")]
+mod vk {
+    use secretfop::auth::{AuthError, VKOAuth};
+    use wiremock::{
+        matchers::{method, path, query_param},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    #[tokio::test]
+    async fn token_is_read_from_redirect_fragment() {
+        let oauth = VKOAuth::new(42);
+
+        let token = oauth
+            .token("https://oauth.vk.com/blank.html#access_token=abc&expires_in=0&user_id=1")
+            .await
+            .expect("token in fragment");
+
+        assert_eq!(token.access_token, "abc");
+        assert_eq!(token.user_id, Some(1));
+    }
+
+    #[tokio::test]
+    async fn code_is_exchanged_to_token() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/access_token"))
+            .and(query_param("client_id", "42"))
+            .and(query_param("client_secret", "secret"))
+            .and(query_param("code", "c0de"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"access_token":"abc","expires_in":0,"user_id":1}"#),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        let oauth = VKOAuth::new(42)
+            .with_client_secret("secret")
+            .with_base_url(server.uri());
+
+        let token = oauth
+            .token("https://oauth.vk.com/blank.html#code=c0de")
+            .await
+            .expect("code is exchanged");
+
+        assert_eq!(token.access_token, "abc");
+    }
+
+    #[tokio::test]
+    async fn denied_access_is_reported() {
+        let oauth = VKOAuth::new(42);
+
+        let result = oauth
+            .token("https://oauth.vk.com/blank.html#error=access_denied&error_description=User+denied+your+request")
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(AuthError::Denied { description, .. }) if description == "User denied your request"
+        ));
+    }
+}
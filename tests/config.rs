@@ -0,0 +1,259 @@
+use std::path::Path;
+
+use chrono::{Local, NaiveTime, TimeZone, Utc};
+use secretfop::config::{
+    parse_duration, parse_size, CacheLocation, Config, Snowflake, SocialAccount, WeeklyTime,
+};
+
+#[test]
+fn weekly_time_finds_last_moment() {
+    let at: WeeklyTime = "sun 18:00".parse().expect("valid");
+    let local = |m, d, h| Local.with_ymd_and_hms(2023, m, d, h, 0, 0).unwrap();
+
+    // 2023-03-05 is sunday
+    assert_eq!(at.last_before(local(3, 8, 12)), Some(local(3, 5, 18)));
+    assert_eq!(at.last_before(local(3, 5, 18)), Some(local(3, 5, 18)));
+    assert_eq!(at.last_before(local(3, 5, 17)), Some(local(2, 26, 18)));
+    assert!("18:00".parse::<WeeklyTime>().is_err());
+}
+
+#[test]
+fn weekly_time_uses_time_zone_of_now() {
+    let at: WeeklyTime = "sun 18:00".parse().expect("valid");
+    let config: Config = serde_yaml::from_str(
+        "vk_token: vk\ntwitter_token: twitter\ntelegram:\n  token: token\n  channel: '@foxart'\ntimezone: Europe/Moscow\n",
+    )
+    .expect("config parses");
+    let tz = config.timezone.expect("time zone is set");
+
+    // 15:00 UTC is 18:00 in Moscow
+    let now = Utc.with_ymd_and_hms(2023, 3, 5, 15, 30, 0).unwrap();
+    let due = at
+        .last_before(now.with_timezone(&tz))
+        .expect("moment exists");
+    assert_eq!(due, Utc.with_ymd_and_hms(2023, 3, 5, 15, 0, 0).unwrap());
+}
+
+#[test]
+fn durations_and_sizes_accept_units() {
+    assert_eq!(parse_duration("90"), Ok(90));
+    assert_eq!(parse_duration("1h30m"), Ok(5400));
+    assert_eq!(parse_duration("14d"), Ok(1209600));
+    assert!(parse_duration("10 parsecs").is_err());
+    assert_eq!(parse_size("10MB"), Ok(10_000_000));
+    assert_eq!(parse_size("256mib"), Ok(256 * 1024 * 1024));
+    assert!(parse_size("").is_err());
+
+    let config: Config = serde_yaml::from_str(
+        "vk_token: vk\ntwitter_token: twitter\ntelegram:\n  token: token\n  channel: '@foxart'\nstale_after: 2w\nmedia_cache_size: 64MiB\nvk:\n  - id: foxart\n    max_age: 3600\n",
+    )
+    .expect("config parses");
+    assert_eq!(config.stale_after, Some(14 * 24 * 60 * 60));
+    assert_eq!(config.media_cache_size, 64 * 1024 * 1024);
+    assert_eq!(config.vk[0].max_age, Some(3600));
+}
+
+#[test]
+fn sample_rate_is_a_fraction() {
+    let parse = |rate: &str| {
+        serde_yaml::from_str::<Config>(&format!(
+            "vk_token: vk\ntwitter_token: twitter\ntelegram:\n  token: token\n  channel: '@foxart'\nvk:\n  - id: foxart\n    sample_rate: {rate}\n"
+        ))
+        .map(|config| config.vk[0].sample_rate)
+    };
+
+    assert_eq!(parse("0.25").expect("rate parses"), Some(0.25));
+    assert_eq!(parse("1").expect("rate parses"), Some(1.0));
+    for rate in ["1.5", "-1", ".nan"] {
+        assert!(parse(rate).is_err(), "{rate} is rejected");
+    }
+}
+
+#[test]
+fn max_failures_is_positive() {
+    let parse = |failures: &str| {
+        serde_yaml::from_str::<Config>(&format!(
+            "vk_token: vk\ntwitter_token: twitter\ntelegram:\n  token: token\n  channel: '@foxart'\nmax_failures: {failures}\n"
+        ))
+        .map(|config| config.max_failures)
+    };
+
+    assert_eq!(parse("1").expect("limit parses"), 1);
+    assert!(parse("0").is_err());
+}
+
+#[test]
+fn flat_telegram_keys_are_deprecated() {
+    let config = Config::from_yaml(
+        "vk_token: vk\ntwitter_token: twitter\ntelegram_token: token\ntelegram_channel: '@foxart'\n",
+    )
+    .expect("old layout parses");
+
+    assert_eq!(config.telegram.token, "token");
+    assert_eq!(
+        config.warnings,
+        [
+            "`telegram_token` is deprecated, move it to `telegram.token`",
+            "`telegram_channel` is deprecated, move it to `telegram.channel`",
+        ]
+    );
+}
+
+#[test]
+fn unknown_keys_are_collected() {
+    let config = Config::from_yaml(
+        "vk_token: vk\ntwitter_token: twitter\ntelegram:\n  token: token\n  chanel: '@typo'\n  channel: '@foxart'\nvk:\n  - id: foxart\n    max_aeg: 10\n",
+    )
+    .expect("unknown keys are ignored");
+
+    assert_eq!(config.unknown_keys, ["telegram.chanel", "vk.0.max_aeg"]);
+    assert!(config
+        .ensure_known_keys(Path::new(".secretfop.yml"))
+        .is_err());
+}
+
+#[test]
+fn copy_to_accepts_chats_with_own_captions() {
+    let account: SocialAccount = serde_yaml::from_str(
+        "id: foxart\ncopy_to:\n  - '@foxart_backup'\n  - chat: '@foxart_en'\n    caption_template: '{text}'\n    hashtags: [art, '#fox']\n",
+    )
+    .expect("account parses");
+
+    assert!(!account.copy_to[0].is_localized());
+    assert_eq!(
+        account.copy_to[1].chat,
+        Snowflake::String("@foxart_en".to_owned())
+    );
+    assert_eq!(account.copy_to[1].hashtags, ["art", "#fox"]);
+}
+
+#[test]
+fn copy_to_accepts_posting_window() {
+    let account: SocialAccount = serde_yaml::from_str(
+        "id: foxart\ncopy_to:\n  - chat: '@foxart_en'\n    posting_window: '09:00-23:00'\n",
+    )
+    .expect("account parses");
+
+    let window = account.copy_to[0].posting_window.expect("window is set");
+    assert!(window.contains(NaiveTime::from_hms_opt(12, 0, 0).unwrap()));
+    assert!(!window.contains(NaiveTime::from_hms_opt(3, 0, 0).unwrap()));
+    assert!(!account.copy_to[0].is_localized());
+}
+
+#[test]
+fn broken_sources_can_be_skipped() {
+    let yaml = "twitter_token: twitter\ntelegram:\n  token: token\n  channel: '@foxart'\nvk:\n  - id: foxart\nvk_discover:\n  - id: fox\nsources:\n  - type: vk\n    id: apiclub\n  - type: telegram_copy\n    id: '@foxnews'\nexec:\n  - id: feed\n    command: [./feed]\n  - id: broken\n    command: ./feed\n";
+    assert!(Config::from_yaml(yaml).is_err());
+
+    let config = Config::from_yaml_with(yaml, true).expect("broken sources are skipped");
+    assert!(config.vk.is_empty());
+    assert!(config.vk_discover.is_none());
+    assert_eq!(config.sources.len(), 1);
+    assert_eq!(config.sources[0].kind, "telegram_copy");
+    assert_eq!(config.exec.len(), 1);
+    assert_eq!(config.warnings.len(), 4);
+    assert!(config.warnings[0].contains("`vk` sources, `vk_token` is not set"));
+    assert!(config.warnings[1].contains("`vk_discover` sources, `vk_token` is not set"));
+    assert!(config.warnings[2].contains("`sources` source #1 of type `vk`"));
+    assert!(config.warnings[3].contains("`exec` source #2"));
+}
+
+#[test]
+fn snowflakes_are_totally_ordered() {
+    use secretfop::config::SnowflakeRef::{Number, String};
+
+    assert!(Number(9) < String("10"));
+    assert!(String("10") > Number(9));
+    assert_eq!(Number(7), String("007"));
+    assert!(Number(u64::MAX) < String("a"));
+    assert!(String("zz") < String("1a0"));
+    assert!(String("abc") < String("abd"));
+
+    let mut ids = vec![
+        String("1a0"),
+        Number(3),
+        String("zz"),
+        String("2"),
+        String("a"),
+    ];
+    ids.sort();
+    assert_eq!(
+        ids,
+        [
+            String("2"),
+            Number(3),
+            String("a"),
+            String("zz"),
+            String("1a0")
+        ]
+    );
+}
+
+#[test]
+fn owned_snowflakes_compare_like_refs() {
+    assert_eq!(Snowflake::String("5".to_owned()), Snowflake::Number(5));
+    assert_ne!(
+        Snowflake::String("@foxart".to_owned()),
+        Snowflake::Number(5)
+    );
+    assert!(Snowflake::Number(9) < Snowflake::String("10".to_owned()));
+}
+
+#[test]
+fn cache_locations_parse() {
+    assert_eq!(
+        ".cache.json".parse::<CacheLocation>(),
+        Ok(CacheLocation::Json(".cache.json".into()))
+    );
+    #[cfg(feature = "sqlite")]
+    assert_eq!(
+        "sqlite:cache.db".parse::<CacheLocation>(),
+        Ok(CacheLocation::Sqlite("cache.db".into()))
+    );
+    #[cfg(not(feature = "sqlite"))]
+    assert!("sqlite:cache.db".parse::<CacheLocation>().is_err());
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn sqlite_cache_keeps_records() {
+    use secretfop::config::CacheRecords;
+
+    let path = std::env::temp_dir().join(format!("secretfop-cache-{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let location = CacheLocation::Sqlite(path.clone());
+    let mut cache = CacheRecords::load_from(&location).expect("empty cache loads");
+    assert!(cache.paused.is_empty());
+
+    cache.pause("vk:apiclub");
+    cache.set_watermark("vk", &Snowflake::Number(1), 42);
+    cache.save_to(&location).expect("cache is saved");
+    cache.resume("vk:apiclub");
+    cache.save_to(&location).expect("cache is saved again");
+
+    let loaded = CacheRecords::load_from(&location).expect("cache loads");
+    assert!(loaded.paused.is_empty());
+    assert_eq!(
+        loaded.watermark("vk", &Snowflake::Number(1)),
+        Some(&Snowflake::Number(42))
+    );
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn file_ids_are_kept_by_kind() {
+    use secretfop::{config::CacheRecords, sources::Media};
+
+    let url = "https://sun9-1.userapi.com/huge.jpg";
+    let mut cache = CacheRecords::new();
+    let document = Media::Document {
+        url: url.to_owned(),
+        filename: None,
+        mime: None,
+    };
+    cache.remember_file_id(&document, "doc".to_owned());
+
+    assert_eq!(cache.file_id(&document), Some("doc"));
+    assert_eq!(cache.file_id(&Media::Photo(url.to_owned())), None);
+}
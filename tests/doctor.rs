@@ -0,0 +1,99 @@
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use secretfop::{
+    config::CacheLocation,
+    doctor,
+    http::{HttpError, Request, Transport},
+};
+
+/// Transport answering like Telegram to a bot that is an admin of `@foxart`
+/// but only a member of other chats.
+struct Telegram;
+
+impl Transport for Telegram {
+    fn send(&self, request: Request) -> BoxFuture<'_, Result<String, HttpError>> {
+        let chat = request
+            .query
+            .iter()
+            .find(|(key, _)| key == "chat_id")
+            .map(|(_, chat)| chat.clone());
+        let body = match (request.api_method(), chat.as_deref()) {
+            ("getMe", _) => r#"{"ok":true,"result":{"id":7,"username":"foxbot"}}"#,
+            ("getChatMember", Some("@foxart")) => {
+                r#"{"ok":true,"result":{"status":"administrator","can_post_messages":true}}"#
+            }
+            ("getChatMember", _) => r#"{"ok":true,"result":{"status":"member"}}"#,
+            _ => r#"{"ok":false,"error_code":404,"description":"Not Found"}"#,
+        };
+        Box::pin(async move { Ok(body.to_owned()) })
+    }
+}
+
+#[tokio::test]
+async fn doctor_reports_missing_rights() {
+    let dir = std::env::temp_dir().join(format!("secretfop-doctor-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("dir is created");
+    let config = dir.join("config.yml");
+    std::fs::write(
+        &config,
+        "vk_token: vk\ntwitter_token: twitter\ntelegram:\n  token: token\n  channel: '@foxart'\n\
+         sources:\n  - type: telegram_copy\n    id: '@foxart_archive'\n    copy_to: ['@foxart_en']\n",
+    )
+    .expect("config is written");
+    let cache = dir.join("cache.json");
+
+    let location = CacheLocation::Json(cache.clone());
+    let checks = doctor::diagnose(&config, &location, Some(Arc::new(Telegram))).await;
+
+    let results: Vec<_> = checks.iter().map(|c| (c.name.as_str(), c.passed)).collect();
+    assert_eq!(
+        results,
+        [
+            ("config", true),
+            ("cache", true),
+            ("sources", true),
+            ("telegram", true),
+            ("channel @foxart", true),
+            ("channel @foxart_en", false),
+        ]
+    );
+    assert!(checks[5].details.contains("as member"));
+    // the cache is not created
+    assert!(!cache.exists());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn doctor_does_not_write_cache_database() {
+    use secretfop::config::CacheRecords;
+
+    let dir = std::env::temp_dir().join(format!("secretfop-doctor-db-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("dir is created");
+    let config = dir.join("config.yml");
+    std::fs::write(
+        &config,
+        "vk_token: vk\ntwitter_token: twitter\ntelegram:\n  token: token\n  channel: '@foxart'\n",
+    )
+    .expect("config is written");
+
+    let missing = CacheLocation::Sqlite(dir.join("missing.db"));
+    let checks = doctor::diagnose(&config, &missing, Some(Arc::new(Telegram))).await;
+    assert!(checks[1].passed, "{:?}", checks[1]);
+    assert!(!dir.join("missing.db").exists());
+
+    let path = dir.join("cache.db");
+    let location = CacheLocation::Sqlite(path.clone());
+    CacheRecords::new()
+        .save_to(&location)
+        .expect("cache is saved");
+    let saved = std::fs::read(&path).expect("cache is read");
+    let checks = doctor::diagnose(&config, &location, Some(Arc::new(Telegram))).await;
+    assert!(checks[1].passed, "{:?}", checks[1]);
+    // sqlite counts changes in the header, so any write changes the file
+    assert_eq!(std::fs::read(&path).expect("cache is read"), saved);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
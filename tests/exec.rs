@@ -0,0 +1,97 @@
+#![cfg(feature = "exec")]
+
+use secretfop::{
+    config::{ExecSink, ExecSource, Snowflake},
+    error::Error,
+    exec::ExecError,
+    sources::{Media, Source},
+};
+
+/// Source of `plugin` account running `script` with `sh`.
+fn source(script: &str) -> ExecSource {
+    let mut source: ExecSource = serde_yaml::from_str("id: plugin\ncommand: []").expect("parses");
+    source.command = vec!["sh".to_owned(), "-c".to_owned(), script.to_owned()];
+
+    source
+}
+
+#[tokio::test]
+async fn exec_source_parses_output() {
+    let posts = source(
+        r#"printf '%s\n\n%s\n' '{"id": 1, "date": 1677600000, "text": "hi", "media": [{"type": "photo", "url": "https://fox.art/1.jpg"}], "url": "https://fox.art/1"}' '{"id": "b", "url": "https://fox.art/b", "source": "fox art"}'"#,
+    )
+    .fetch()
+    .await
+    .expect("posts are parsed");
+
+    assert_eq!(posts.len(), 2);
+    assert_eq!(posts[0].id, Snowflake::Number(1));
+    assert_eq!(posts[0].source_id, Snowflake::String("plugin".to_owned()));
+    assert_eq!(posts[0].date, 1677600000);
+    assert_eq!(posts[0].text, "hi");
+    assert_eq!(
+        posts[0].media,
+        [Media::Photo("https://fox.art/1.jpg".to_owned())]
+    );
+    assert_eq!(posts[0].source, "exec // plugin");
+    assert_eq!(posts[1].id, Snowflake::String("b".to_owned()));
+    assert_eq!(posts[1].text, "");
+    assert!(posts[1].media.is_empty());
+    assert_eq!(posts[1].source, "fox art");
+}
+
+#[tokio::test]
+async fn exec_source_reports_failures() {
+    let err = source("printf 'no posts' >&2; exit 3")
+        .fetch()
+        .await
+        .expect_err("command fails");
+    assert!(matches!(
+        err,
+        Error::ExecSource { account, source: ExecError::Status { status, stderr } }
+            if account == "plugin" && status.code() == Some(3) && stderr == "no posts"
+    ));
+
+    let err = source(r#"printf '{"id": 1, "url": "https://fox.art/1"}\n{"id": 2\n'"#)
+        .fetch()
+        .await
+        .expect_err("output is malformed");
+    assert!(matches!(
+        err,
+        Error::ExecSource {
+            source: ExecError::Scheme { line: 2, .. },
+            ..
+        }
+    ));
+
+    let mut empty = source("");
+    empty.command.clear();
+    assert!(matches!(
+        empty.fetch().await,
+        Err(Error::ExecSource {
+            source: ExecError::EmptyCommand,
+            ..
+        })
+    ));
+}
+
+#[tokio::test]
+async fn exec_sink_receives_post() {
+    let post = source(r#"printf '{"id": 1, "text": "hi", "url": "https://fox.art/1"}'"#)
+        .fetch()
+        .await
+        .expect("post is parsed")
+        .remove(0);
+    let sink = |script: &str| ExecSink {
+        command: vec!["sh".to_owned(), "-c".to_owned(), script.to_owned()],
+    };
+
+    sink(r#"grep -q '"text":"hi"'"#)
+        .send(&post)
+        .await
+        .expect("post is passed");
+    assert!(matches!(
+        sink("cat >/dev/null; exit 1").send(&post).await,
+        Err(ExecError::Status { .. })
+    ));
+}
@@ -0,0 +1,84 @@
+use secretfop::{
+    config::Snowflake,
+    fingerprint::{self, fingerprint},
+    sources::{Media, Post},
+};
+
+fn post(id: u64, text: &str, media: &[&str]) -> Post {
+    Post {
+        id: Snowflake::Number(id),
+        source_id: Snowflake::Number(123456),
+        date: 1677600000 + id,
+        text: text.to_owned(),
+        media: media
+            .iter()
+            .map(|&url| Media::Photo(url.to_owned()))
+            .collect(),
+        extra: Vec::new(),
+        stats: None,
+        source: "vk // Fox Art".to_owned(),
+        url: format!("https://vk.com/wall-123456_{id}"),
+        messages: Vec::new(),
+    }
+}
+
+#[test]
+fn same_content_has_same_fingerprint() {
+    let original = post(
+        1,
+        "Fresh art\nby  Fox",
+        &["https://sun9-1.userapi.com/1.jpg?size=510x510"],
+    );
+    // reposted elsewhere, with other case, spacing and signature of the URL
+    let repost = post(
+        2,
+        "fresh ART by Fox ",
+        &["https://sun9-1.userapi.com/1.jpg?size=1280x1280#x"],
+    );
+
+    assert_eq!(fingerprint(&original), fingerprint(&repost));
+    assert_eq!(fingerprint(&original).media.len(), 1);
+}
+
+#[test]
+fn different_content_has_different_fingerprint() {
+    let original = post(1, "Fresh art", &["https://sun9-1.userapi.com/1.jpg"]);
+
+    let other_media = post(1, "Fresh art", &["https://sun9-1.userapi.com/2.jpg"]);
+    assert_ne!(fingerprint(&original), fingerprint(&other_media));
+    assert_eq!(fingerprint(&original).text, fingerprint(&other_media).text);
+
+    let more_media = post(
+        1,
+        "Fresh art",
+        &[
+            "https://sun9-1.userapi.com/1.jpg",
+            "https://sun9-1.userapi.com/2.jpg",
+        ],
+    );
+    assert_ne!(fingerprint(&original), fingerprint(&more_media));
+    let reordered = post(
+        1,
+        "Fresh art",
+        &[
+            "https://sun9-1.userapi.com/2.jpg",
+            "https://sun9-1.userapi.com/1.jpg",
+        ],
+    );
+    assert_ne!(fingerprint(&more_media), fingerprint(&reordered));
+
+    let other_text = post(1, "Old art", &["https://sun9-1.userapi.com/1.jpg"]);
+    assert_ne!(fingerprint(&original), fingerprint(&other_text));
+
+    // the kind of media only matters for file ids
+    let photo = Media::Photo("https://vk.com/1.mp4".to_owned());
+    let video = Media::Video("https://vk.com/1.mp4".to_owned());
+    assert_eq!(
+        fingerprint::media_hash(&photo),
+        fingerprint::media_hash(&video)
+    );
+    assert_ne!(
+        fingerprint::file_hash(&photo),
+        fingerprint::file_hash(&video)
+    );
+}
@@ -0,0 +1,117 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use secretfop::{
+    config::{Snowflake, SocialAccount},
+    hooks::{Age, Chain, ContentKinds, Filter, SkipReason},
+    sources::{ContentKind, Media, Post},
+};
+
+fn account(yaml: &str) -> SocialAccount {
+    serde_yaml::from_str(yaml).expect("account parses")
+}
+
+fn post(text: &str, media: Vec<Media>) -> Post {
+    Post {
+        id: Snowflake::Number(1),
+        source_id: Snowflake::Number(123456),
+        date: now(),
+        text: text.to_owned(),
+        media,
+        extra: Vec::new(),
+        stats: None,
+        source: "vk // Fox Art".to_owned(),
+        url: "https://vk.com/wall-123456_1".to_owned(),
+        messages: Vec::new(),
+    }
+}
+
+fn photo() -> Media {
+    Media::Photo("https://sun9-1.userapi.com/1.jpg".to_owned())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("time after epoch")
+        .as_secs()
+}
+
+#[test]
+fn age_limits_include_boundaries() {
+    let account = account("id: foxart\nmin_age: 1m\nmax_age: 1h");
+    let now = 1677600000;
+
+    assert!(!account.fits_age(now - 59, now));
+    assert!(account.fits_age(now - 60, now));
+    assert!(account.fits_age(now - 3600, now));
+    assert!(!account.fits_age(now - 3601, now));
+    // posts from the future are of zero age
+    assert!(!account.fits_age(now + 60, now));
+
+    let mut old = post("Fresh art", vec![photo()]);
+    old.date = self::now() - 7200;
+    assert!(!Age.accepts(&account, &old));
+    assert_eq!(Age.reason(&account, &old), SkipReason::TooOld);
+    let new = post("Fresh art", vec![photo()]);
+    assert!(!Age.accepts(&account, &new));
+    assert_eq!(Age.reason(&account, &new), SkipReason::TooNew);
+}
+
+#[test]
+fn content_kinds_drop_and_reject() {
+    let chain = Chain::empty().transform(ContentKinds).filter(ContentKinds);
+    let check = |account: &SocialAccount, mut post: Post| {
+        let reason = chain.check(account, &mut post);
+        (post, reason)
+    };
+    let video = Media::Video("https://vk.com/1.mp4".to_owned());
+
+    let photos = account("id: foxart\ncontent: [photos]");
+    let (photo_post, reason) = check(&photos, post("Fresh art", vec![photo(), video.clone()]));
+    assert_eq!(reason, None);
+    assert_eq!(photo_post.media, [photo()]);
+    assert_eq!(photo_post.text, "");
+    let (_, reason) = check(&photos, post("Fresh art", vec![video.clone()]));
+    assert_eq!(reason, Some(SkipReason::NoMedia));
+    let mut poll = post("Fresh art", vec![photo()]);
+    poll.extra.push(ContentKind::Polls);
+    assert_eq!(check(&photos, poll).1, Some(SkipReason::Content));
+
+    // every kind is allowed by default, but text alone only with `text_posts`
+    let any = account("id: foxart");
+    assert_eq!(check(&any, post("Fresh art", vec![video])).1, None);
+    assert_eq!(
+        check(&any, post("Fresh art", Vec::new())).1,
+        Some(SkipReason::NoMedia)
+    );
+    let text = account("id: foxart\ntext_posts: true");
+    assert_eq!(check(&text, post("Fresh art", Vec::new())).1, None);
+    assert_eq!(
+        check(&text, post("  ", Vec::new())).1,
+        Some(SkipReason::NoMedia)
+    );
+}
+
+#[cfg(feature = "lang-detect")]
+#[test]
+fn languages_reject_other_text() {
+    use secretfop::hooks::Languages;
+
+    let account = account("id: foxart\nlanguages: [ru]");
+    let english = post(
+        "The fox has drawn a new picture this evening, take a look",
+        vec![photo()],
+    );
+    let russian = post(
+        "Лиса нарисовала новую картину этим вечером, посмотрите её",
+        vec![photo()],
+    );
+
+    assert!(Languages.accepts(&account, &russian));
+    assert!(!Languages.accepts(&account, &english));
+    assert_eq!(Languages.reason(&account, &english), SkipReason::Language);
+    // text of unknown language is accepted
+    assert!(Languages.accepts(&account, &post("🦊🦊", vec![photo()])));
+    assert!(Languages.accepts(&account, &post("", vec![photo()])));
+    assert!(serde_yaml::from_str::<SocialAccount>("id: foxart\nlanguages: [xx]").is_err());
+}
@@ -0,0 +1,70 @@
+use std::sync::Arc;
+
+use secretfop::{
+    http::ReqwestTransport,
+    media::{FileType, InvalidMedia, MediaCache},
+    sources::Media,
+};
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+#[tokio::test]
+async fn media_is_downloaded_once_and_evicted() {
+    let server = MockServer::start().await;
+    for (name, size) in [("/a.jpg", 600), ("/b.jpg", 600)] {
+        Mock::given(method("GET"))
+            .and(path(name))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(vec![7; size]))
+            .expect(1)
+            .mount(&server)
+            .await;
+    }
+    let dir = std::env::temp_dir().join(format!("secretfop-media-{}", std::process::id()));
+    let cache = MediaCache::new(&dir, 1000, Arc::new(ReqwestTransport::default()));
+
+    let a = cache.get(&format!("{}/a.jpg?sign=1", server.uri())).await;
+    let again = cache.get(&format!("{}/a.jpg?sign=2", server.uri())).await;
+    let b = cache.get(&format!("{}/b.jpg", server.uri())).await;
+    let files = std::fs::read_dir(&dir).map(|d| d.count());
+    std::fs::remove_dir_all(&dir).ok();
+
+    assert_eq!(a.expect("downloaded").len(), 600);
+    assert_eq!(again.expect("cached"), vec![7; 600]);
+    assert_eq!(b.expect("downloaded").len(), 600);
+    // a.jpg is evicted to fit 1000 bytes
+    assert_eq!(files.expect("directory exists"), 1);
+}
+
+#[tokio::test]
+async fn media_are_checked_by_their_bytes() {
+    let png = b"\x89PNG\r\n\x1a\n rest".to_vec();
+    let gif = b"GIF89a rest".to_vec();
+    let html = b"\n  <!DOCTYPE html><html></html>".to_vec();
+    assert_eq!(FileType::sniff(&png), Some(FileType::Png));
+    assert_eq!(FileType::sniff(b"\0\0\0\x20ftypisom"), Some(FileType::Mp4));
+    assert_eq!(FileType::sniff(b"\0\0\0\x20ftypM4A "), Some(FileType::M4a));
+    assert_eq!(FileType::sniff(b"plain text"), None);
+
+    let server = MockServer::start().await;
+    for (name, body) in [("/a.png", png), ("/b.gif", gif), ("/c", html)] {
+        Mock::given(method("GET"))
+            .and(path(name))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(body))
+            .mount(&server)
+            .await;
+    }
+    let dir = std::env::temp_dir().join(format!("secretfop-checked-{}", std::process::id()));
+    let cache = MediaCache::new(&dir, 1000, Arc::new(ReqwestTransport::default()));
+    let url = |name| format!("{}/{name}", server.uri());
+
+    let image = cache.check(&Media::Video(url("a.png"))).await;
+    let gif = cache.check(&Media::Photo(url("b.gif"))).await;
+    let page = cache.check(&Media::Photo(url("c"))).await;
+    std::fs::remove_dir_all(&dir).ok();
+
+    assert_eq!(image.expect("image is valid"), Media::Photo(url("a.png")));
+    assert_eq!(gif.expect("gif is valid"), Media::Animation(url("b.gif")));
+    assert!(matches!(page, Err(InvalidMedia::UnknownType)));
+}
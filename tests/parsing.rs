@@ -0,0 +1,104 @@
+//! Property tests of API response parsing: arbitrary payloads should never
+//! panic, and malformed posts should not break the rest of the feed.
+
+use proptest::prelude::*;
+use secretfop::telegram;
+use serde_json::Value;
+
+fn arb_json() -> impl Strategy<Value = Value> {
+    let leaf = prop_oneof![
+        Just(Value::Null),
+        any::<bool>().prop_map(Value::from),
+        any::<i64>().prop_map(Value::from),
+        any::<f64>().prop_map(Value::from),
+        ".*".prop_map(Value::from),
+    ];
+
+    leaf.prop_recursive(4, 32, 6, |inner| {
+        prop_oneof![
+            prop::collection::vec(inner.clone(), 0..6).prop_map(Value::from),
+            prop::collection::hash_map(
+                prop_oneof!["[a-z_]{1,12}".boxed(), Just("type".to_owned()).boxed()],
+                inner,
+                0..6
+            )
+            .prop_map(|m| Value::Object(m.into_iter().collect())),
+        ]
+    })
+}
+
+proptest! {
+    #[test]
+    fn telegram_parses_any_string(s in ".*") {
+        let _ = telegram::parse_response::<Value>(&s);
+    }
+
+    #[test]
+    fn telegram_parses_any_json(v in arb_json()) {
+        let _ = telegram::parse_response::<Vec<Value>>(&v.to_string());
+    }
+
+    #[test]
+    fn sanitized_html_is_valid(s in "[a-z<>/&;# ]*") {
+        prop_assert!(telegram::validate_html(&telegram::sanitize_html(&s)).is_ok());
+    }
+
+    #[test]
+    fn truncated_html_is_valid(
+        parts in prop::collection::vec(("b|i|code", "[a-z🦊&<> ]{0,8}"), 0..6),
+        units in 0usize..40,
+    ) {
+        let html: String = parts
+            .iter()
+            .map(|(tag, text)| format!("<{tag}>{}</{tag}>", telegram::ProtectedString(text)))
+            .collect();
+        let html = format!("<a href=\"https://vk.com\">{html}</a>");
+        let truncated = telegram::truncate_html(&html, units);
+        prop_assert!(telegram::validate_html(&truncated).is_ok());
+        prop_assert!(telegram::html_len(&truncated) <= units.max(1));
+    }
+}
+
+#[cfg(feature = "vk")]
+mod vk {
+    use super::*;
+    use secretfop::sources::vk::{self, VKGroupFeed};
+    use serde_json::json;
+
+    fn wall(items: Vec<Value>) -> String {
+        json!({
+            "response": {
+                "items": items,
+                "groups": [{ "id": 1, "name": "group" }]
+            }
+        })
+        .to_string()
+    }
+
+    proptest! {
+        #[test]
+        fn parses_any_string(s in ".*") {
+            let _ = vk::parse_response::<Value>(&s);
+            let _ = VKGroupFeed::from_response(&s);
+        }
+
+        #[test]
+        fn parses_any_json(v in arb_json()) {
+            let _ = VKGroupFeed::from_response(&json!({ "response": v }).to_string());
+        }
+
+        #[test]
+        fn malformed_item_keeps_feed(garbage in arb_json(), attachment in arb_json()) {
+            let good = json!({
+                "id": 1,
+                "date": 1,
+                "text": "",
+                "attachments": [attachment],
+            });
+            let feed = VKGroupFeed::from_response(&wall(vec![garbage, good]))
+                .expect("feed with one valid post parses");
+
+            prop_assert!(feed.items.iter().any(|i| i.id == 1));
+        }
+    }
+}
@@ -0,0 +1,1439 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use chrono::Local;
+use futures::future::BoxFuture;
+use secretfop::{
+    config::{
+        CacheRecords, Config, PostOrder, ResolvedId, Snowflake, SocialAccount, SourceHealth,
+        TimeRange,
+    },
+    error::{Error, Result},
+    fingerprint::text_hash,
+    hooks::{Chain, SkipReason},
+    http::{HttpError, Method, Request, Traffic, Transport},
+    pipeline::Pipeline,
+    pipeline::PlanStatus,
+    pipeline::PlannedPost,
+    sources::{
+        channel::{self, ChannelSource},
+        Media, Post, Source,
+    },
+    telegram::TelegramError,
+};
+
+/// Source of `foxart` account with given posts, its alias resolves to
+/// `resolved`.
+struct TestSource {
+    account: SocialAccount,
+    resolved: Option<u64>,
+    posts: Vec<Post>,
+}
+
+impl TestSource {
+    fn new(posts: Vec<Post>) -> Box<Self> {
+        Box::new(Self {
+            account: serde_yaml::from_str("id: foxart").expect("account parses"),
+            resolved: None,
+            posts,
+        })
+    }
+}
+
+impl Source for TestSource {
+    fn account(&self) -> &SocialAccount {
+        &self.account
+    }
+
+    fn network(&self) -> &'static str {
+        "test"
+    }
+
+    fn fetch(&self) -> BoxFuture<'_, Result<Vec<Post>>> {
+        Box::pin(async { Ok(self.posts.clone()) })
+    }
+
+    fn resolve(&self) -> BoxFuture<'_, Result<Option<u64>>> {
+        Box::pin(async { Ok(self.resolved) })
+    }
+
+    fn history(&self, page: usize) -> BoxFuture<'_, Result<Option<Vec<Post>>>> {
+        // pages of two posts, latest first
+        let end = self.posts.len().saturating_sub(page * 2);
+        let start = end.saturating_sub(2);
+        Box::pin(async move { Ok(Some(self.posts[start..end].to_vec())) })
+    }
+
+    fn exists<'a>(
+        &'a self,
+        _source_id: &'a Snowflake,
+        id: u64,
+    ) -> BoxFuture<'a, Result<Option<bool>>> {
+        Box::pin(async move {
+            Ok(Some(
+                self.posts.iter().any(|p| p.id == Snowflake::Number(id)),
+            ))
+        })
+    }
+}
+
+fn post(id: u64) -> Post {
+    Post {
+        id: Snowflake::Number(id),
+        source_id: Snowflake::Number(123456),
+        date: 1677600000 + id,
+        text: format!("Fresh art #{id}"),
+        media: vec![Media::Photo(format!("https://sun9-1.userapi.com/{id}.jpg"))],
+        extra: Vec::new(),
+        stats: None,
+        source: "vk // Fox Art".to_owned(),
+        url: format!("https://vk.com/wall-123456_{id}"),
+        messages: Vec::new(),
+    }
+}
+
+/// Transport answering every request like Telegram answers an album of one
+/// photo.
+struct SentPhoto;
+
+impl Transport for SentPhoto {
+    fn send(&self, _request: Request) -> BoxFuture<'_, std::result::Result<String, HttpError>> {
+        Box::pin(async {
+            Ok(r#"{"ok":true,"result":[{"message_id":42,"photo":[{"file_id":"f"}]}]}"#.to_owned())
+        })
+    }
+}
+
+/// Transport answering every request like Telegram answers a text message,
+/// remembering called methods and their chats.
+#[derive(Default)]
+struct SentText {
+    methods: Mutex<Vec<String>>,
+    chats: Mutex<Vec<String>>,
+}
+
+impl Transport for SentText {
+    fn send(&self, request: Request) -> BoxFuture<'_, std::result::Result<String, HttpError>> {
+        let method = request.api_method().to_owned();
+        self.methods.lock().unwrap().push(method);
+        let chat = request.query.iter().find(|(key, _)| key == "chat_id");
+        self.chats
+            .lock()
+            .unwrap()
+            .extend(chat.map(|(_, chat)| chat.clone()));
+        Box::pin(async { Ok(r#"{"ok":true,"result":{"message_id":42}}"#.to_owned()) })
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("time after epoch")
+        .as_secs()
+}
+
+fn config() -> Config {
+    serde_yaml::from_str(
+        "vk_token: vk\ntwitter_token: twitter\ntelegram:\n  token: token\n  channel: '@foxart'\n",
+    )
+    .expect("config parses")
+}
+
+#[tokio::test]
+async fn long_flood_wait_skips_run() {
+    let now = now();
+    let mut cache = CacheRecords::new();
+    cache.flood_until = Some(now + 3600);
+
+    let errors = Pipeline::new(&config())
+        .sources(Vec::new())
+        .run(&mut cache)
+        .await;
+
+    assert!(matches!(&errors[..], [Error::FloodWait { seconds }] if *seconds > 3500));
+    assert_eq!(cache.flood_until, Some(now + 3600));
+}
+
+#[tokio::test]
+async fn stale_source_is_reported_once() {
+    let mut config = config();
+    config.stale_after = Some(14 * 24 * 60 * 60);
+    let mut cache = CacheRecords::new();
+    cache.health.insert(
+        "test/foxart".to_owned(),
+        SourceHealth {
+            last_post: now() - 20 * 24 * 60 * 60,
+            alerted: false,
+            inaccessible_at: None,
+            verified_at: None,
+        },
+    );
+
+    let errors = Pipeline::new(&config)
+        .sources(vec![TestSource::new(Vec::new())])
+        .run(&mut cache)
+        .await;
+    assert!(matches!(&errors[..], [Error::StaleSource { days: 20, .. }]));
+    assert!(cache.health["test/foxart"].alerted);
+
+    let errors = Pipeline::new(&config)
+        .sources(vec![TestSource::new(Vec::new())])
+        .run(&mut cache)
+        .await;
+    assert!(errors.is_empty());
+}
+
+#[tokio::test]
+async fn changed_alias_is_reported() {
+    let mut cache = CacheRecords::new();
+    cache.resolved.insert(
+        "test/foxart".to_owned(),
+        ResolvedId {
+            id: 1,
+            resolved_at: now() - 2 * 24 * 60 * 60,
+        },
+    );
+
+    let errors = Pipeline::new(&config())
+        .sources(vec![Box::new(TestSource {
+            resolved: Some(2),
+            ..*TestSource::new(Vec::new())
+        })])
+        .run(&mut cache)
+        .await;
+
+    assert!(matches!(
+        &errors[..],
+        [Error::IdChanged { old: 1, new: 2, .. }]
+    ));
+    assert_eq!(cache.resolved["test/foxart"].id, 2);
+}
+
+#[tokio::test]
+async fn dry_run_lists_new_posts() {
+    let mut cache = CacheRecords::new();
+    cache.remember("test", &post(1));
+
+    let (plan, errors) = Pipeline::new(&config())
+        .sources(vec![TestSource::new(vec![post(1), post(2)])])
+        .dry_run(&mut cache, false)
+        .await;
+
+    assert!(errors.is_empty());
+    let plan: Vec<_> = plan
+        .iter()
+        .map(|p| (p.post.url.as_str(), p.status))
+        .collect();
+    assert_eq!(plan, [("https://vk.com/wall-123456_2", PlanStatus::New)]);
+}
+
+#[tokio::test]
+async fn dry_run_renders_requests() {
+    let (plan, errors) = Pipeline::new(&config())
+        .sources(vec![TestSource::new(vec![post(1)])])
+        .dry_run(&mut CacheRecords::new(), false)
+        .await;
+
+    assert!(errors.is_empty());
+    let message = plan[0].message.as_ref().unwrap();
+    assert_eq!(message.method, "sendMediaGroup");
+    let json = message.to_json();
+    assert_eq!(json["chat_id"], "@foxart");
+    assert_eq!(json["media"][0]["type"], "photo");
+    assert_eq!(json["media"][0]["parse_mode"], "HTML");
+    let caption = json["media"][0]["caption"].as_str().unwrap();
+    assert!(
+        caption.contains("https://vk.com/wall-123456_1"),
+        "{caption}"
+    );
+}
+
+#[tokio::test]
+async fn watermark_of_deleted_post_is_repaired() {
+    let mut cache = CacheRecords::new();
+    cache.remember("test", &post(5));
+    cache.health.insert(
+        "test/foxart".to_owned(),
+        SourceHealth {
+            last_post: post(5).date,
+            alerted: false,
+            inaccessible_at: None,
+            verified_at: None,
+        },
+    );
+
+    let (plan, errors) = Pipeline::new(&config())
+        .sources(vec![TestSource::new(vec![post(2), post(3)])])
+        .dry_run(&mut cache, false)
+        .await;
+
+    assert!(matches!(
+        &errors[..],
+        [Error::WatermarkRepaired { old: 5, new: 3, .. }]
+    ));
+    assert_eq!(
+        cache.watermark("test", &Snowflake::Number(123456)),
+        Some(&Snowflake::Number(3))
+    );
+    assert!(cache.health["test/foxart"].verified_at.is_some());
+    assert!(plan.is_empty());
+
+    // verified once a day
+    cache.set_watermark("test", &Snowflake::Number(123456), 5);
+    let (_, errors) = Pipeline::new(&config())
+        .sources(vec![TestSource::new(vec![post(2), post(3)])])
+        .dry_run(&mut cache, false)
+        .await;
+    assert!(errors.is_empty());
+}
+
+#[tokio::test]
+async fn paused_and_disabled_sources_are_not_fetched() {
+    let mut cache = CacheRecords::new();
+    assert!(cache.pause("test:foxart"));
+    assert!(!cache.pause("test/foxart"));
+
+    let (plan, errors) = Pipeline::new(&config())
+        .sources(vec![TestSource::new(vec![post(1)])])
+        .dry_run(&mut cache, false)
+        .await;
+    assert!(errors.is_empty());
+    assert!(plan.is_empty());
+
+    assert!(cache.resume("test/foxart"));
+    assert!(!cache.resume("test/foxart"));
+    let (plan, _) = Pipeline::new(&config())
+        .sources(vec![TestSource::new(vec![post(1)])])
+        .dry_run(&mut cache, false)
+        .await;
+    assert_eq!(plan.len(), 1);
+
+    let (plan, _) = Pipeline::new(&config())
+        .sources(vec![Box::new(TestSource {
+            account: serde_yaml::from_str("id: foxart\nenabled: false").expect("account parses"),
+            resolved: None,
+            posts: vec![post(1)],
+        })])
+        .dry_run(&mut cache, false)
+        .await;
+    assert!(plan.is_empty());
+}
+
+#[tokio::test]
+async fn named_pipelines_apply_to_accounts() {
+    let mut config = config();
+    config.pipelines =
+        serde_yaml::from_str("art: [exclude \"giveaway\", hashtag art]").expect("pipelines parse");
+    let mut giveaway = post(2);
+    giveaway.text.push_str(" GIVEAWAY");
+
+    let (plan, errors) = Pipeline::new(&config)
+        .sources(vec![Box::new(TestSource {
+            account: serde_yaml::from_str("id: foxart\npipelines: [art]").expect("account parses"),
+            ..*TestSource::new(vec![post(1), giveaway])
+        })])
+        .dry_run(&mut CacheRecords::new(), false)
+        .await;
+
+    assert!(errors.is_empty());
+    let texts: Vec<_> = plan.iter().map(|p| p.post.text.as_str()).collect();
+    assert_eq!(texts, ["Fresh art #1\n\n#art"]);
+}
+
+#[tokio::test]
+async fn weighted_order_interleaves_sources() {
+    let mut config = config();
+    config.order = PostOrder::Weighted;
+    let source = |account: &str, ids: &[u64]| -> Box<dyn Source> {
+        Box::new(TestSource {
+            account: serde_yaml::from_str(account).expect("account parses"),
+            ..*TestSource::new(ids.iter().map(|&id| post(id)).collect())
+        })
+    };
+
+    let (plan, errors) = Pipeline::new(&config)
+        .sources(vec![
+            source("id: memes\nweight: 3", &[1, 2, 3, 4]),
+            source("id: news", &[11, 12]),
+        ])
+        .dry_run(&mut CacheRecords::new(), false)
+        .await;
+
+    assert!(errors.is_empty());
+    let ids: Vec<_> = plan.iter().map(|p| p.post.date - 1677600000).collect();
+    assert_eq!(ids, [1, 2, 11, 3, 4, 12]);
+}
+
+#[tokio::test]
+async fn every_order_handles_sources_of_any_length() {
+    let source = |account: &str, ids: &[u64]| -> Box<dyn Source> {
+        Box::new(TestSource {
+            account: serde_yaml::from_str(account).expect("account parses"),
+            ..*TestSource::new(ids.iter().map(|&id| post(id)).collect())
+        })
+    };
+    let orders = [
+        (PostOrder::Source, [1, 4, 5, 8, 9, 2, 3]),
+        (PostOrder::Date, [1, 2, 3, 4, 5, 8, 9]),
+        // the longer source goes on alone once the shorter one runs out
+        (PostOrder::Weighted, [1, 2, 4, 3, 5, 8, 9]),
+    ];
+
+    for (order, expected) in orders {
+        let mut config = config();
+        config.order = order;
+        let (plan, errors) = Pipeline::new(&config)
+            .sources(vec![
+                source("id: art", &[1, 4, 5, 8, 9]),
+                source("id: memes\nweight: 5", &[]),
+                source("id: news", &[2, 3]),
+            ])
+            .dry_run(&mut CacheRecords::new(), false)
+            .await;
+
+        assert!(errors.is_empty());
+        let ids: Vec<_> = plan.iter().map(|p| p.post.date - 1677600000).collect();
+        assert_eq!(ids, expected, "{order:?}");
+    }
+}
+
+#[tokio::test]
+async fn high_priority_sources_go_first() {
+    let mut config = config();
+    config.order = PostOrder::Date;
+    let source = |account: &str, ids: &[u64]| -> Box<dyn Source> {
+        Box::new(TestSource {
+            account: serde_yaml::from_str(account).expect("account parses"),
+            ..*TestSource::new(ids.iter().map(|&id| post(id)).collect())
+        })
+    };
+
+    let (plan, errors) = Pipeline::new(&config)
+        .sources(vec![
+            source("id: memes\npriority: low", &[1, 4]),
+            source("id: art", &[2, 5]),
+            source("id: news\npriority: high", &[3, 6]),
+        ])
+        .dry_run(&mut CacheRecords::new(), false)
+        .await;
+
+    assert!(errors.is_empty());
+    let ids: Vec<_> = plan.iter().map(|p| p.post.date - 1677600000).collect();
+    assert_eq!(ids, [3, 6, 2, 5, 1, 4]);
+}
+
+#[tokio::test]
+async fn preview_renders_caption() {
+    let mut fish = post(2);
+    fish.text = "Fish & <chips>".to_owned();
+
+    let preview = Pipeline::new(&config())
+        .sources(vec![TestSource::new(vec![post(1), fish])])
+        .preview("test:foxart", "2")
+        .await
+        .expect("post is found");
+
+    assert!(preview.accepted);
+    assert!(preview.caption.starts_with("Fish &amp; &lt;chips&gt;"));
+    assert!(matches!(
+        Pipeline::new(&config())
+            .sources(vec![TestSource::new(Vec::new())])
+            .preview("test:foxart", "2")
+            .await,
+        Err(Error::NoSuchPost { .. })
+    ));
+}
+
+#[tokio::test]
+async fn attribution_presets_credit_source() {
+    async fn caption(attribution: &str, media: bool) -> String {
+        let mut post = post(1);
+        if !media {
+            post.media.clear();
+        }
+        let mut source = TestSource::new(vec![post]);
+        source.account.attribution = serde_yaml::from_str(attribution).expect("preset parses");
+
+        Pipeline::new(&config())
+            .sources(vec![source])
+            .preview("test:foxart", "1")
+            .await
+            .expect("post is found")
+            .caption
+    }
+
+    assert_eq!(
+        caption("via", true).await,
+        "Fresh art #1\n\nvia <a href=\"https://vk.com/wall-123456_1\">vk // Fox Art</a>"
+    );
+    assert_eq!(caption("hidden", true).await, "Fresh art #1");
+    assert_eq!(caption("inline_button", false).await, "Fresh art #1");
+    // albums can not have buttons
+    assert!(caption("inline_button", true).await.contains("src: <a"));
+}
+
+#[tokio::test]
+async fn blocked_media_is_dropped() {
+    let mut config = config();
+    config.media_blocklist = vec!["userapi.com".to_owned(), "vk.com/watermarked/".to_owned()];
+    let mut mixed = post(2);
+    mixed
+        .media
+        .push(Media::Photo("https://vk.com/images/2.jpg".to_owned()));
+    mixed
+        .media
+        .push(Media::Photo("https://vk.com/watermarked/2.jpg".to_owned()));
+
+    let preview = |id: &'static str| {
+        Pipeline::new(&config)
+            .sources(vec![TestSource::new(vec![post(1), mixed.clone()])])
+            .preview("test:foxart", id)
+    };
+    let only_blocked = preview("1").await.expect("post is found");
+    assert!(!only_blocked.accepted);
+    let kept = preview("2").await.expect("post is found");
+    assert!(kept.accepted);
+    assert_eq!(
+        kept.post.media,
+        [Media::Photo("https://vk.com/images/2.jpg".to_owned())]
+    );
+}
+
+#[tokio::test]
+async fn only_matching_posts_are_accepted() {
+    let mut tagged = post(2);
+    tagged.text = "Fresh art #Подборка@foxart".to_owned();
+    let mut similar = post(3);
+    similar.text = "Fresh art #подборкаДня".to_owned();
+    let mut matching = post(4);
+    matching.text = "Commissions open!".to_owned();
+
+    let mut source = TestSource::new(vec![post(1), tagged, similar, matching]);
+    source.account.only_if = Some(
+        serde_yaml::from_str("hashtags: [подборка]\nregex: ['(?i)^commissions? open']")
+            .expect("rules parse"),
+    );
+    let config = config();
+    let mut accepted = Vec::new();
+    for id in ["1", "2", "3", "4"] {
+        let source = TestSource {
+            account: source.account.clone(),
+            resolved: None,
+            posts: source.posts.clone(),
+        };
+        let preview = Pipeline::new(&config)
+            .sources(vec![Box::new(source)])
+            .preview("test:foxart", id)
+            .await
+            .expect("post is found");
+        accepted.push(preview.accepted);
+    }
+    assert_eq!(accepted, [false, true, false, true]);
+}
+
+#[tokio::test]
+async fn seeded_links_mark_duplicates() {
+    let mut cache = CacheRecords::new();
+    cache.seed_link("https://vk.com/wall-123456_1");
+
+    let (plan, _) = Pipeline::new(&config())
+        .sources(vec![TestSource::new(vec![post(1), post(2)])])
+        .dry_run(&mut cache, true)
+        .await;
+
+    let plan: Vec<_> = plan
+        .iter()
+        .map(|p| (p.post.url.as_str(), p.status))
+        .collect();
+    assert_eq!(
+        plan,
+        [
+            ("https://vk.com/wall-123456_1", PlanStatus::Duplicate),
+            ("https://vk.com/wall-123456_2", PlanStatus::New)
+        ]
+    );
+}
+
+#[tokio::test]
+async fn diff_lists_skip_reasons() {
+    let mut config = config();
+    config.media_blocklist = vec!["userapi.com".to_owned()];
+    let mut cache = CacheRecords::new();
+    let mut text = post(2);
+    text.media
+        .push(Media::Photo("https://vk.com/images/2.jpg".to_owned()));
+    let mut old = post(3);
+    old.media = text.media.clone();
+    old.date = 1;
+    let mut source = TestSource::new(vec![post(1), text, old]);
+    source.account.max_age = Some(10 * 365 * 24 * 60 * 60);
+
+    let (plan, _) = Pipeline::new(&config)
+        .sources(vec![source])
+        .dry_run(&mut cache, true)
+        .await;
+
+    let plan: Vec<_> = plan
+        .iter()
+        .map(|p| (p.post.url.as_str(), p.status))
+        .collect();
+    assert_eq!(
+        plan,
+        [
+            ("https://vk.com/wall-123456_2", PlanStatus::New),
+            (
+                "https://vk.com/wall-123456_1",
+                PlanStatus::Skipped(SkipReason::NoMedia)
+            ),
+            (
+                "https://vk.com/wall-123456_3",
+                PlanStatus::Skipped(SkipReason::TooOld)
+            )
+        ]
+    );
+}
+
+#[tokio::test]
+async fn event_log_records_run() {
+    let path = std::env::temp_dir().join(format!("secretfop-events-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let mut config = config();
+    config.event_log = Some(path.clone());
+    let mut old = post(2);
+    old.date = 1;
+    let mut source = TestSource::new(vec![post(1), old]);
+    source.account.max_age = Some(10 * 365 * 24 * 60 * 60);
+    let mut cache = CacheRecords::new();
+    cache.remember("test", &post(1));
+
+    let errors = Pipeline::new(&config)
+        .sources(vec![source])
+        .run(&mut cache)
+        .await;
+    assert!(errors.is_empty());
+
+    let log = std::fs::read_to_string(&path).expect("log is written");
+    let events: Vec<serde_json::Value> = log
+        .lines()
+        .map(|l| serde_json::from_str(l).expect("line is json"))
+        .collect();
+    assert_eq!(events.len(), 3);
+    assert_eq!(events[0]["event"], "fetch");
+    assert_eq!(events[0]["source"], "test/foxart");
+    assert_eq!(events[0]["posts"], 2);
+    assert_eq!(events[1]["event"], "skip");
+    assert_eq!(events[1]["post"], "https://vk.com/wall-123456_2");
+    assert_eq!(events[1]["reason"], "too_old");
+    assert_eq!(events[0]["run"], events[1]["run"]);
+    assert_eq!(events[2]["event"], "traffic");
+    assert!(events[2].get("source").is_none());
+
+    let _ = std::fs::remove_file(&path);
+}
+
+/// Transport answering like Pushgateway, remembering pushed requests.
+#[derive(Default)]
+struct Pushgateway {
+    pushed: Mutex<Vec<Request>>,
+}
+
+impl Transport for Pushgateway {
+    fn send(&self, request: Request) -> BoxFuture<'_, std::result::Result<String, HttpError>> {
+        self.pushed.lock().unwrap().push(request);
+        Box::pin(async { Ok(String::new()) })
+    }
+}
+
+#[tokio::test]
+async fn metrics_are_pushed_after_run() {
+    let mut config = config();
+    config.pushgateway = Some("http://localhost:9091/metrics/job/secretfop".to_owned());
+    let mut old = post(2);
+    old.date = 1;
+    let mut source = TestSource::new(vec![post(1), old]);
+    source.account.max_age = Some(10 * 365 * 24 * 60 * 60);
+    let mut cache = CacheRecords::new();
+    cache.remember("test", &post(1));
+    let transport = Arc::new(Pushgateway::default());
+
+    let errors = Pipeline::new(&config)
+        .sources(vec![source])
+        .transport(transport.clone())
+        .run(&mut cache)
+        .await;
+    assert!(errors.is_empty(), "{errors:?}");
+
+    let pushed = transport.pushed.lock().unwrap();
+    assert_eq!(pushed.len(), 1);
+    assert_eq!(pushed[0].method, Method::Put);
+    assert_eq!(pushed[0].url, "http://localhost:9091/metrics/job/secretfop");
+    let body = pushed[0].body.as_deref().unwrap_or_default();
+    assert!(
+        body.contains("secretfop_fetched_posts{source=\"test/foxart\"} 2\n"),
+        "{body}"
+    );
+    assert!(
+        body.contains("secretfop_skipped_posts{reason=\"too_old\"} 1\n"),
+        "{body}"
+    );
+    assert!(body.contains("secretfop_errors 0\n"), "{body}");
+}
+
+#[tokio::test]
+async fn received_channel_posts_are_copied() {
+    let channel = Snowflake::String("@foxart_archive".to_owned());
+    let received = |id: u64| Post {
+        id: Snowflake::Number(id),
+        source_id: channel.clone(),
+        url: format!("https://t.me/foxart_archive/{id}"),
+        messages: vec![id],
+        ..post(id)
+    };
+    let mut cache = CacheRecords::new();
+    cache.remember(channel::NETWORK, &received(1));
+    cache.channel_posts = vec![received(1), received(2)];
+    let account = serde_yaml::from_str("id: \"@foxart_archive\"").expect("account parses");
+
+    let (plan, errors) = Pipeline::new(&config())
+        .sources(vec![Box::new(ChannelSource::new(account))])
+        .dry_run(&mut cache, false)
+        .await;
+
+    assert!(errors.is_empty());
+    let plan: Vec<_> = plan
+        .iter()
+        .map(|p| (p.post.url.as_str(), p.status))
+        .collect();
+    assert_eq!(plan, [("https://t.me/foxart_archive/2", PlanStatus::New)]);
+    // mirrored posts are forgotten
+    assert_eq!(cache.channel_posts.len(), 1);
+}
+
+#[tokio::test]
+async fn new_sources_post_only_latest_posts() {
+    let source = |on_new_source: &str| -> Box<dyn Source> {
+        let account = format!("id: foxart\non_new_source: {on_new_source}");
+        Box::new(TestSource {
+            account: serde_yaml::from_str(&account).expect("account parses"),
+            ..*TestSource::new(vec![post(1), post(2), post(3)])
+        })
+    };
+    let planned = |plan: &[PlannedPost]| -> Vec<String> {
+        plan.iter().map(|p| p.post.id.to_string()).collect()
+    };
+
+    let mut cache = CacheRecords::new();
+    let (plan, errors) = Pipeline::new(&config())
+        .sources(vec![source("post_latest 2")])
+        .dry_run(&mut cache, false)
+        .await;
+    assert!(errors.is_empty());
+    assert_eq!(planned(&plan), ["2", "3"]);
+    assert_eq!(
+        cache.watermark("test", &Snowflake::Number(123456)),
+        Some(&Snowflake::Number(1))
+    );
+
+    let mut cache = CacheRecords::new();
+    let (plan, _) = Pipeline::new(&config())
+        .sources(vec![source("populate")])
+        .dry_run(&mut cache, false)
+        .await;
+    assert!(plan.is_empty());
+    assert_eq!(
+        cache.watermark("test", &Snowflake::Number(123456)),
+        Some(&Snowflake::Number(3))
+    );
+
+    // known sources post everything new
+    let (plan, _) = Pipeline::new(&config())
+        .sources(vec![TestSource::new(vec![post(4), post(5)])])
+        .dry_run(&mut cache, false)
+        .await;
+    assert_eq!(planned(&plan), ["4", "5"]);
+
+    assert!(
+        serde_yaml::from_str::<SocialAccount>("id: foxart\non_new_source: post_latest").is_err()
+    );
+}
+
+#[tokio::test]
+async fn bandwidth_cap_defers_posts() {
+    let mut config = config();
+    // traffic of posting only the first post
+    let traffic = Arc::new(Traffic::default());
+    Pipeline::new(&config)
+        .sources(vec![TestSource::new(vec![post(1)])])
+        .transport(Arc::new(SentPhoto))
+        .traffic(traffic.clone())
+        .run(&mut CacheRecords::new())
+        .await;
+    let limit = traffic.total().total();
+    config.max_bandwidth_per_run = Some(limit);
+    let traffic = Arc::new(Traffic::default());
+    let mut cache = CacheRecords::new();
+
+    let errors = Pipeline::new(&config)
+        .sources(vec![TestSource::new(vec![post(1), post(2)])])
+        .transport(Arc::new(SentPhoto))
+        .traffic(traffic.clone())
+        .run(&mut cache)
+        .await;
+
+    assert!(matches!(
+        &errors[..],
+        [Error::BandwidthExceeded { limit: l, .. }] if *l == limit
+    ));
+    assert_eq!(
+        cache.watermark("test", &Snowflake::Number(123456)),
+        Some(&Snowflake::Number(1))
+    );
+    assert!(traffic.total().uploaded > 0 && traffic.total().downloaded > 0);
+    // sent posts are counted for their source
+    let by_source = traffic.by_source();
+    assert_eq!(by_source.len(), 1);
+    assert_eq!(by_source[0].0, "test/foxart");
+    assert!(by_source[0].1.total() > 0);
+}
+
+#[test]
+fn string_ids_move_watermarks() {
+    let post = |id: &str| Post {
+        id: Snowflake::String(id.to_owned()),
+        ..post(1)
+    };
+    // caches of older versions store numbers
+    let mut cache: CacheRecords =
+        serde_json::from_str(r#"{"test":{"123456":5}}"#).expect("cache parses");
+    assert!(!cache.is_new("test", &post("5")));
+    assert!(cache.is_new("test", &post("a1")));
+
+    cache.remember("test", &post("a1"));
+    cache.remember("test", &post("9"));
+    assert!(!cache.is_new("test", &post("a0")));
+    assert!(cache.is_new("test", &post("a2")));
+    assert_eq!(
+        cache.watermark("test", &Snowflake::Number(123456)),
+        Some(&Snowflake::String("a1".to_owned()))
+    );
+}
+
+#[tokio::test]
+async fn text_posts_are_sent_as_messages() {
+    let mut text = post(2);
+    text.media.clear();
+    let source = |text_posts: bool| {
+        let mut source = TestSource::new(vec![text.clone()]);
+        source.account.text_posts = text_posts;
+        source
+    };
+
+    let (plan, _) = Pipeline::new(&config())
+        .sources(vec![source(false)])
+        .dry_run(&mut CacheRecords::new(), true)
+        .await;
+    assert_eq!(plan[0].status, PlanStatus::Skipped(SkipReason::NoMedia));
+
+    let transport = Arc::new(SentText::default());
+    let mut cache = CacheRecords::new();
+    let errors = Pipeline::new(&config())
+        .sources(vec![source(true)])
+        .transport(transport.clone())
+        .run(&mut cache)
+        .await;
+
+    assert!(errors.is_empty());
+    assert_eq!(*transport.methods.lock().unwrap(), ["getMe", "sendMessage"]);
+    assert_eq!(cache.posted[0].message_id, 42);
+}
+
+#[tokio::test]
+async fn backfill_keeps_watermark() {
+    let config = config();
+    let mut cache = CacheRecords::new();
+    cache.remember("test", &post(7));
+    cache.seed_link(&post(3).url);
+
+    let errors = Pipeline::new(&config)
+        .sources(vec![TestSource::new((1..=7).map(post).collect())])
+        .transport(Arc::new(SentPhoto))
+        .backfill(&mut cache, "test:foxart", 2..=4, Duration::ZERO)
+        .await;
+    assert!(errors.is_empty());
+    assert!(cache.is_linked(&post(2).url) && cache.is_linked(&post(4).url));
+    assert!(!cache.is_linked(&post(1).url) && !cache.is_linked(&post(5).url));
+    assert_eq!(
+        cache.watermark("test", &Snowflake::Number(123456)),
+        Some(&Snowflake::Number(7))
+    );
+
+    // nothing is sent again
+    let transport = Arc::new(SentText::default());
+    let errors = Pipeline::new(&config)
+        .sources(vec![TestSource::new((1..=7).map(post).collect())])
+        .transport(transport.clone())
+        .backfill(&mut cache, "test:foxart", 2..=4, Duration::ZERO)
+        .await;
+    assert!(errors.is_empty());
+    assert!(transport.methods.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn accounts_post_to_their_channels() {
+    let mut text = post(2);
+    text.media.clear();
+    let mut source = TestSource::new(vec![text]);
+    source.account.text_posts = true;
+    source.account.pin = true;
+    source.account.channel = Some(Snowflake::String("@foxart_vk".to_owned()));
+    let transport = Arc::new(SentText::default());
+    let mut cache = CacheRecords::new();
+
+    let errors = Pipeline::new(&config())
+        .sources(vec![source])
+        .transport(transport.clone())
+        .run(&mut cache)
+        .await;
+
+    assert!(errors.is_empty());
+    assert_eq!(
+        *transport.methods.lock().unwrap(),
+        ["getMe", "sendMessage", "pinChatMessage"]
+    );
+    assert_eq!(
+        *transport.chats.lock().unwrap(),
+        ["@foxart_vk", "@foxart_vk"]
+    );
+    assert_eq!(
+        cache.posted[0].chat,
+        Some(Snowflake::String("@foxart_vk".to_owned()))
+    );
+    assert_eq!(cache.pinned, None);
+    assert_eq!(cache.pinned_in["@foxart_vk"], 42);
+}
+
+/// Transport answering like Telegram to a bot that is only a member of the
+/// channel, remembering called methods.
+#[derive(Default)]
+struct Member {
+    methods: Mutex<Vec<String>>,
+}
+
+impl Transport for Member {
+    fn send(&self, request: Request) -> BoxFuture<'_, std::result::Result<String, HttpError>> {
+        let method = request.api_method().to_owned();
+        let body = match method.as_str() {
+            "getMe" => r#"{"ok":true,"result":{"id":7}}"#,
+            "getChatMember" => r#"{"ok":true,"result":{"status":"member"}}"#,
+            _ => {
+                r#"{"ok":false,"error_code":400,"description":"Bad Request: need administrator rights in the channel chat"}"#
+            }
+        };
+        self.methods.lock().unwrap().push(method);
+        Box::pin(async move { Ok(body.to_owned()) })
+    }
+}
+
+#[tokio::test]
+async fn channels_without_rights_are_reported_once() {
+    let transport = Arc::new(Member::default());
+    let mut cache = CacheRecords::new();
+
+    let errors = Pipeline::new(&config())
+        .sources(vec![TestSource::new(vec![post(1), post(2)])])
+        .transport(transport.clone())
+        .run(&mut cache)
+        .await;
+
+    assert!(matches!(
+        &errors[..],
+        [Error::CantPost { chat, status }] if chat == "@foxart" && status == "member"
+    ));
+    assert_eq!(
+        *transport.methods.lock().unwrap(),
+        ["getMe", "getChatMember"]
+    );
+    assert_eq!(cache.watermark("test", &Snowflake::Number(123456)), None);
+}
+
+#[tokio::test]
+async fn quiet_hours_hold_posts_back() {
+    let now = Local::now().time();
+    let quiet = TimeRange {
+        start: now - chrono::Duration::hours(1),
+        end: now + chrono::Duration::hours(1),
+    };
+    let mut source = TestSource::new((1..=7).map(post).collect());
+    source.account.quiet_hours = Some(quiet);
+    let mut cache = CacheRecords::new();
+    let errors = Pipeline::new(&config())
+        .sources(vec![source])
+        .transport(Arc::new(SentPhoto))
+        .run(&mut cache)
+        .await;
+    assert!(errors.is_empty());
+    assert!(cache.posted.is_empty());
+    assert_eq!(cache.held_posts.len(), 7);
+
+    // the source returns only the latest posts after quiet hours
+    let errors = Pipeline::new(&config())
+        .sources(vec![TestSource::new((3..=7).map(post).collect())])
+        .transport(Arc::new(SentPhoto))
+        .run(&mut cache)
+        .await;
+    assert!(errors.is_empty());
+    let posted: Vec<_> = cache.posted.iter().map(|p| p.url.clone()).collect();
+    assert_eq!(posted, (1..=7).map(|id| post(id).url).collect::<Vec<_>>());
+    assert!(cache.held_posts.is_empty());
+}
+
+#[tokio::test]
+async fn posts_wait_for_posting_window() {
+    let now = Local::now().time();
+    let mut config = config();
+    config.telegram.posting_window = Some(TimeRange {
+        start: now + chrono::Duration::hours(1),
+        end: now + chrono::Duration::hours(2),
+    });
+    let mut cache = CacheRecords::new();
+    let errors = Pipeline::new(&config)
+        .sources(vec![TestSource::new(vec![post(1), post(2)])])
+        .transport(Arc::new(SentPhoto))
+        .run(&mut cache)
+        .await;
+    assert!(errors.is_empty());
+    assert!(cache.posted.is_empty());
+    assert_eq!(cache.held_posts.len(), 2);
+
+    // the account window is used instead of the channel one
+    let mut source = TestSource::new(Vec::new());
+    source.account.posting_window = Some(TimeRange {
+        start: now - chrono::Duration::hours(1),
+        end: now + chrono::Duration::hours(1),
+    });
+    let errors = Pipeline::new(&config)
+        .sources(vec![source])
+        .transport(Arc::new(SentPhoto))
+        .run(&mut cache)
+        .await;
+    assert!(errors.is_empty());
+    assert_eq!(cache.posted.len(), 2);
+    assert!(cache.held_posts.is_empty());
+}
+
+#[tokio::test]
+async fn sources_with_many_held_posts_are_not_fetched() {
+    let now = Local::now().time();
+    let mut source = TestSource::new(vec![post(51)]);
+    source.account.quiet_hours = Some(TimeRange {
+        start: now - chrono::Duration::hours(1),
+        end: now + chrono::Duration::hours(1),
+    });
+    let mut cache = CacheRecords::new();
+    for id in 1..=50 {
+        cache.hold("test", &source.account, &post(id));
+    }
+
+    let errors = Pipeline::new(&config())
+        .sources(vec![source])
+        .transport(Arc::new(SentPhoto))
+        .run(&mut cache)
+        .await;
+    assert!(errors.is_empty());
+    assert_eq!(cache.held_posts.len(), 50);
+    assert!(cache.held_posts.iter().all(|h| h.post.url != post(51).url));
+}
+
+/// Transport answering every request like Telegram answers copying deleted
+/// messages.
+struct CopiedNothing;
+
+impl Transport for CopiedNothing {
+    fn send(&self, _request: Request) -> BoxFuture<'_, std::result::Result<String, HttpError>> {
+        Box::pin(async { Ok(r#"{"ok":true,"result":[]}"#.to_owned()) })
+    }
+}
+
+#[tokio::test]
+async fn copying_nothing_skips_post() {
+    let mut copied = post(1);
+    copied.messages = vec![5];
+    let mut source = TestSource::new(vec![copied]);
+    source.account.pin = true;
+    let mut cache = CacheRecords::new();
+
+    let errors = Pipeline::new(&config())
+        .sources(vec![source])
+        .transport(Arc::new(CopiedNothing))
+        .run(&mut cache)
+        .await;
+
+    let url = post(1).url;
+    assert!(matches!(
+        &errors[..],
+        [Error::Post { post, source: TelegramError::NothingCopied }] if *post == url
+    ));
+    assert!(cache.posted.is_empty());
+    assert_eq!(
+        cache.watermark("test", &Snowflake::Number(123456)),
+        Some(&Snowflake::Number(1))
+    );
+}
+
+/// Transport answering `getUpdates` with message 42 of `@foxart` and of
+/// another channel, and other requests like [`SentPhoto`].
+struct SameIds;
+
+impl Transport for SameIds {
+    fn send(&self, request: Request) -> BoxFuture<'_, std::result::Result<String, HttpError>> {
+        let body = match request.api_method() {
+            "getUpdates" => {
+                r#"{"ok":true,"result":[
+                    {"update_id":1,"channel_post":{"message_id":42,"chat":{"id":-100,"username":"foxart"},"text":"Fresh art #1"}},
+                    {"update_id":2,"channel_post":{"message_id":42,"chat":{"id":-200,"username":"foxnews"},"text":"Other art"}}
+                ]}"#
+            }
+            _ => r#"{"ok":true,"result":[{"message_id":42,"photo":[{"file_id":"f"}]}]}"#,
+        };
+        Box::pin(async move { Ok(body.to_owned()) })
+    }
+}
+
+#[tokio::test]
+async fn posts_of_other_channels_are_seeded() {
+    let mut cache = CacheRecords::new();
+    let errors = Pipeline::new(&config())
+        .sources(vec![TestSource::new(vec![post(1)])])
+        .transport(Arc::new(SameIds))
+        .run(&mut cache)
+        .await;
+    assert!(errors.is_empty());
+
+    let mut config = config();
+    config.dedup = true;
+    let errors = Pipeline::new(&config)
+        .sources(Vec::new())
+        .transport(Arc::new(SameIds))
+        .run(&mut cache)
+        .await;
+    assert!(errors.is_empty());
+    assert_eq!(cache.seeded, [text_hash("Other art")]);
+}
+
+/// Transport answering `sendMediaGroup` with `answers` in order, like an
+/// album of one photo for `true` and a server error for `false`, and other
+/// requests with an error.
+struct Flaky {
+    answers: Mutex<Vec<bool>>,
+    sent: Mutex<usize>,
+}
+
+impl Flaky {
+    fn new(answers: &[bool]) -> Self {
+        Self {
+            answers: Mutex::new(answers.iter().rev().copied().collect()),
+            sent: Mutex::new(0),
+        }
+    }
+}
+
+impl Transport for Flaky {
+    fn send(&self, request: Request) -> BoxFuture<'_, std::result::Result<String, HttpError>> {
+        let ok = request.api_method() == "sendMediaGroup" && {
+            *self.sent.lock().unwrap() += 1;
+            self.answers.lock().unwrap().pop().unwrap_or(false)
+        };
+        let body = if ok {
+            r#"{"ok":true,"result":[{"message_id":42,"photo":[{"file_id":"f"}]}]}"#
+        } else {
+            r#"{"ok":false,"error_code":500,"description":"Internal Server Error"}"#
+        };
+        Box::pin(async move { Ok(body.to_owned()) })
+    }
+}
+
+#[tokio::test]
+async fn breaker_stops_failing_chat() {
+    let mut config = config();
+    config.max_failures = 2;
+    let posts = || TestSource::new((1..=5).map(post).collect());
+
+    let transport = Arc::new(Flaky::new(&[false, false]));
+    let mut cache = CacheRecords::new();
+    let errors = Pipeline::new(&config)
+        .sources(vec![posts()])
+        .transport(transport.clone())
+        .run(&mut cache)
+        .await;
+    assert_eq!(*transport.sent.lock().unwrap(), 2);
+    assert!(matches!(
+        errors.last(),
+        Some(Error::Breaker { chat, failures: 2 }) if chat == "@foxart"
+    ));
+
+    // a success resets the count
+    let transport = Arc::new(Flaky::new(&[false, true, false, true, true]));
+    let mut cache = CacheRecords::new();
+    let errors = Pipeline::new(&config)
+        .sources(vec![posts()])
+        .transport(transport.clone())
+        .run(&mut cache)
+        .await;
+    assert_eq!(*transport.sent.lock().unwrap(), 5);
+    assert!(!errors.iter().any(|e| matches!(e, Error::Breaker { .. })));
+    assert_eq!(cache.posted.len(), 3);
+}
+
+/// Transport remembering media of every album, answering with a photo for
+/// each of them, with message ids from 1 by album. Pinned messages are
+/// remembered with their chats, other requests get an empty list.
+#[derive(Default)]
+struct Albums {
+    media: Mutex<Vec<serde_json::Value>>,
+    pins: Mutex<Vec<(String, String)>>,
+}
+
+impl Transport for Albums {
+    fn send(&self, request: Request) -> BoxFuture<'_, std::result::Result<String, HttpError>> {
+        let param = |name: &str| {
+            request
+                .query
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.clone())
+        };
+        if request.api_method() == "pinChatMessage" {
+            let pin = (param("chat_id").unwrap(), param("message_id").unwrap());
+            self.pins.lock().unwrap().push(pin);
+        }
+        let media: serde_json::Value = match param("media") {
+            Some(media) => serde_json::from_str(&media).expect("media is json"),
+            None => return Box::pin(async { Ok(r#"{"ok":true,"result":[]}"#.to_owned()) }),
+        };
+        let mut albums = self.media.lock().unwrap();
+        let photo = format!(
+            r#"{{"message_id":{},"photo":[{{"file_id":"f"}}]}}"#,
+            albums.len() + 1
+        );
+        let messages = vec![photo; media.as_array().map_or(0, Vec::len)].join(",");
+        albums.push(media);
+        Box::pin(async move { Ok(format!(r#"{{"ok":true,"result":[{messages}]}}"#)) })
+    }
+}
+
+#[tokio::test]
+async fn batched_posts_are_sent_as_albums() {
+    let mut posts: Vec<_> = (1..=14).map(post).collect();
+    posts[0].text = "Fresh art. Drawn in one evening".to_owned();
+    posts[1].text = String::new();
+    posts[2].media = vec![Media::Video("https://vk.com/3.mp4".to_owned())];
+    posts[3]
+        .media
+        .push(Media::Photo("https://sun9-1.userapi.com/b.jpg".to_owned()));
+    posts[13].text = "fox ".repeat(500);
+    let mut source = TestSource::new(posts);
+    source.account.batch = true;
+
+    let transport = Arc::new(Albums::default());
+    let errors = Pipeline::new(&config())
+        .sources(vec![source])
+        .transport(transport.clone())
+        .run(&mut CacheRecords::new())
+        .await;
+
+    assert!(errors.is_empty(), "{errors:?}");
+    let albums = transport.media.lock().unwrap();
+    let sizes: Vec<_> = albums.iter().map(|m| m.as_array().unwrap().len()).collect();
+    // the video and two-photo posts go alone, others by 10
+    assert_eq!(sizes, [10, 1, 2, 2]);
+    assert_eq!(albums[1][0]["type"], "video");
+    let caption = albums[0][0]["caption"].as_str().unwrap();
+    let lines: Vec<_> = caption.lines().collect();
+    assert_eq!(
+        lines[..3],
+        [
+            "1. <a href=\"https://vk.com/wall-123456_1\">Fresh art.</a>",
+            "2. <a href=\"https://vk.com/wall-123456_2\">post 2</a>",
+            "3. <a href=\"https://vk.com/wall-123456_5\">Fresh art #5</a>",
+        ]
+    );
+    assert!(caption.ends_with("src: vk // Fox Art"), "{caption}");
+    let caption = albums[3][0]["caption"].as_str().unwrap();
+    assert!(caption.starts_with("1. <a href=\"https://vk.com/wall-123456_13\">"));
+    // long captions are cut, keeping the markup valid
+    assert!(caption.ends_with("…</a>"), "{caption}");
+    assert!(caption.matches("fox").count() < 500);
+}
+
+#[tokio::test]
+async fn filtered_posts_are_skipped() {
+    let path = std::env::temp_dir().join(format!("secretfop-filter-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let mut config = config();
+    config.event_log = Some(path.clone());
+    let mut giveaway = post(2);
+    giveaway.text = "Giveaway! Repost to win".to_owned();
+    let chain = || {
+        Chain::default().filter(|_: &SocialAccount, post: &Post| !post.text.contains("Giveaway"))
+    };
+
+    let (plan, _) = Pipeline::new(&config)
+        .sources(vec![TestSource::new(vec![post(1), giveaway.clone()])])
+        .chain(chain())
+        .dry_run(&mut CacheRecords::new(), true)
+        .await;
+    let plan: Vec<_> = plan.iter().map(|p| (p.post.id.clone(), p.status)).collect();
+    assert_eq!(
+        plan,
+        [
+            (Snowflake::Number(1), PlanStatus::New),
+            (
+                Snowflake::Number(2),
+                PlanStatus::Skipped(SkipReason::Filtered)
+            )
+        ]
+    );
+
+    let transport = Arc::new(Albums::default());
+    let mut cache = CacheRecords::new();
+    let errors = Pipeline::new(&config)
+        .sources(vec![TestSource::new(vec![post(1), giveaway.clone()])])
+        .chain(chain())
+        .transport(transport.clone())
+        .run(&mut cache)
+        .await;
+    assert!(errors.is_empty(), "{errors:?}");
+    assert_eq!(transport.media.lock().unwrap().len(), 1);
+    assert!(!cache.is_new("test", &post(1)));
+
+    let log = std::fs::read_to_string(&path).expect("log is written");
+    let skips: Vec<serde_json::Value> = log
+        .lines()
+        .map(|l| serde_json::from_str(l).expect("line is json"))
+        .filter(|e: &serde_json::Value| e["event"] == "skip")
+        .collect();
+    assert_eq!(skips.len(), 1);
+    assert_eq!(skips[0]["post"], giveaway.url);
+    assert_eq!(skips[0]["reason"], "filtered");
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn only_pinned_accounts_pin_posts() {
+    let source = |account: &str, ids: &[u64]| -> Box<dyn Source> {
+        Box::new(TestSource {
+            account: serde_yaml::from_str(account).expect("account parses"),
+            ..*TestSource::new(ids.iter().map(|&id| post(id)).collect())
+        })
+    };
+
+    let transport = Arc::new(Albums::default());
+    let mut cache = CacheRecords::new();
+    let errors = Pipeline::new(&config())
+        .sources(vec![
+            source("id: news", &[1]),
+            source("id: art\npin: true", &[2]),
+            source("id: memes", &[3]),
+        ])
+        .transport(transport.clone())
+        .run(&mut cache)
+        .await;
+
+    assert!(errors.is_empty(), "{errors:?}");
+    assert_eq!(transport.media.lock().unwrap().len(), 3);
+    assert_eq!(
+        *transport.pins.lock().unwrap(),
+        [("@foxart".to_owned(), "2".to_owned())]
+    );
+    assert_eq!(cache.pinned, Some(2));
+}
+
+/// Transport answering like Telegram, remembering chats of copies. Copies to
+/// `limited` chat are refused with a long rate limit.
+struct Lanes {
+    limited: &'static str,
+    copies: Mutex<Vec<String>>,
+}
+
+impl Transport for Lanes {
+    fn send(&self, request: Request) -> BoxFuture<'_, std::result::Result<String, HttpError>> {
+        let chat = request
+            .query
+            .iter()
+            .find(|(key, _)| key == "chat_id")
+            .map(|(_, chat)| chat.clone());
+        let body = match (request.api_method(), chat) {
+            ("copyMessages", Some(chat)) if chat == self.limited => {
+                r#"{"ok":false,"error_code":429,"description":"Too Many Requests","parameters":{"retry_after":3600}}"#
+            }
+            ("copyMessages", Some(chat)) => {
+                self.copies.lock().unwrap().push(chat);
+                r#"{"ok":true,"result":[{"message_id":7}]}"#
+            }
+            ("sendMediaGroup", _) => {
+                r#"{"ok":true,"result":[{"message_id":42,"photo":[{"file_id":"f"}]}]}"#
+            }
+            _ => r#"{"ok":true,"result":[]}"#,
+        };
+        Box::pin(async move { Ok(body.to_owned()) })
+    }
+}
+
+#[tokio::test]
+async fn copy_lanes_wait_independently() {
+    let now = Local::now().time();
+    let source = || {
+        let mut source = TestSource::new(vec![post(1)]);
+        source.account =
+            serde_yaml::from_str("id: foxart\ncopy_to: ['@limited', '@closed', '@open']")
+                .expect("account parses");
+        source.account.copy_to[1].posting_window = Some(TimeRange {
+            start: now + chrono::Duration::hours(1),
+            end: now + chrono::Duration::hours(2),
+        });
+        source
+    };
+    let pending = |cache: &CacheRecords| {
+        let mut chats: Vec<_> = cache
+            .pending_copies
+            .iter()
+            .map(|c| c.chat.to_string())
+            .collect();
+        chats.sort();
+        chats
+    };
+
+    let transport = Arc::new(Lanes {
+        limited: "@limited",
+        copies: Mutex::new(Vec::new()),
+    });
+    let mut cache = CacheRecords::new();
+    let errors = Pipeline::new(&config())
+        .sources(vec![source()])
+        .transport(transport.clone())
+        .run(&mut cache)
+        .await;
+    assert!(matches!(
+        &errors[..],
+        [Error::Copy { chat, source: TelegramError::RateLimited { .. }, .. }] if chat == "@limited"
+    ));
+    assert_eq!(*transport.copies.lock().unwrap(), ["@open"]);
+    assert_eq!(pending(&cache), ["@closed", "@limited"]);
+
+    // the limited lane catches up, the closed one keeps waiting
+    let transport = Arc::new(Lanes {
+        limited: "",
+        copies: Mutex::new(Vec::new()),
+    });
+    let errors = Pipeline::new(&config())
+        .sources(vec![source()])
+        .transport(transport.clone())
+        .run(&mut cache)
+        .await;
+    assert!(errors.is_empty(), "{errors:?}");
+    assert_eq!(*transport.copies.lock().unwrap(), ["@limited"]);
+    assert_eq!(pending(&cache), ["@closed"]);
+}
@@ -0,0 +1,193 @@
+#![cfg(feature = "vk")]
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use futures::future::BoxFuture;
+use secretfop::{
+    config::SnowflakeRef,
+    http::{
+        ChaosTransport, HttpError, PacedTransport, PacingProfile, ProxyPool, RecordTransport,
+        ReplayTransport, Request, Transport,
+    },
+    sources::vk::{self, VKClient},
+    telegram,
+};
+
+/// Transport counting requests, that fails all of them if `broken`.
+struct CountingTransport {
+    broken: bool,
+    requests: AtomicUsize,
+}
+
+impl Transport for CountingTransport {
+    fn send(&self, _request: Request) -> BoxFuture<'_, Result<String, HttpError>> {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        Box::pin(async move {
+            if self.broken {
+                Err("connection refused".into())
+            } else {
+                Ok("{}".to_owned())
+            }
+        })
+    }
+}
+
+#[tokio::test]
+async fn replay_falls_back_to_method_name() {
+    let client = VKClient::new("token".to_owned())
+        .with_transport(Arc::new(ReplayTransport::new("tests/fixtures/vk")));
+
+    let feed = client
+        .get_posts(SnowflakeRef::Number(123456))
+        .send()
+        .await
+        .expect("recorded feed parses");
+
+    assert_eq!(feed.group_id, 123456);
+}
+
+#[tokio::test]
+async fn replay_reports_missing_response() {
+    let client = VKClient::new("token".to_owned())
+        .with_transport(Arc::new(ReplayTransport::new("tests/fixtures/telegram")));
+
+    let res = client.get_posts(SnowflakeRef::Number(123456)).send().await;
+
+    assert!(matches!(res, Err(e) if e.to_string().contains("no recorded response for wall.get-")));
+}
+
+#[test]
+fn record_name_ignores_host_and_credentials() {
+    let a = Request::get("https://api.vk.com/method/wall.get")
+        .bearer_auth("a")
+        .query("count", "5");
+    let b = Request::get("http://127.0.0.1:1234/wall.get")
+        .bearer_auth("b")
+        .query("count", "5");
+
+    assert_eq!(a.record_name(), b.record_name());
+    assert!(a.record_name().starts_with("wall.get-"));
+    assert_ne!(
+        a.record_name(),
+        a.clone().query("offset", "5").record_name()
+    );
+}
+
+#[tokio::test]
+async fn record_saves_redacted_responses() {
+    let dir = std::env::temp_dir().join(format!("secretfop-record-{}", std::process::id()));
+    let transport = RecordTransport::new(Arc::new(ReplayTransport::new("tests/fixtures/vk")), &dir)
+        .expect("directory is created")
+        .redact("Fox Art");
+
+    let request = Request::get("https://api.vk.com/method/wall.get").query("count", "5");
+    let res = transport.send(request.clone()).await.expect("replayed");
+    let recorded = std::fs::read_to_string(dir.join(request.record_name())).expect("recorded");
+    std::fs::remove_dir_all(&dir).ok();
+
+    assert!(res.contains("Fox Art"));
+    assert!(!recorded.contains("Fox Art") && recorded.contains("REDACTED"));
+    // the directory is gone, so the response can't be recorded
+    assert!(transport.send(request).await.is_err());
+}
+
+#[tokio::test]
+async fn proxy_pool_evicts_failing_proxy() {
+    let proxy = |broken| {
+        Arc::new(CountingTransport {
+            broken,
+            requests: AtomicUsize::new(0),
+        })
+    };
+    let (broken, working) = (proxy(true), proxy(false));
+    let pool = ProxyPool::new(vec![broken.clone(), working.clone()]);
+
+    for _ in 0..10 {
+        let res = pool
+            .send(Request::get("https://api.vk.com/method/wall.get"))
+            .await;
+        assert_eq!(res.expect("retried through working proxy"), "{}");
+    }
+
+    assert_eq!(broken.requests.load(Ordering::Relaxed), 3);
+    assert_eq!(working.requests.load(Ordering::Relaxed), 10);
+}
+
+#[tokio::test]
+async fn paced_transport_retries_network_errors() {
+    let broken = Arc::new(CountingTransport {
+        broken: true,
+        requests: AtomicUsize::new(0),
+    });
+    let paced = PacedTransport::new(
+        broken.clone(),
+        PacingProfile {
+            concurrency: 1,
+            delay: Duration::ZERO,
+            retries: 1,
+        },
+    );
+
+    let res = paced
+        .send(Request::get("https://api.vk.com/method/wall.get"))
+        .await;
+
+    assert!(res.is_err());
+    assert_eq!(broken.requests.load(Ordering::Relaxed), 2);
+
+    // posts may be made before the failure, so they are sent once
+    let res = paced
+        .send(Request::post(
+            "https://api.telegram.org/bottoken/sendMediaGroup",
+        ))
+        .await;
+
+    assert!(res.is_err());
+    assert_eq!(broken.requests.load(Ordering::Relaxed), 3);
+}
+
+#[tokio::test]
+async fn chaos_transport_simulates_failures() {
+    let inner = Arc::new(CountingTransport {
+        broken: false,
+        requests: AtomicUsize::new(0),
+    });
+
+    let calm = ChaosTransport::new(inner.clone(), 0.0);
+    for _ in 0..10 {
+        let res = calm
+            .send(Request::get("https://api.vk.com/method/wall.get"))
+            .await;
+        assert_eq!(res.expect("request is sent"), "{}");
+    }
+    assert_eq!(inner.requests.load(Ordering::Relaxed), 10);
+
+    let chaos = ChaosTransport::new(inner.clone(), 1.0);
+    for _ in 0..20 {
+        if let Ok(body) = chaos
+            .send(Request::get("https://api.vk.com/method/wall.get"))
+            .await
+        {
+            assert!(vk::parse_response::<serde_json::Value>(&body).is_err());
+        }
+        if let Ok(body) = chaos
+            .send(Request::post(
+                "https://api.telegram.org/bottoken/sendMessage",
+            ))
+            .await
+        {
+            assert!(telegram::parse_response::<serde_json::Value>(&body).is_err());
+        }
+        assert!(chaos
+            .download(Request::get("https://sun9-1.userapi.com/w.jpg"))
+            .await
+            .is_err());
+    }
+    assert_eq!(inner.requests.load(Ordering::Relaxed), 10);
+}
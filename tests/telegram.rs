@@ -0,0 +1,817 @@
+use std::{
+    borrow::Cow,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use secretfop::{
+    config::{CaptionMode, Cleanup, Snowflake, SnowflakeRef, Strip},
+    http::ReqwestTransport,
+    media::{MediaCache, DEFAULT_CACHE_SIZE},
+    sources::{channel::channel_posts, ForeignMedia, ForeignPost, Media, PostStats},
+    telegram::{self, TelegramClient, TelegramError, TelegramMessage},
+    text,
+};
+use wiremock::{
+    matchers::{header_regex, method, path, query_param},
+    Mock, MockServer, ResponseTemplate,
+};
+
+fn fixture(name: &str) -> String {
+    std::fs::read_to_string(format!("tests/fixtures/telegram/{name}")).expect("fixture exists")
+}
+
+fn post() -> ForeignPost<'static, &'static str> {
+    ForeignPost {
+        id: SnowflakeRef::Number(4321),
+        source_id: SnowflakeRef::Number(123456),
+        date: 1677600000,
+        text: Cow::Borrowed("Fresh art & <friends>"),
+        media: vec![
+            ForeignMedia::Photo("https://sun9-1.userapi.com/w.jpg"),
+            ForeignMedia::Video("https://example.com/clip.mp4"),
+        ],
+        extra: Vec::new(),
+        stats: None,
+        source: "vk // Fox Art",
+        url: "https://vk.com/wall-123456_4321",
+    }
+}
+
+async fn serve(name: &str) -> (MockServer, TelegramClient) {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/bottoken/sendMediaGroup"))
+        .and(query_param("chat_id", "@foxart"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(fixture(name)))
+        .mount(&server)
+        .await;
+    let client = TelegramClient::new("token".to_owned(), Snowflake::String("@foxart".to_owned()))
+        .with_base_url(server.uri());
+
+    (server, client)
+}
+
+#[tokio::test]
+async fn send_media_group_returns_first_message_id() {
+    let (server, client) = serve("send_media_group.json").await;
+
+    let id = client
+        .send_message()
+        .by_foreign(&post())
+        .send()
+        .await
+        .expect("message is sent");
+    assert_eq!(id, 101);
+
+    let requests = server.received_requests().await.expect("recording enabled");
+    let (_, media) = requests[0]
+        .url
+        .query_pairs()
+        .find(|(k, _)| k == "media")
+        .expect("media is sent");
+    let media: serde_json::Value = serde_json::from_str(&media).expect("media is json");
+    assert_eq!(media[0]["type"], "photo");
+    assert_eq!(media[0]["parse_mode"], "HTML");
+    assert_eq!(
+        media[0]["caption"],
+        "Fresh art &amp; &lt;friends&gt;\n\nsrc: <a href=\"https://vk.com/wall-123456_4321\">vk // Fox Art</a>"
+    );
+    assert_eq!(media[1]["type"], "video");
+    assert!(media[1].get("caption").is_none());
+}
+
+#[tokio::test]
+async fn send_media_group_reports_rate_limit() {
+    let (_server, client) = serve("too_many_requests.json").await;
+    let client = client.with_max_flood_wait(Duration::ZERO);
+
+    let err = client
+        .send_message()
+        .by_foreign(&post())
+        .send()
+        .await
+        .expect_err("error response");
+
+    assert!(matches!(
+        err,
+        TelegramError::RateLimited { timeout } if timeout == Duration::from_secs(35)
+    ));
+}
+
+#[tokio::test]
+async fn send_media_group_reports_missing_chat() {
+    let (_server, client) = serve("chat_not_found.json").await;
+
+    let err = client
+        .send_message()
+        .by_foreign(&post())
+        .send()
+        .await
+        .expect_err("error response");
+
+    assert!(matches!(err, TelegramError::ChatNotFound { .. }));
+}
+
+#[test]
+fn errors_are_typed_by_description() {
+    let error = |code, description| {
+        let json = serde_json::json!({"ok": false, "error_code": code, "description": description});
+        telegram::parse_response::<u64>(&json.to_string()).expect_err("error response")
+    };
+
+    assert!(matches!(
+        error(403, "Forbidden: bot was kicked from the channel chat"),
+        TelegramError::Kicked { .. }
+    ));
+    assert!(matches!(
+        error(400, "Bad Request: message caption is too long"),
+        TelegramError::CaptionTooLong
+    ));
+    assert!(matches!(
+        error(400, "Bad Request: wrong file identifier/HTTP URL specified"),
+        TelegramError::BadMedia { .. }
+    ));
+    assert!(matches!(
+        error(400, "Bad Request: message to edit not found"),
+        TelegramError::Server {
+            error_code: 400,
+            ..
+        }
+    ));
+}
+
+#[test]
+fn caption_renders_template() {
+    let post = ForeignPost {
+        stats: Some(PostStats {
+            likes: 120,
+            views: 5300,
+            ..Default::default()
+        }),
+        ..post()
+    };
+
+    let caption = telegram::caption(
+        &post,
+        CaptionMode::Full,
+        0,
+        Some("{text}\n\n❤️ {likes} 👁 {views} {unknown}\n{source}"),
+    );
+
+    assert_eq!(
+        caption,
+        "Fresh art &amp; &lt;friends&gt;\n\n❤️ 120 👁 5300 {unknown}\nvk // Fox Art"
+    );
+    assert_eq!(
+        telegram::caption(&post, CaptionMode::Full, 0, None),
+        "Fresh art &amp; &lt;friends&gt;\n\nsrc: <a href=\"https://vk.com/wall-123456_4321\">vk // Fox Art</a>"
+    );
+}
+
+#[test]
+fn export_texts_joins_formatted_parts() {
+    let texts = telegram::export_texts(&fixture("export.json")).expect("export parses");
+
+    assert_eq!(
+        texts,
+        [
+            "Fresh art by Pavel & friends <3",
+            "Sketch by Fox https://vk.com/foxart",
+            "Fox\n\nsrc: vk // Fox Art"
+        ]
+    );
+    assert_eq!(
+        telegram::export_links(&fixture("export.json")).expect("export parses"),
+        ["https://vk.com/wall-123456_4321"]
+    );
+}
+
+#[tokio::test]
+async fn send_messages_returns_file_ids() {
+    let (_server, client) = serve("send_media_group.json").await;
+
+    let messages = client
+        .send_message()
+        .by_foreign(&post())
+        .send_messages()
+        .await
+        .expect("message is sent")
+        .messages;
+
+    let photo = messages[0].photo.last().map(|f| f.file_id.as_str());
+    let video = messages[1].video.as_ref().map(|f| f.file_id.as_str());
+    assert_eq!((photo, video), (Some("AgACAgIAAx0"), Some("BAACAgIAAx0")));
+}
+
+#[tokio::test]
+async fn copy_messages_sends_album_ids() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/bottoken/copyMessages"))
+        .and(query_param("chat_id", "@foxart_en"))
+        .and(query_param("from_chat_id", "@foxart"))
+        .and(query_param("message_ids", "[101,102]"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_string(
+                r#"{"ok": true, "result": [{"message_id": 7}, {"message_id": 8}]}"#,
+            ),
+        )
+        .mount(&server)
+        .await;
+    let client = TelegramClient::new("token".to_owned(), Snowflake::String("@foxart".to_owned()))
+        .with_base_url(server.uri());
+
+    let ids = client
+        .copy_messages(
+            &Snowflake::String("@foxart_en".to_owned()),
+            &client.channel_id,
+            &[101, 102],
+        )
+        .await
+        .expect("messages are copied");
+
+    assert_eq!(ids, [7, 8]);
+}
+
+#[tokio::test]
+async fn failed_url_media_is_uploaded() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/w.jpg"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(vec![0xff, 0xd8, 0xff]))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/bottoken/sendMediaGroup"))
+        .and(header_regex("content-type", "^multipart/form-data"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(fixture("send_media_group.json")))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/bottoken/sendMediaGroup"))
+        .respond_with(ResponseTemplate::new(400).set_body_string(
+            r#"{"ok": false, "error_code": 400, "description": "Bad Request: failed to send message #1 with the error message \"WEBPAGE_CURL_FAILED\""}"#,
+        ))
+        .mount(&server)
+        .await;
+    let dir = std::env::temp_dir().join(format!("secretfop-upload-{}", std::process::id()));
+    let client = TelegramClient::new("token".to_owned(), Snowflake::String("@foxart".to_owned()))
+        .with_base_url(server.uri())
+        .with_media_cache(Arc::new(MediaCache::new(
+            &dir,
+            DEFAULT_CACHE_SIZE,
+            Arc::new(ReqwestTransport::default()),
+        )));
+    let photo = format!("{}/w.jpg", server.uri());
+
+    let res = client
+        .send_message()
+        .text("Fresh art")
+        .media([
+            ForeignMedia::Photo(&photo),
+            ForeignMedia::Video("https://vk.com/doc-123456_600000001"),
+        ])
+        .send()
+        .await;
+    std::fs::remove_dir_all(&dir).ok();
+    assert_eq!(res.expect("message is sent"), 101);
+
+    let requests = server.received_requests().await.expect("recording enabled");
+    let upload = requests.last().expect("upload is sent");
+    let (_, media) = upload
+        .url
+        .query_pairs()
+        .find(|(k, _)| k == "media")
+        .expect("media is sent");
+    let media: serde_json::Value = serde_json::from_str(&media).expect("media is json");
+    assert_eq!(media[0]["media"], "attach://file0");
+    assert_eq!(media[1]["media"], "https://vk.com/doc-123456_600000001");
+}
+
+#[tokio::test]
+async fn rejected_media_is_dropped() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/bottoken/sendMediaGroup"))
+        .respond_with(ResponseTemplate::new(400).set_body_string(
+            r#"{"ok": false, "error_code": 400, "description": "Bad Request: failed to send message #1 with the error message \"WEBPAGE_MEDIA_EMPTY\""}"#,
+        ))
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/bottoken/sendMediaGroup"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(fixture("send_media_group.json")))
+        .mount(&server)
+        .await;
+    let client = TelegramClient::new("token".to_owned(), Snowflake::String("@foxart".to_owned()))
+        .with_base_url(server.uri());
+
+    let sent = client
+        .send_message()
+        .text("Fresh art")
+        .media([
+            ForeignMedia::Photo("https://sun9-1.userapi.com/broken.jpg"),
+            ForeignMedia::Photo("https://sun9-1.userapi.com/w.jpg"),
+        ])
+        .send_messages()
+        .await
+        .expect("message is sent");
+    assert_eq!(sent.dropped, [0]);
+    assert_eq!(sent.by_media().next().map(|(i, _)| i), Some(1));
+
+    let requests = server.received_requests().await.expect("recording enabled");
+    let (_, media) = requests[1]
+        .url
+        .query_pairs()
+        .find(|(k, _)| k == "media")
+        .expect("media is sent");
+    let media: serde_json::Value = serde_json::from_str(&media).expect("media is json");
+    assert_eq!(media.as_array().map(Vec::len), Some(1));
+    assert_eq!(media[0]["media"], "https://sun9-1.userapi.com/w.jpg");
+    assert_eq!(media[0]["caption"], "Fresh art");
+}
+
+#[test]
+fn broken_html_falls_back_to_plain_text() {
+    assert!(
+        telegram::validate_html("<b>bold</b> &amp; <a href=\"https://vk.com\">link</a>").is_ok()
+    );
+    assert!(telegram::validate_html("<b>bold</i>").is_err());
+    assert!(telegram::validate_html("<marquee>hi</marquee>").is_err());
+    assert!(telegram::validate_html("fish & chips").is_err());
+
+    assert_eq!(
+        telegram::sanitize_html("<b>Fresh art</i> &amp; friends"),
+        "Fresh art &amp; friends"
+    );
+    assert!(matches!(
+        telegram::sanitize_html("<i>ok</i>"),
+        Cow::Borrowed("<i>ok</i>")
+    ));
+}
+
+#[test]
+fn caption_length_is_counted_in_utf16() {
+    assert_eq!(text::utf16_len("🦊🦊 fox"), 8);
+    assert_eq!(
+        telegram::html_len("<b>🦊</b> &amp; <a href=\"https://vk.com\">fox</a>"),
+        8
+    );
+
+    // every fox is two units, so only two of them fit into five
+    assert_eq!(
+        text::summarize("🦊🦊🦊🦊", CaptionMode::Truncate, 5),
+        Some("🦊🦊")
+    );
+    assert_eq!(text::summarize("🦊🦊", CaptionMode::Truncate, 4), None);
+}
+
+#[test]
+fn first_sentence_is_summarized() {
+    let summarize = |s| text::summarize(s, CaptionMode::FirstSentence, 0);
+
+    assert_eq!(summarize("Fresh art! Drawn at night"), Some("Fresh art!"));
+    assert_eq!(summarize("Fresh art\nDrawn at night"), Some("Fresh art"));
+    // abbreviations and initials do not end sentences
+    assert_eq!(
+        summarize("Art by Mr. Fox, e.g. this one. More soon"),
+        Some("Art by Mr. Fox, e.g. this one.")
+    );
+    assert_eq!(
+        summarize("Drawn by J. R. Fox. More soon"),
+        Some("Drawn by J. R. Fox.")
+    );
+    assert_eq!(summarize("Wait... it moves"), Some("Wait..."));
+    assert_eq!(summarize("Wait… it moves"), Some("Wait…"));
+    assert_eq!(summarize("v1.2 is out. Update"), Some("v1.2 is out."));
+    // text of one sentence is posted as is
+    assert_eq!(summarize("Fresh art"), None);
+    assert_eq!(summarize("Fresh art.  \n"), None);
+    assert_eq!(summarize("Art by Mr. Fox"), None);
+}
+
+#[test]
+fn mangled_text_is_normalized() {
+    let normalize = |s| text::normalize(Cow::Borrowed(s)).into_owned();
+
+    assert_eq!(normalize("РџСЂРёРІРµС‚, 🦊 cafÃ©!"), "Привет, 🦊 café!");
+    assert_eq!(normalize(r"\xcf\xf0\xe8\xe2\xe5\xf2 fox"), "Привет fox");
+    assert_eq!(normalize(r"\xd0\x9f\xd1\x80\xd0\xb8"), "При");
+    // decomposed é
+    assert_eq!(normalize("cafe\u{301}"), "café");
+    assert!(matches!(
+        text::normalize(Cow::Borrowed("Привет, café façade")),
+        Cow::Borrowed(_)
+    ));
+}
+
+#[test]
+fn normalization_changes_only_mangled_text() {
+    let changed = [
+        ("ÐŸÑ€Ð¸Ð²ÐµÑ‚ fox", "Привет fox"),
+        ("Â£5", "£5"),
+        (r"\xf0\x9f\xa6\x8a fox", "🦊 fox"),
+        // angstrom and ohm signs
+        ("\u{212b} \u{2126}", "\u{c5} \u{3a9}"),
+    ];
+    for (text, normalized) in changed {
+        assert_eq!(text::normalize(Cow::Borrowed(text)), normalized, "{text:?}");
+    }
+
+    let kept = [
+        "plain fox",
+        r"path C:\x41",
+        "Привет, мир",
+        "naïve zoë 🦊",
+        "Ünïcödé",
+        "ﬁ ligature",
+        "fox\u{200b}art",
+    ];
+    for text in kept {
+        assert!(
+            matches!(text::normalize(Cow::Borrowed(text)), Cow::Borrowed(_)),
+            "{text:?} is kept"
+        );
+    }
+}
+
+#[test]
+fn mentions_and_links_are_stripped() {
+    let cases: &[(Strip, &str, &str)] = &[
+        (Strip::Mentions, "[club1|Fox Art] draws", "draws"),
+        (Strip::Mentions, "art by @foxart today", "art by today"),
+        (Strip::Mentions, "@foxart (Fox Art) drew it", "drew it"),
+        (Strip::Mentions, "*foxart\nhi", "hi"),
+        (Strip::Mentions, "mail fox@vk.com", "mail fox@vk.com"),
+        (Strip::Mentions, "price 5*3", "price 5*3"),
+        (Strip::Links, "see https://vk.com/foxart now", "see now"),
+        (Strip::Links, "www.fox.art here", "here"),
+        (Strip::Links, "join t.me/foxart", "join"),
+        (Strip::Links, "http://fox.art/a, ok", "ok"),
+        (Strip::Links, "art:https://fox.art/a", "art:"),
+        (Strip::Links, "myvk.com/fox", "myvk.com/fox"),
+        (Strip::Links, "fox.art", "fox.art"),
+    ];
+
+    for &(strip, text, stripped) in cases {
+        let res = text::strip(Cow::Borrowed(text), &[strip]);
+        assert_eq!(res, stripped, "{strip:?} of {text:?}");
+        assert_eq!(matches!(res, Cow::Borrowed(_)), text == stripped);
+    }
+}
+
+#[test]
+fn text_is_cleaned_up() {
+    let family = "👨\u{200d}👩\u{200d}👧\u{200d}👦";
+    let cases: &[(Cleanup, &str, &str)] = &[
+        (Cleanup::Emoji, "fox 🦊🦊🦊🦊🦊 art", "fox 🦊🦊🦊 art"),
+        (Cleanup::Emoji, "fox 🦊 🦊 🦊 🦊 art", "fox 🦊🦊🦊 art"),
+        (Cleanup::Emoji, "fox 🦊🦊🦊 art", "fox 🦊🦊🦊 art"),
+        (Cleanup::Emoji, "👍🏽👍🏽👍🏽👍🏽👍🏽", "👍🏽👍🏽👍🏽"),
+        (Cleanup::Emoji, "Fresh art\n🦊 🎨\n✨", "Fresh art"),
+        (Cleanup::Emoji, "Fresh art\n🦊\n\n", "Fresh art"),
+        (Cleanup::Emoji, "Fresh art 🦊", "Fresh art 🦊"),
+        (Cleanup::BlankLines, "a\n\n\n\nb", "a\n\nb"),
+        (Cleanup::BlankLines, "a\n \n\t\nb", "a\n\nb"),
+        (Cleanup::BlankLines, "a\n\nb", "a\n\nb"),
+        (Cleanup::Quotes, "„fox‟ ‘art’", "\"fox\" 'art'"),
+        (Cleanup::TrailingWhitespace, "fox \nart\t\n", "fox\nart"),
+    ];
+
+    for &(cleanup, text, cleaned) in cases {
+        let res = text::cleanup(Cow::Borrowed(text), &[cleanup]);
+        assert_eq!(res, cleaned, "{cleanup:?} of {text:?}");
+        assert_eq!(matches!(res, Cow::Borrowed(_)), text == cleaned);
+    }
+
+    // joined emoji count as one
+    let families = |n| family.repeat(n);
+    let cleanup = |s: &str| text::cleanup(Cow::Borrowed(s), &[Cleanup::Emoji]).into_owned();
+    assert_eq!(cleanup(&families(3)), families(3));
+    assert_eq!(cleanup(&families(5)), families(3));
+}
+
+#[cfg(feature = "lang-detect")]
+#[test]
+fn languages_are_detected() {
+    let code = |code| text::lang_from_code(code).map(|lang| lang.code());
+    assert_eq!(code("ru"), Some("rus"));
+    assert_eq!(code("RUS"), Some("rus"));
+    assert_eq!(code("uk"), Some("ukr"));
+    assert_eq!(code("xx"), None);
+
+    let detect = |text| text::detect_language(text).map(|lang| lang.code());
+    assert_eq!(
+        detect("Лиса нарисовала новую картину этим вечером, посмотрите её"),
+        Some("rus")
+    );
+    assert_eq!(
+        detect("The fox has drawn a new picture this evening, take a look"),
+        Some("eng")
+    );
+    // too short to tell
+    assert_eq!(detect("ok"), None);
+    assert_eq!(detect("🦊🦊"), None);
+
+    let account: secretfop::config::SocialAccount =
+        serde_yaml::from_str("id: foxart\nlanguages: [ru, uk]").expect("account parses");
+    assert!(account.accepts_language("Лиса нарисовала новую картину этим вечером, посмотрите её"));
+    assert!(!account.accepts_language("The fox has drawn a new picture this evening, take a look"));
+    assert!(account.accepts_language("🦊🦊"));
+}
+
+#[test]
+fn truncation_keeps_html_whole() {
+    assert_eq!(
+        telegram::truncate_html("<b>bold text</b> and more", 8),
+        "<b>bold te…</b>"
+    );
+    assert_eq!(telegram::truncate_html("a &amp; b", 3), "a…");
+    assert_eq!(
+        telegram::truncate_html("<a href=\"https://vk.com\">link</a>", 3),
+        "<a href=\"https://vk.com\">li…</a>"
+    );
+    assert!(matches!(
+        telegram::truncate_html("<i>ok</i>", 2),
+        Cow::Borrowed("<i>ok</i>")
+    ));
+}
+
+#[tokio::test]
+async fn text_message_gets_link_button() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/bottoken/sendMessage"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(
+            r#"{"ok":true,"result":{"message_id":7,"chat":{"id":-100123},"date":1677600000,"text":"Fresh art"}}"#,
+        ))
+        .mount(&server)
+        .await;
+    let client = TelegramClient::new("token".to_owned(), Snowflake::String("@foxart".to_owned()))
+        .with_base_url(server.uri());
+
+    let id = client
+        .send_message()
+        .text("Fresh art")
+        .button("vk // Fox Art", "https://vk.com/wall-123456_4321")
+        .send()
+        .await
+        .expect("message is sent");
+    assert_eq!(id, 7);
+
+    let requests = server.received_requests().await.expect("recording enabled");
+    let (_, markup) = requests[0]
+        .url
+        .query_pairs()
+        .find(|(k, _)| k == "reply_markup")
+        .expect("markup is sent");
+    let markup: serde_json::Value = serde_json::from_str(&markup).expect("markup is json");
+    assert_eq!(
+        markup["inline_keyboard"][0][0]["url"],
+        "https://vk.com/wall-123456_4321"
+    );
+}
+
+#[tokio::test]
+async fn dead_media_is_sent_as_links() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/bottoken/sendMessage"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(
+            r#"{"ok":true,"result":{"message_id":7,"chat":{"id":-100123},"date":1677600000,"text":"Fresh art"}}"#,
+        ))
+        .mount(&server)
+        .await;
+    let client = TelegramClient::new("token".to_owned(), Snowflake::String("@foxart".to_owned()))
+        .with_base_url(server.uri());
+
+    client
+        .send_message()
+        .text("Fresh art")
+        .media([
+            ForeignMedia::Photo("https://sun9-1.userapi.com/w.jpg"),
+            ForeignMedia::Video("https://example.com/clip.mp4?a=1&b=2"),
+        ])
+        .media_as_links()
+        .send()
+        .await
+        .expect("message is sent");
+
+    let requests = server.received_requests().await.expect("recording enabled");
+    let (_, text) = requests[0]
+        .url
+        .query_pairs()
+        .find(|(k, _)| k == "text")
+        .expect("text is sent");
+    assert_eq!(
+        text,
+        "Fresh art\n\n<a href=\"https://sun9-1.userapi.com/w.jpg\">photo</a> · \
+         <a href=\"https://example.com/clip.mp4?a=1&amp;b=2\">video</a>"
+    );
+}
+
+#[test]
+fn channel_album_is_one_post() {
+    let messages: Vec<TelegramMessage> = serde_json::from_value(serde_json::json!([
+        {
+            "message_id": 7, "date": 1677600000, "media_group_id": "a1",
+            "chat": {"id": -1001234567890i64, "title": "Fox archive"},
+            "photo": [{"file_id": "small"}, {"file_id": "big"}], "caption": "Fresh art"
+        },
+        {
+            "message_id": 8, "date": 1677600000, "media_group_id": "a1",
+            "chat": {"id": -1001234567890i64, "title": "Fox archive"},
+            "video": {"file_id": "clip"}
+        },
+        {
+            "message_id": 9, "date": 1677600060,
+            "chat": {"id": -1001234567890i64, "title": "Fox archive"},
+            "text": "Hello"
+        }
+    ]))
+    .expect("messages parse");
+    let channel = Snowflake::String("-1001234567890".to_owned());
+
+    let posts = channel_posts(&channel, &messages.iter().collect::<Vec<_>>());
+
+    assert_eq!(posts.len(), 2);
+    assert_eq!(posts[0].messages, [7, 8]);
+    assert_eq!(posts[0].text, "Fresh art");
+    assert_eq!(posts[0].url, "https://t.me/c/1234567890/7");
+    assert_eq!(posts[0].source, "telegram // Fox archive");
+    assert!(matches!(
+        &posts[0].media[..],
+        [Media::Photo(photo), Media::Video(video)] if photo == "big" && video == "clip"
+    ));
+    assert_eq!(posts[1].messages, [9]);
+    assert!(posts[1].media.is_empty());
+}
+
+#[tokio::test]
+async fn short_rate_limits_are_waited_out() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/bottoken/pinChatMessage"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(
+            r#"{"ok":false,"error_code":429,"description":"Too Many Requests: retry after 1","parameters":{"retry_after":1}}"#,
+        ))
+        .up_to_n_times(1)
+        .with_priority(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/bottoken/pinChatMessage"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"ok":true,"result":true}"#))
+        .mount(&server)
+        .await;
+    let channel = Snowflake::String("@foxart".to_owned());
+    let client =
+        TelegramClient::new("token".to_owned(), channel.clone()).with_base_url(server.uri());
+
+    let started = std::time::Instant::now();
+    client
+        .pin_message(&channel, 42)
+        .await
+        .expect("pinned after waiting");
+
+    assert!(started.elapsed() >= Duration::from_secs(1));
+    let requests = server.received_requests().await.expect("recording enabled");
+    assert_eq!(requests.len(), 2);
+}
+
+#[test]
+fn audio_and_documents_keep_metadata() {
+    let client = TelegramClient::new("token".to_owned(), Snowflake::String("@foxart".to_owned()));
+    let message = client
+        .send_message()
+        .text("<b>mix</b>")
+        .media([
+            ForeignMedia::Audio {
+                url: "https://example.com/song.mp3",
+                title: Some("Fox Song"),
+                performer: Some("Fox"),
+            },
+            ForeignMedia::Document {
+                url: "https://example.com/a.pdf",
+                filename: Some("art.pdf"),
+                mime: Some("application/pdf"),
+            },
+        ])
+        .render()
+        .expect("message renders");
+
+    let media = &message.to_json()["media"];
+    assert_eq!(media[0]["type"], "audio");
+    assert_eq!(media[0]["title"], "Fox Song");
+    assert_eq!(media[0]["performer"], "Fox");
+    assert_eq!(media[0]["caption"], "<b>mix</b>");
+    assert_eq!(media[1]["type"], "document");
+    assert!(media[1].get("title").is_none());
+
+    let media: Vec<Media> = serde_json::from_str(
+        r#"[{"type":"photo","url":"p"},{"type":"document","url":"d","filename":"art.pdf"}]"#,
+    )
+    .expect("media parse");
+    assert_eq!(
+        media[1],
+        Media::Document {
+            url: "d".to_owned(),
+            filename: Some("art.pdf".to_owned()),
+            mime: None,
+        }
+    );
+    let json = serde_json::to_string(&media).expect("media serialize");
+    assert_eq!(
+        json,
+        r#"[{"type":"photo","url":"p"},{"type":"document","url":"d","filename":"art.pdf"}]"#
+    );
+}
+
+#[tokio::test]
+async fn mixed_media_are_sent_in_groups() {
+    let server = MockServer::start().await;
+    let next = Arc::new(AtomicU64::new(1));
+    Mock::given(method("POST"))
+        .and(path("/bottoken/sendMediaGroup"))
+        .respond_with(move |req: &wiremock::Request| {
+            let (_, media) = req
+                .url
+                .query_pairs()
+                .find(|(k, _)| k == "media")
+                .expect("media is sent");
+            let media: Vec<serde_json::Value> =
+                serde_json::from_str(&media).expect("media is json");
+            let messages: Vec<_> = media
+                .iter()
+                .map(|_| serde_json::json!({ "message_id": next.fetch_add(1, Ordering::Relaxed) }))
+                .collect();
+            ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "ok": true,
+                "result": messages,
+            }))
+        })
+        .mount(&server)
+        .await;
+    let client = TelegramClient::new("token".to_owned(), Snowflake::String("@foxart".to_owned()))
+        .with_base_url(server.uri());
+
+    let sent = client
+        .send_message()
+        .text("Fresh art")
+        .media([
+            ForeignMedia::Photo("https://sun9-1.userapi.com/a.jpg"),
+            ForeignMedia::Audio {
+                url: "https://example.com/song.mp3",
+                title: None,
+                performer: None,
+            },
+            ForeignMedia::Video("https://example.com/clip.mp4"),
+            ForeignMedia::Document {
+                url: "https://example.com/a.pdf",
+                filename: None,
+                mime: None,
+            },
+        ])
+        .send_messages()
+        .await
+        .expect("message is sent");
+    let ids: Vec<_> = sent.by_media().map(|(i, m)| (i, m.message_id)).collect();
+    assert_eq!(ids, [(0, 1), (2, 2), (1, 3), (3, 4)]);
+    assert!(sent.dropped.is_empty());
+
+    let requests = server.received_requests().await.expect("recording enabled");
+    let groups: Vec<serde_json::Value> = requests
+        .iter()
+        .map(|req| {
+            let (_, media) = req
+                .url
+                .query_pairs()
+                .find(|(k, _)| k == "media")
+                .expect("media is sent");
+            serde_json::from_str(&media).expect("media is json")
+        })
+        .collect();
+    let types: Vec<Vec<_>> = groups
+        .iter()
+        .map(|g| {
+            g.as_array()
+                .unwrap()
+                .iter()
+                .map(|m| m["type"].clone())
+                .collect()
+        })
+        .collect();
+    assert_eq!(
+        types,
+        [vec!["photo", "video"], vec!["audio"], vec!["document"]]
+    );
+    assert_eq!(groups[0][0]["caption"], "Fresh art");
+    assert!(groups[1][0].get("caption").is_none());
+}
@@ -0,0 +1,337 @@
+#![cfg(feature = "vk")]
+
+use std::sync::Arc;
+
+use secretfop::{
+    config::{SnowflakeRef, VKTokenKind},
+    http::{ReqwestTransport, USER_AGENT},
+    sources::{
+        vk::{VKClient, VKError, VKMedia},
+        ContentKind,
+    },
+};
+use wiremock::{
+    matchers::{header, method, path, query_param},
+    Mock, MockServer, ResponseTemplate,
+};
+
+fn fixture(name: &str) -> String {
+    std::fs::read_to_string(format!("tests/fixtures/vk/{name}")).expect("fixture exists")
+}
+
+async fn serve(name: &str) -> (MockServer, VKClient) {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/wall.get"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(fixture(name)))
+        .mount(&server)
+        .await;
+    let client = VKClient::new("token".to_owned()).with_base_url(server.uri());
+
+    (server, client)
+}
+
+#[tokio::test]
+async fn wall_get_parses_feed() {
+    let (_server, client) = serve("wall.get.json").await;
+
+    let feed = client
+        .get_posts(SnowflakeRef::Number(123456))
+        .send()
+        .await
+        .expect("feed parses");
+
+    assert_eq!(feed.group_id, 123456);
+    assert_eq!(feed.group_source_name, "vk // Fox Art");
+    // ad is filtered out
+    let ids: Vec<_> = feed.items.iter().map(|i| i.id).collect();
+    assert_eq!(ids, [4321, 4319]);
+
+    let item = &feed.items[0];
+    assert_eq!(item.date, 1677600000);
+    assert_eq!(item.text, "Fresh art by [id1|Pavel] & friends <3");
+    assert!(matches!(
+        &item.media[..],
+        [VKMedia::Photo(photo), VKMedia::Animation(gif)]
+            if photo == "https://sun9-1.userapi.com/w.jpg"
+                && gif == "https://vk.com/doc-123456_600000001"
+    ));
+    assert_eq!(item.extra, [ContentKind::Polls]);
+    assert_eq!((item.stats.likes, item.stats.views), (120, 5300));
+
+    let item = &feed.items[1];
+    assert!(item.media.is_empty());
+    assert_eq!(item.extra, [ContentKind::Videos]);
+}
+
+#[tokio::test]
+async fn wall_get_sends_expected_request() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/wall.get"))
+        .and(header("authorization", "Bearer token"))
+        .and(header("user-agent", USER_AGENT))
+        .and(query_param("owner_id", "-123456"))
+        .and(query_param("extended", "1"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(fixture("wall.get.json")))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/wall.get"))
+        .and(query_param("domain", "foxart"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(fixture("wall.get.json")))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let client = VKClient::new("token".to_owned()).with_base_url(server.uri());
+
+    client
+        .get_posts(SnowflakeRef::String("123456"))
+        .send()
+        .await
+        .expect("numeric string is sent as owner_id");
+    client
+        .get_posts(SnowflakeRef::String("foxart"))
+        .send()
+        .await
+        .expect("screen name is sent as domain");
+}
+
+#[tokio::test]
+async fn wall_get_reports_server_error() {
+    let (_server, client) = serve("error.json").await;
+
+    let err = client
+        .get_posts(SnowflakeRef::Number(123456))
+        .send()
+        .await
+        .err()
+        .expect("error response");
+
+    assert!(matches!(err, VKError::Server { error_code: 5, .. }));
+}
+
+#[tokio::test]
+async fn wall_get_reports_inaccessible_group() {
+    let (_server, client) = serve("access_denied.json").await;
+
+    let err = client
+        .get_posts(SnowflakeRef::Number(123456))
+        .send()
+        .await
+        .err()
+        .expect("error response");
+
+    assert!(matches!(err, VKError::Inaccessible { error_code: 15, .. }));
+}
+
+#[tokio::test]
+async fn wall_get_without_groups_is_content_error() {
+    let (_server, client) = serve("no_groups.json").await;
+
+    let err = client
+        .get_posts(SnowflakeRef::Number(123456))
+        .send()
+        .await
+        .err()
+        .expect("error response");
+
+    assert!(matches!(err, VKError::Content));
+}
+
+#[tokio::test]
+async fn resolve_screen_name_returns_group_id() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/utils.resolveScreenName"))
+        .and(query_param("screen_name", "foxart"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_string(fixture("utils.resolveScreenName.json")),
+        )
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/utils.resolveScreenName"))
+        .and(query_param("screen_name", "nobody"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_string(fixture("utils.resolveScreenName.unknown.json")),
+        )
+        .mount(&server)
+        .await;
+    let client = VKClient::new("token".to_owned()).with_base_url(server.uri());
+
+    let id = client
+        .resolve_screen_name("foxart")
+        .await
+        .expect("name resolves");
+    assert_eq!(id, 123456);
+
+    let err = client
+        .resolve_screen_name("nobody")
+        .await
+        .expect_err("unknown name");
+    assert!(matches!(err, VKError::UnknownScreenName(name) if name == "nobody"));
+}
+
+#[tokio::test]
+async fn get_by_id_finds_existing_posts() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/wall.getById"))
+        .and(query_param("posts", "-123456_4321"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(fixture("wall.getById.json")))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/wall.getById"))
+        .and(query_param("posts", "-123456_4322"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"response":[]}"#))
+        .mount(&server)
+        .await;
+    let client = VKClient::new("token".to_owned()).with_base_url(server.uri());
+
+    assert!(client
+        .post_exists(123456, 4321)
+        .await
+        .expect("post is found"));
+    assert!(!client
+        .post_exists(123456, 4322)
+        .await
+        .expect("post is gone"));
+}
+
+#[tokio::test]
+async fn wall_get_skips_malformed_items() {
+    let (_server, client) = serve("wall.get.malformed.json").await;
+
+    let feed = client
+        .get_posts(SnowflakeRef::Number(123456))
+        .send()
+        .await
+        .expect("feed parses");
+
+    let ids: Vec<_> = feed.items.iter().map(|i| i.id).collect();
+    assert_eq!(ids, [5003, 5001]);
+
+    let item = &feed.items[0];
+    assert!(matches!(
+        &item.media[..],
+        [VKMedia::Photo(photo)] if photo == "https://sun9-1.userapi.com/z.jpg"
+    ));
+    assert_eq!(item.extra, [ContentKind::Docs]);
+
+    let item = &feed.items[1];
+    assert_eq!(item.date, 0);
+    assert!(item.text.is_empty() && item.media.is_empty());
+}
+
+#[tokio::test]
+async fn wall_get_skips_unpublished_posts() {
+    let (_server, client) = serve("wall.get.postponed.json").await;
+
+    let feed = client
+        .get_posts(SnowflakeRef::Number(123456))
+        .send()
+        .await
+        .expect("feed parses");
+
+    let ids: Vec<_> = feed.items.iter().map(|i| i.id).collect();
+    assert_eq!(ids, [5007]);
+}
+
+#[tokio::test]
+async fn get_comments_resolves_authors() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/wall.getComments"))
+        .and(query_param("owner_id", "-123456"))
+        .and(query_param("post_id", "4321"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(fixture("wall.getComments.json")))
+        .mount(&server)
+        .await;
+    let client = VKClient::new("token".to_owned()).with_base_url(server.uri());
+
+    let comments = client
+        .get_comments(123456, 4321)
+        .await
+        .expect("comments parse");
+
+    let comments: Vec<_> = comments
+        .iter()
+        .map(|c| (c.author.as_str(), c.text.as_str(), c.likes))
+        .collect();
+    assert_eq!(
+        comments,
+        [("Pavel Durov", "So cute!", 2), ("Fox Art", "Thanks <3", 7)]
+    );
+}
+
+#[tokio::test]
+async fn get_subscriptions_skips_closed_groups() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/groups.get"))
+        .and(query_param("user_id", "1"))
+        .and(query_param("extended", "1"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(fixture("groups.get.json")))
+        .mount(&server)
+        .await;
+    let client = VKClient::new("token".to_owned()).with_base_url(server.uri());
+
+    let groups = client.get_subscriptions(1).await.expect("groups parse");
+
+    let groups: Vec<_> = groups
+        .iter()
+        .map(|g| (g.id, g.screen_name.as_str(), g.activity.as_str()))
+        .collect();
+    assert_eq!(groups, [(123456, "foxart", "Art"), (123459, "memefox", "")]);
+}
+
+#[tokio::test]
+async fn service_token_is_sent_as_parameter() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/wall.get"))
+        .and(query_param("access_token", "service"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(fixture("wall.get.json")))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let client = VKClient::new("service".to_owned())
+        .with_token_kind(VKTokenKind::Service)
+        .with_base_url(server.uri());
+
+    client
+        .get_posts(SnowflakeRef::Number(123456))
+        .send()
+        .await
+        .expect("service key reads walls");
+    let result = client.get_subscriptions(1).await;
+
+    assert!(matches!(result, Err(VKError::NeedsUserToken(m)) if m == "groups.get"));
+}
+
+#[tokio::test]
+async fn custom_user_agent_is_sent() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/wall.get"))
+        .and(header("user-agent", "foxbot/1.0 (+https://t.me/foxart)"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(fixture("wall.get.json")))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let client = VKClient::new("token".to_owned())
+        .with_base_url(server.uri())
+        .with_transport(Arc::new(ReqwestTransport::with_user_agent(
+            "foxbot/1.0 (+https://t.me/foxart)",
+        )));
+
+    client
+        .get_posts(SnowflakeRef::Number(123456))
+        .send()
+        .await
+        .expect("feed parses");
+}